@@ -7,7 +7,9 @@ use core::f32;
 use std::fmt::Display;
 
 use itertools::{Itertools, MinMaxResult};
-use musical_lights_core::audio::{AggregatedBinsBuilder, FftOutputs};
+use musical_lights_core::audio::{
+    novelty_sparkle, AggregatedBins, AggregatedBinsBuilder, FftOutputs, SpectralFlux,
+};
 use musical_lights_core::remap;
 
 /// TODO: i'm not sure where the code that turns this heat into a XY matrix belongs. or code for rotating the matrix by frame count
@@ -30,7 +32,12 @@ pub struct MicLoudnessPattern<
     /// TODO: i think we should change from average to RMS and see how that changes things first
     floor_db: f32,
     floor_peak_db: f32,
-    /// if the column was louder this tick than the previous tick
+    /// how much each column's loudness went up since the last tick
+    flux: SpectralFlux<X>,
+    novelty: [f32; X],
+    /// recent maximum novelty of each column. decays over time
+    novelty_peak: [f32; X],
+    /// if the column had a real transient this tick. not just any frame-to-frame wiggle
     sparkle: [bool; X],
     /// 1.0 == 100%
     sparkle_chance: f32,
@@ -54,7 +61,7 @@ where
 {
     #[deprecated(note = "i like the filter bank way more")]
     pub const fn new(
-        uninit_scale_builder: S,
+        scale_builder: S,
         floor_db: f32,
         floor_peak_db: f32,
         sparkle_chance: f32,
@@ -69,11 +76,14 @@ where
 
         Self {
             fft_out_buf: [0.0; FFT_OUTPUTS],
-            scale_builder: uninit_scale_builder,
+            scale_builder,
             scale_out_buf: [0.0; X],
             loudness: [0; X],
             floor_db,
             floor_peak_db,
+            flux: SpectralFlux::new(),
+            novelty: [0.0; X],
+            novelty_peak: [0.0; X],
             sparkle: [false; X],
             sparkle_chance,
             peak_ema_min_dbfs,
@@ -82,11 +92,6 @@ where
         }
     }
 
-    /// You must call this before using new!
-    pub fn init(&mut self, sample_rate_hz: f32) {
-        self.scale_builder.init(sample_rate_hz);
-    }
-
     /// TODO: i need to learn more about AGC because I think that's the right thing to use here
     fn update_max(&mut self, max: f32) {
         const ALPHA: f32 = 0.1;
@@ -162,8 +167,7 @@ where
         }
         // yield_now();
 
-        // TODO: how should we use an AGC?
-        // self.agc.process(dbfs);
+        // TODO: run the mic samples through `audio::Agc` before the fft instead of tracking min/max here
 
         // TODO: print the dbfs for debugging. we need some tests to make sure the input dbfs make sense. i think they are too low right now
 
@@ -183,22 +187,27 @@ where
         self.update_ema();
         // yield_now();
 
-        for ((loudness, x), sparkle) in self
+        // the bands are already in dB, so the flux doesn't need any more compression.
+        // anything below the floor isn't shown, so it can't sparkle. this also keeps -inf out of the flux
+        let floor_db = self.floor_db;
+        let bands = AggregatedBins(self.scale_out_buf.map(|x| x.max(floor_db)));
+        self.flux.bands_novelty_into(&bands, &mut self.novelty);
+
+        for ((((loudness, x), sparkle), &novelty), novelty_peak) in self
             .loudness
             .iter_mut()
             .zip(self.scale_out_buf.iter().copied())
             .zip(self.sparkle.iter_mut())
+            .zip(self.novelty.iter())
+            .zip(self.novelty_peak.iter_mut())
         {
-            // capture the previous loudness so we can compare
-            let old_loudness = *loudness;
-
             // TODO: scale this on the average instead of the floor? or maybe cut the bottom 25%? `a` and `c` definitely need thought
             *loudness = (remap(x, self.floor_db, self.peak_ema_max_dbfs, 0.0, Y as f32)
                 .clamp(0.0, Y as f32)) as u8;
 
             // TODO: only sparkle if its the top-most band overall
             // TODO: "band" or "channel"? I'm inconsistent
-            *sparkle = *loudness > old_loudness;
+            *sparkle = novelty_sparkle(novelty, novelty_peak);
         }
         // yield_now();
 
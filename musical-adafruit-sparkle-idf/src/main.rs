@@ -20,7 +20,7 @@ use esp_idf_svc::{
 };
 use esp_idf_sys::{bootloader_random_disable, bootloader_random_enable, esp_random};
 use musical_lights_core::{
-    audio::{parse_i2s_16_bit_mono_to_f32_array, BarkBank, Samples},
+    audio::{BarkBank, I2sFormat, Samples},
     compass::{Coordinate, Magnetometer},
     errors::MyError,
     fps::FpsTracker,
@@ -79,16 +79,17 @@ const FPS_TARGET: f32 = 55.5;
 /// TODO: refactored. fft isn't relevant anymore. scale this based off a desired FPS
 const I2S_SAMPLE_SIZE: usize = (I2S_SAMPLE_RATE_HZ as f32 / FPS_TARGET) as usize;
 
-/// TODO: with 24-bit audio, this should use `size_of::<i32>`
-const I2S_U8_BUFFER_SIZE: usize = I2S_SAMPLE_SIZE * size_of::<i16>();
+/// this needs to match the `DataBitWidth` in `mic_task`
+const I2S_FORMAT: I2sFormat = I2sFormat::PHILIPS_16_BIT;
+
+const I2S_U8_BUFFER_SIZE: usize = I2S_SAMPLE_SIZE * I2S_FORMAT.slot_bytes();
 
 // 24 buckets don't fit inside of 400! we collapse to 20 to fit in 400
 const AGGREGATED_OUTPUTS: usize = 20;
 
 const _SAFETY_CHECKS: () = {
-    // assert!(FFT_INPUTS % I2S_SAMPLE_SIZE == 0);
+    // NOTE: if we go back to an fft, `BufferedFFT::push_samples_and_fft` with an `Overlap` handles any I2S_SAMPLE_SIZE
     assert!(I2S_SAMPLE_SIZE > 1);
-    // assert!(I2S_SAMPLE_OVERLAP == 1 || I2S_SAMPLE_OVERLAP == 2 || I2S_SAMPLE_OVERLAP == 4)
 };
 
 const MY_BAND_MAX: u8 = 128;
@@ -553,8 +554,7 @@ fn mic_task(
     loop {
         i2s_driver.read_exact(i2s_u8_buf)?;
 
-        // TODO: compile time option to choose between 16-bit or 24-bit audio
-        parse_i2s_16_bit_mono_to_f32_array(i2s_u8_buf, &mut i2s_sample_buf.0);
+        I2S_FORMAT.parse_mono_to_f32(i2s_u8_buf, &mut i2s_sample_buf.0)?;
 
         let spectrum = filter_bank.push_samples(&i2s_sample_buf.0);
 
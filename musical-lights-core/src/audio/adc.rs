@@ -0,0 +1,159 @@
+//! Turn raw ADC counts from an analog mic into the same -1.0..=1.0 samples that the I2S mics give.
+//!
+//! Analog mic boards (MAX4466, MAX9814, etc.) output a voltage centered on a bias voltage. The ADC measures that against
+//! its reference voltage, which is never exactly what the datasheet says.
+//!
+//! ADC counts -> AdcConverter -> DcBlocker -> Agc -> ...
+use super::Samples;
+
+/// Converts ADC counts to samples. The biggest swing that the ADC can see without clipping is 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdcConverter {
+    /// the highest count. 4095 for a 12-bit ADC
+    max_count: u32,
+    /// the actual voltage at max_count
+    vref_mv: f32,
+    /// the voltage that the mic sits at when it is silent. this is usually printed on the board
+    bias_mv: f32,
+    /// multiply the counts by this...
+    gain: f32,
+    /// ...and then subtract this
+    offset: f32,
+}
+
+impl AdcConverter {
+    /// `bits` is the ADC resolution. `vref_mv` is the reference voltage. `bias_mv` is the mic's DC offset.
+    ///
+    /// If the bias isn't known, use `vref_mv / 2.0` and put a [`DcBlocker`](crate::audio::DcBlocker) after this.
+    pub const fn new(bits: u32, vref_mv: f32, bias_mv: f32) -> Self {
+        assert!(bits > 0 && bits <= 16);
+        assert!(vref_mv > 0.0);
+        assert!(bias_mv > 0.0 && bias_mv < vref_mv);
+
+        let mut x = Self {
+            max_count: (1 << bits) - 1,
+            vref_mv,
+            bias_mv,
+            gain: 0.0,
+            offset: 0.0,
+        };
+
+        x.update();
+
+        x
+    }
+
+    /// Use the chip's internal reference to find out what vref really is. This is how the STM32 does it.
+    ///
+    /// - `vrefint_cal` is the factory calibration value that was read at `cal_vref_mv` (embassy's `VREF_CALIB_MV`)
+    /// - `vrefint_reading` is what the ADC reads on the internal reference channel right now
+    ///
+    /// The bias is scaled too since the mic's bias is usually a divider off of the same supply.
+    pub const fn calibrate_vrefint(
+        mut self,
+        vrefint_cal: u16,
+        vrefint_reading: u16,
+        cal_vref_mv: f32,
+    ) -> Self {
+        assert!(vrefint_reading > 0);
+
+        let vref_mv = cal_vref_mv * vrefint_cal as f32 / vrefint_reading as f32;
+
+        self.bias_mv *= vref_mv / self.vref_mv;
+        self.vref_mv = vref_mv;
+
+        self.update();
+
+        self
+    }
+
+    /// the measured reference voltage
+    pub const fn vref_mv(&self) -> f32 {
+        self.vref_mv
+    }
+
+    pub const fn bias_mv(&self) -> f32 {
+        self.bias_mv
+    }
+
+    /// one count in millivolts
+    pub const fn mv_per_count(&self) -> f32 {
+        self.vref_mv / self.max_count as f32
+    }
+
+    /// millivolts to -1.0..=1.0. the bias is 0.0. the closer rail is ±1.0
+    const fn update(&mut self) {
+        let swing_mv = self.bias_mv.min(self.vref_mv - self.bias_mv);
+
+        self.gain = self.mv_per_count() / swing_mv;
+        self.offset = self.bias_mv / swing_mv;
+    }
+
+    #[inline]
+    pub fn convert_one(&self, count: u16) -> f32 {
+        (count as f32 * self.gain - self.offset).clamp(-1.0, 1.0)
+    }
+
+    pub fn convert_samples<const S: usize>(&self, counts: &[u16; S], output: &mut Samples<S>) {
+        self.convert(counts, &mut output.0);
+    }
+
+    /// `counts` and `output` must be the same length
+    pub fn convert(&self, counts: &[u16], output: &mut [f32]) {
+        assert_eq!(counts.len(), output.len());
+
+        for (&count, x) in counts.iter().zip(output.iter_mut()) {
+            *x = self.convert_one(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_centered_bias() {
+        let adc = AdcConverter::new(12, 3300.0, 1650.0);
+
+        assert_eq!(adc.convert_one(0), -1.0);
+        assert_eq!(adc.convert_one(4095), 1.0);
+        assert!(adc.convert_one(2048).abs() < 1e-3);
+
+        let mut output = Samples([0.0; 3]);
+        adc.convert_samples(&[1024, 2048, 3071], &mut output);
+        assert!((output.0[0] + 0.5).abs() < 1e-3, "{:?}", output.0);
+        assert!((output.0[2] - 0.5).abs() < 1e-3, "{:?}", output.0);
+    }
+
+    /// the MAX9814 sits at 1.25 V. it can only swing 1.25 V before hitting 0
+    #[test]
+    fn test_low_bias() {
+        let adc = AdcConverter::new(12, 3300.0, 1250.0);
+
+        let bias_count = (1250.0 / adc.mv_per_count()) as u16;
+        assert!(adc.convert_one(bias_count).abs() < 1e-3);
+
+        assert_eq!(adc.convert_one(0), -1.0);
+
+        // half way to 0 V
+        let x = adc.convert_one(bias_count / 2);
+        assert!((x + 0.5).abs() < 1e-3, "{x}");
+
+        // the top of the range clips
+        assert_eq!(adc.convert_one(4095), 1.0);
+    }
+
+    #[test]
+    fn test_vrefint() {
+        // the supply sagged from 3.3 V to 3.0 V. vrefint reads higher because the counts are smaller
+        let adc = AdcConverter::new(12, 3300.0, 1650.0).calibrate_vrefint(1500, 1650, 3300.0);
+
+        assert!((adc.vref_mv() - 3000.0).abs() < 1e-2, "{}", adc.vref_mv());
+        assert!((adc.bias_mv() - 1500.0).abs() < 1e-2, "{}", adc.bias_mv());
+
+        // the bias is still in the middle of the counts
+        assert!(adc.convert_one(2048).abs() < 1e-3);
+    }
+}
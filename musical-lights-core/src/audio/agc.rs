@@ -0,0 +1,344 @@
+//! Automatic gain control.
+//!
+//! The mic level changes a lot between a quiet room and standing next to the speakers. This turns the samples up or
+//! down so that everything after it (FFT, filter banks, loudness meters) sees about the same level.
+//!
+//! Put it between the [`Samples`] and any analyser:
+//!
+//! Samples -> Agc -> Buffer -> Window -> FFT -> ...
+//!
+//! - attack: how fast the gain goes down when it gets loud. keep this short so we don't clip
+//! - hold: how long to wait after being loud before turning the gain back up. this keeps the gain from pumping between beats
+//! - release: how fast the gain goes up when it gets quiet. keep this long
+//! - noise gate: below this level the gain is frozen so we don't crank the gain up on the hiss of an empty room
+use super::Samples;
+use crate::logging::trace;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// the hold timer only counts down once the input is at least this much quieter than what the gain is set for (dB)
+const HOLD_HYSTERESIS_DB: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AgcConfig {
+    /// the rms level that we want the output to be at (dBFS)
+    pub target_dbfs: f32,
+    /// never turn the input up more than this (dB)
+    pub max_gain_db: f32,
+    /// never turn the input down more than this (dB). this is negative
+    pub min_gain_db: f32,
+    /// time constant for turning the gain down. after this long, the gain has moved 63% of the way
+    pub attack_s: f32,
+    /// how long to wait after turning the gain down before turning it back up
+    pub hold_s: f32,
+    /// time constant for turning the gain up. after this long, the gain has moved 63% of the way
+    pub release_s: f32,
+    /// blocks quieter than this don't change the gain (dBFS)
+    pub gate_dbfs: f32,
+}
+
+impl Default for AgcConfig {
+    fn default() -> Self {
+        Self {
+            target_dbfs: -20.0,
+            max_gain_db: 30.0,
+            min_gain_db: -20.0,
+            attack_s: 0.01,
+            hold_s: 0.5,
+            release_s: 1.0,
+            gate_dbfs: -60.0,
+        }
+    }
+}
+
+/// Turns samples up or down so that their rms level is near [`AgcConfig::target_dbfs`].
+///
+/// The level is measured once per call to [`Agc::process`], so the time constants are only as precise as the block size.
+/// The gain ramps across each block so that changes don't click.
+///
+/// Use this instead of the old PeakScaledBuilder and DownResistanceBuilder. Those scaled the outputs after the FFT. This
+/// fixes the level before anything else sees it.
+/// TODO: peak limiter after this so that the attack can be slower
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Agc {
+    config: AgcConfig,
+    sample_rate_hz: f32,
+    /// the gate as rms amplitude instead of dBFS. this saves a log for every quiet block
+    gate_rms: f32,
+    gain_db: f32,
+    /// time left before we are allowed to release
+    hold_remaining_s: f32,
+    /// rms level of the most recent input block (dBFS)
+    input_dbfs: f32,
+}
+
+impl Agc {
+    pub fn new(config: AgcConfig, sample_rate_hz: f32) -> Self {
+        assert!(config.min_gain_db <= 0.0);
+        assert!(config.max_gain_db >= 0.0);
+        assert!(config.attack_s > 0.0);
+        assert!(config.release_s > 0.0);
+        assert!(config.hold_s >= 0.0);
+        assert!(sample_rate_hz > 0.0);
+
+        Self {
+            gate_rms: dbfs_to_amplitude(config.gate_dbfs),
+            config,
+            sample_rate_hz,
+            gain_db: 0.0,
+            hold_remaining_s: 0.0,
+            input_dbfs: f32::NEG_INFINITY,
+        }
+    }
+
+    pub fn config(&self) -> &AgcConfig {
+        &self.config
+    }
+
+    /// the gain that will be used for the next block (dB)
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    /// [`Self::gain_db`] as a multiplier for the samples
+    pub fn gain(&self) -> f32 {
+        dbfs_to_amplitude(self.gain_db)
+    }
+
+    /// rms level of the most recent block before any gain was applied (dBFS)
+    pub fn input_dbfs(&self) -> f32 {
+        self.input_dbfs
+    }
+
+    /// go back to unity gain. call this when the input source changes
+    pub fn reset(&mut self) {
+        self.gain_db = 0.0;
+        self.hold_remaining_s = 0.0;
+        self.input_dbfs = f32::NEG_INFINITY;
+    }
+
+    pub fn process_samples<const S: usize>(&mut self, samples: &mut Samples<S>) {
+        self.process(&mut samples.0);
+    }
+
+    /// measure the block, update the gain, and then apply the gain to the block in place
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if samples.is_empty() {
+            return;
+        }
+
+        let block_s = samples.len() as f32 / self.sample_rate_hz;
+
+        let rms = (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt();
+
+        let old_gain_db = self.gain_db;
+
+        if rms < self.gate_rms {
+            // too quiet to tell music from noise. keep the gain where it is
+            // TODO: slowly drift back to unity gain if it stays quiet for a long time?
+            self.input_dbfs = if rms > 0.0 {
+                amplitude_to_dbfs(rms)
+            } else {
+                f32::NEG_INFINITY
+            };
+        } else {
+            self.input_dbfs = amplitude_to_dbfs(rms);
+
+            let desired_db = (self.config.target_dbfs - self.input_dbfs)
+                .clamp(self.config.min_gain_db, self.config.max_gain_db);
+
+            if desired_db < self.gain_db {
+                // attack
+                self.gain_db +=
+                    (desired_db - self.gain_db) * smoothing(block_s, self.config.attack_s);
+
+                self.hold_remaining_s = self.config.hold_s;
+            } else if self.hold_remaining_s > 0.0 {
+                // hold
+                if desired_db - self.gain_db < HOLD_HYSTERESIS_DB {
+                    // still about as loud as when we turned it down. keep holding
+                    self.hold_remaining_s = self.config.hold_s;
+                } else {
+                    self.hold_remaining_s = (self.hold_remaining_s - block_s).max(0.0);
+                }
+            } else {
+                // release
+                self.gain_db +=
+                    (desired_db - self.gain_db) * smoothing(block_s, self.config.release_s);
+            }
+        }
+
+        trace!("agc: {} dBFS in, {} dB gain", self.input_dbfs, self.gain_db);
+
+        // ramp from the old gain to the new gain
+        let old_gain = dbfs_to_amplitude(old_gain_db);
+        let new_gain = self.gain();
+
+        let step = (new_gain - old_gain) / samples.len() as f32;
+
+        for (i, x) in samples.iter_mut().enumerate() {
+            *x *= old_gain + step * (i + 1) as f32;
+        }
+    }
+}
+
+/// how far to move towards the target during a block. this is an exponential moving average with a time constant
+#[inline]
+fn smoothing(block_s: f32, time_constant_s: f32) -> f32 {
+    1.0 - (-block_s / time_constant_s).exp()
+}
+
+#[inline]
+fn amplitude_to_dbfs(x: f32) -> f32 {
+    20.0 * x.log10()
+}
+
+#[inline]
+fn dbfs_to_amplitude(dbfs: f32) -> f32 {
+    10.0f32.powf(dbfs / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE_HZ: f32 = 48_000.0;
+
+    /// 10 ms
+    const BLOCK: usize = 480;
+
+    fn config() -> AgcConfig {
+        AgcConfig {
+            target_dbfs: -20.0,
+            max_gain_db: 30.0,
+            min_gain_db: -20.0,
+            attack_s: 0.01,
+            hold_s: 0.5,
+            release_s: 0.5,
+            gate_dbfs: -60.0,
+        }
+    }
+
+    /// push `blocks` blocks of a constant level through the agc. returns the output level of the last block (dBFS)
+    ///
+    /// a constant (DC) input keeps the rms exact so the tests don't depend on where a sine wave starts
+    fn step(agc: &mut Agc, dbfs: f32, blocks: usize) -> f32 {
+        let mut out = f32::NEG_INFINITY;
+
+        for _ in 0..blocks {
+            let mut samples = [dbfs_to_amplitude(dbfs); BLOCK];
+
+            agc.process(&mut samples);
+
+            // the last sample has the full new gain
+            out = amplitude_to_dbfs(samples[BLOCK - 1]);
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_quiet_gets_turned_up() {
+        let mut agc = Agc::new(config(), SAMPLE_RATE_HZ);
+
+        assert_eq!(agc.gain_db(), 0.0);
+
+        // 5 seconds is 10 release time constants
+        let out = step(&mut agc, -40.0, 500);
+
+        assert!((out - -20.0).abs() < 0.01, "{out}");
+        assert!((agc.gain_db() - 20.0).abs() < 0.01);
+        assert!((agc.input_dbfs() - -40.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_attack_hold_release() {
+        let mut agc = Agc::new(config(), SAMPLE_RATE_HZ);
+
+        step(&mut agc, -40.0, 500);
+
+        // a loud step. the first block gets through too loud, but the attack is fast
+        let out = step(&mut agc, -10.0, 1);
+        assert!(out > -10.0, "{out}");
+
+        // 5 attack time constants
+        let out = step(&mut agc, -10.0, 5);
+        assert!((out - -20.0).abs() < 0.2, "{out}");
+
+        let out = step(&mut agc, -10.0, 100);
+        assert!((out - -20.0).abs() < 0.01, "{out}");
+
+        // back to quiet. the gain holds for 50 blocks
+        let out = step(&mut agc, -40.0, 49);
+        assert!((out - -50.0).abs() < 0.01, "{out}");
+        assert!((agc.gain_db() - -10.0).abs() < 0.01);
+
+        // then it starts to release
+        step(&mut agc, -40.0, 3);
+        assert!(agc.gain_db() > -10.0);
+
+        // one release time constant later it has moved about 63% of the way
+        step(&mut agc, -40.0, 48);
+        let expected = -10.0 + 30.0 * (1.0 - (-1.0f32).exp());
+        assert!((agc.gain_db() - expected).abs() < 1.0, "{}", agc.gain_db());
+
+        let out = step(&mut agc, -40.0, 500);
+        assert!((out - -20.0).abs() < 0.01, "{out}");
+    }
+
+    #[test]
+    fn test_gain_limits() {
+        let mut agc = Agc::new(config(), SAMPLE_RATE_HZ);
+
+        // this needs 35 dB, but we only allow 30
+        let out = step(&mut agc, -55.0, 1000);
+        assert!((out - -25.0).abs() < 0.01, "{out}");
+        assert!((agc.gain_db() - 30.0).abs() < 0.01);
+
+        // this needs -25 dB, but we only allow -20
+        let out = step(&mut agc, 5.0, 1000);
+        assert!((out - -15.0).abs() < 0.01, "{out}");
+        assert!((agc.gain_db() - -20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_noise_gate() {
+        let mut agc = Agc::new(config(), SAMPLE_RATE_HZ);
+
+        step(&mut agc, -40.0, 500);
+
+        let gain_db = agc.gain_db();
+
+        // hiss and silence below the gate don't change anything
+        let out = step(&mut agc, -70.0, 1000);
+        assert!((out - -50.0).abs() < 0.01, "{out}");
+        assert_eq!(agc.gain_db(), gain_db);
+
+        step(&mut agc, f32::NEG_INFINITY, 100);
+        assert_eq!(agc.gain_db(), gain_db);
+        assert_eq!(agc.input_dbfs(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_ramp() {
+        let mut agc = Agc::new(config(), SAMPLE_RATE_HZ);
+
+        let mut samples = Samples([0.1; BLOCK]);
+
+        agc.process_samples(&mut samples);
+
+        // -20 dBFS in, so no change in gain and nothing to ramp
+        assert!(samples.0.iter().all(|x| (x - 0.1).abs() < 1e-6));
+
+        // a loud block ramps down smoothly from unity gain
+        let mut samples = Samples([1.0; BLOCK]);
+
+        agc.process_samples(&mut samples);
+
+        assert!((samples.0[0] - 1.0).abs() < 0.01);
+        assert!(samples.0.windows(2).all(|x| x[1] < x[0]));
+    }
+}
@@ -23,9 +23,8 @@ pub struct Amplitudes<const N: usize>(pub [f32; N]);
 #[repr(transparent)]
 pub struct WeightedAmplitudes<const N: usize>(pub [f32; N]);
 
-/// bin amounts summed in some way, probably exponentially.
+/// bin amounts combined in some way (see [`Aggregation`]), probably exponentially.
 /// TODO: rename this to SummedAmplitudes? Or do we want it to have a more generic name?
-/// TODO: some people say things should be summed, but others say to take the average. then others say to calculate the RMS. And sometimes you divide by the number of bins and other times you don't. I have no idea what i'm doing.
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(transparent)]
@@ -37,6 +36,74 @@ impl<const N: usize> Default for AggregatedBins<N> {
     }
 }
 
+/// How to combine the power of all the FFT bins that go into one output.
+///
+/// Some people say things should be summed, but others say to take the average. then others say to calculate the RMS.
+/// And sometimes you divide by the number of bins and other times you don't. So pick one.
+///
+/// The bands from a bark or exponential scale are much wider at the top, so anything except `Sum` and `Max` keeps the
+/// treble from looking louder just because it has more bins.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Aggregation {
+    /// Total power in the band. Wide bands look louder. This is what everything did before there was a choice
+    #[default]
+    Sum,
+    /// Average power per bin
+    Mean,
+    /// sqrt of the average power per bin. This is an amplitude instead of a power!
+    Rms,
+    /// The loudest bin in the band. Good for tonal music where one note is the whole band
+    Max,
+    /// Sum divided by the number of bins squared. This is the old `weighted_power` that was on the FFT outputs. Wide
+    /// bands are turned down even more than with `Mean`
+    BinCountNormalized,
+    /// Power per Hz. The same shape as `Mean`, but the units don't change with the FFT size.
+    /// `bin_hz` is the width of one bin (sample rate / FFT inputs)
+    EnergyDensity { bin_hz: f32 },
+}
+
+impl Aggregation {
+    /// `Sum` and `Max` don't care how many bins are in each band
+    pub const fn needs_bin_counts(&self) -> bool {
+        !matches!(self, Self::Sum | Self::Max)
+    }
+
+    /// `Rms` gives amplitudes. Everything else gives power
+    pub const fn is_amplitude(&self) -> bool {
+        matches!(self, Self::Rms)
+    }
+
+    /// finish one band. `x` is the sum of the power in the band (or the max for `Max`)
+    #[inline]
+    pub fn finish(&self, x: f32, bin_count: usize) -> f32 {
+        self.finish_weighted(x, bin_count as f32)
+    }
+
+    /// [`Self::finish`] for bands where bins only partly count. `n` is the sum of the bins' weights
+    #[inline]
+    pub fn finish_weighted(&self, x: f32, n: f32) -> f32 {
+        match self {
+            Self::Sum | Self::Max => x,
+            _ if n <= 0.0 => 0.0,
+            Self::Mean => x / n,
+            Self::Rms => (x / n).sqrt(),
+            Self::BinCountNormalized => x / (n * n),
+            Self::EnergyDensity { bin_hz } => x / (n * bin_hz),
+        }
+    }
+
+    /// dBFS for power or amplitude
+    #[inline]
+    pub fn to_decibels(&self, x: f32) -> f32 {
+        if self.is_amplitude() {
+            20. * x.log10()
+        } else {
+            10. * x.log10()
+        }
+    }
+}
+
 /// TODO: I kind of wnat this to be a trait, but a trait can't have const functions
 impl<const N: usize> AggregatedBins<N> {
     pub const fn new() -> Self {
@@ -54,6 +121,11 @@ pub trait AggregatedBinsBuilder<const IN: usize, const OUT: usize> {
     /// TODO: should this be a `bin` function or `bin_map`
     fn bin_map(&self) -> &[Option<usize>; IN];
 
+    /// How many bins go into each output. Override this if the builder already has them.
+    fn bin_counts(&self) -> [usize; OUT] {
+        bin_counts_from_map(self.bin_map())
+    }
+
     fn loudness(&self, spectrum: &FftOutputs<'_, IN>) -> Self::Output {
         let mut output = Self::Output::default();
 
@@ -67,14 +139,39 @@ pub trait AggregatedBinsBuilder<const IN: usize, const OUT: usize> {
     /// TODO: this feels derivable. need to practice macros
     #[inline]
     fn loudness_into(&self, spectrum: &FftOutputs<IN>, output: &mut Self::Output) {
+        self.loudness_into_with(spectrum, Aggregation::Sum, output);
+    }
+
+    fn loudness_with(
+        &self,
+        spectrum: &FftOutputs<'_, IN>,
+        aggregation: Aggregation,
+    ) -> Self::Output {
+        let mut output = Self::Output::default();
+
+        self.loudness_into_with(spectrum, aggregation, &mut output);
+
+        output
+    }
+
+    /// [`Self::loudness_into`] with a different way to combine the bins. The output is in dBFS
+    fn loudness_into_with(
+        &self,
+        spectrum: &FftOutputs<IN>,
+        aggregation: Aggregation,
+        output: &mut Self::Output,
+    ) {
         let output_inner = self.as_inner_mut(output);
 
-        self.sum_power_into(spectrum.iter_mean_square_power_density(), output_inner);
+        self.aggregate_power_into(
+            aggregation,
+            spectrum.iter_mean_square_power_density(),
+            output_inner,
+        );
 
         // // TODO: convert to dbfs here?
         for x in output_inner.iter_mut() {
-            let rms = x.sqrt();
-            *x = 20. * rms.log10();
+            *x = aggregation.to_decibels(*x);
         }
     }
 
@@ -88,8 +185,31 @@ pub trait AggregatedBinsBuilder<const IN: usize, const OUT: usize> {
         AggregatedBins::<OUT>::sum_power_into(self.bin_map(), input_power, output);
     }
 
-    /// do setup that can't be done in a const context. defaults to a noop.
-    fn init(&mut self, sample_rate_hz: f32);
+    /// combine the power of the bins with any [`Aggregation`]
+    #[inline]
+    fn aggregate_power_into<I>(
+        &self,
+        aggregation: Aggregation,
+        input_power: I,
+        output: &mut [f32; OUT],
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<f32>,
+    {
+        let bin_counts = if aggregation.needs_bin_counts() {
+            self.bin_counts()
+        } else {
+            [0; OUT]
+        };
+
+        AggregatedBins::<OUT>::aggregate_power_into(
+            self.bin_map(),
+            &bin_counts,
+            aggregation,
+            input_power,
+            output,
+        );
+    }
 }
 
 /// TODO: From trait won't work because we need some state (the precomputed equal loudness curves)
@@ -131,26 +251,62 @@ impl<const OUT: usize> AggregatedBins<OUT> {
             }
         }
     }
+
+    /// Combine groups of power with any [`Aggregation`]. `bin_counts` is only used by the modes that need it. get them
+    /// from [`bin_counts_from_map`]
+    pub fn aggregate_power_into<const IN: usize, I>(
+        map: &[Option<usize>; IN],
+        bin_counts: &[usize; OUT],
+        aggregation: Aggregation,
+        input_power: I,
+        output: &mut [f32; OUT],
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<f32>,
+    {
+        output.fill(0.0);
+
+        for (x, &i) in input_power.into_iter().zip(map.iter()) {
+            if let Some(i) = i {
+                let x = *x.borrow();
+
+                if let Aggregation::Max = aggregation {
+                    output[i] = output[i].max(x);
+                } else {
+                    output[i] += x;
+                }
+            }
+        }
+
+        for (x, &n) in output.iter_mut().zip(bin_counts.iter()) {
+            *x = aggregation.finish(*x, n);
+        }
+    }
 }
 
-/// TODO: tests on this! or just delete it? i don't think we use it anymore
-/// TODO: can this be made const?
+/// count how many bins go into each output. `counts` is added to, so start with zeros
 /// TODO: whats a better name for this? _buf? _in_place? _into?
-pub fn bin_counts_from_map_buf<const OUT: usize>(map: &[Option<usize>], counts: &mut [usize; OUT]) {
-    for i in map.iter().copied().flatten() {
-        counts[i] += 1;
+pub const fn bin_counts_from_map_buf<const OUT: usize>(
+    map: &[Option<usize>],
+    counts: &mut [usize; OUT],
+) {
+    // while-in-const is stable. for loops are not
+    let mut i = 0;
+    while i < map.len() {
+        if let Some(x) = map[i] {
+            counts[x] += 1;
+        }
+        i += 1;
     }
 }
 
-/*
-pub fn bin_counts_from_map<const OUT: usize>(map: &[Option<usize>]) -> [usize; OUT] {
+pub const fn bin_counts_from_map<const OUT: usize>(map: &[Option<usize>]) -> [usize; OUT] {
     let mut bin_counts = [0; OUT];
 
     bin_counts_from_map_buf(map, &mut bin_counts);
 
     bin_counts
 }
-*/
 
 #[cfg(test)]
 mod tests {
@@ -167,4 +323,49 @@ mod tests {
 
         assert_eq!(output, [2.0, 12.0]);
     }
+
+    #[test]
+    fn test_bin_counts() {
+        let map = [None, Some(0), Some(2), Some(2), None, Some(2)];
+
+        const COUNTS: [usize; 3] = bin_counts_from_map(&[None, Some(0), Some(2), Some(2)]);
+        assert_eq!(COUNTS, [1, 0, 2]);
+
+        assert_eq!(bin_counts_from_map::<3>(&map), [1, 0, 3]);
+    }
+
+    /// every mode on the same spectrum. band 0 is 1 bin. band 1 is 3 bins. band 2 is empty
+    #[test]
+    fn test_aggregation_modes() {
+        let map = [None, Some(0), Some(1), Some(1), Some(1)];
+        let power = [100.0, 2.0, 1.0, 4.0, 16.0];
+
+        let counts = bin_counts_from_map(&map);
+        assert_eq!(counts, [1, 3, 0]);
+
+        for (aggregation, expected) in [
+            (Aggregation::Sum, [2.0, 21.0, 0.0]),
+            (Aggregation::Mean, [2.0, 7.0, 0.0]),
+            (Aggregation::Rms, [2.0f32.sqrt(), 7.0f32.sqrt(), 0.0]),
+            (Aggregation::Max, [2.0, 16.0, 0.0]),
+            (Aggregation::BinCountNormalized, [2.0, 21.0 / 9.0, 0.0]),
+            (Aggregation::EnergyDensity { bin_hz: 10.0 }, [0.2, 0.7, 0.0]),
+        ] {
+            let mut output = [f32::NAN; 3];
+
+            AggregatedBins::aggregate_power_into(&map, &counts, aggregation, power, &mut output);
+
+            for (a, b) in output.iter().zip(expected) {
+                assert!(
+                    (a - b).abs() < 1e-6,
+                    "{aggregation:?}: {output:?} != {expected:?}"
+                );
+            }
+        }
+
+        // sum matches the old function
+        let mut summed = [0.0; 3];
+        AggregatedBins::sum_power_into(&map, power, &mut summed);
+        assert_eq!(summed, [2.0, 21.0, 0.0]);
+    }
 }
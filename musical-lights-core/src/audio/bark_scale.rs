@@ -6,7 +6,7 @@ const BARK_SCALE_OUT: usize = 24;
 
 pub struct BarkScaleBuilder<const IN: usize> {
     map: [Option<usize>; IN],
-    /// store bin counts so we can scale channels properly.
+    /// store bin counts so we can scale channels properly. See [`Aggregation`](crate::audio::Aggregation)
     /// TODO: this could probably be the f32 instead, but this is simpler for now
     bin_counts: [usize; BARK_SCALE_OUT],
 }
@@ -18,15 +18,27 @@ pub struct BarkScaleBuilder<const IN: usize> {
 pub struct BarkScaleAmplitudes(pub AggregatedBins<BARK_SCALE_OUT>);
 
 impl<const BINS: usize> BarkScaleBuilder<BINS> {
-    pub fn new(sample_rate_hz: f32) -> Self {
-        let mut x = Self::uninit();
-        x.init(sample_rate_hz);
-        x
-    }
+    /// The whole map is built here. This is const so that it can go in a `static`
+    pub const fn new(sample_rate_hz: f32) -> Self {
+        let mut map = [None; BINS];
+
+        let mut i = 0;
+        while i < BINS {
+            let f = bin_to_frequency(i, sample_rate_hz, BINS);
+
+            // bark is 1-24, but we want 0-23
+            // TODO: do we want a 25th bin thats all the highest frequencies?
+            map[i] = match bark_scale(f) {
+                Some(b) => Some(b - 1),
+                None => None,
+            };
 
-    pub const fn uninit() -> Self {
-        let map = [Some(0); BINS];
-        let bin_counts = [0; BARK_SCALE_OUT];
+            i += 1;
+        }
+
+        let mut bin_counts = [0; BARK_SCALE_OUT];
+
+        bin_counts_from_map_buf(&map, &mut bin_counts);
 
         BarkScaleBuilder { map, bin_counts }
     }
@@ -45,20 +57,9 @@ impl<const IN: usize> AggregatedBinsBuilder<IN, BARK_SCALE_OUT> for BarkScaleBui
         &mut output.0.0
     }
 
-    fn init(&mut self, sample_rate_hz: f32) {
-        for (i, x) in self.map.iter_mut().enumerate() {
-            let f = bin_to_frequency(i, sample_rate_hz, IN);
-
-            // bark is 1-24, but we want 0-23
-            // TODO: do we want a 25th bin thats all the highest frequencies?
-            let b = bark_scale(f).map(|x| x - 1);
-
-            // trace!("{} {} = {:?}", i, f, b);
-
-            *x = b;
-        }
-
-        bin_counts_from_map_buf(&self.map, &mut self.bin_counts);
+    #[inline]
+    fn bin_counts(&self) -> [usize; BARK_SCALE_OUT] {
+        self.bin_counts
     }
 }
 
@@ -105,7 +106,26 @@ pub const fn bark_scale(f: f32) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
-    use super::bark_scale;
+    use super::*;
+
+    /// the whole map is built at compile time
+    static BARK: BarkScaleBuilder<1024> = BarkScaleBuilder::new(44_100.0);
+
+    #[test]
+    fn test_static() {
+        // ~21.5 Hz per bin
+        assert_eq!(BARK.map[0], None);
+        assert_eq!(BARK.map[1], Some(0));
+        assert_eq!(BARK.map[4], Some(0));
+        assert_eq!(BARK.map[5], Some(1));
+        assert_eq!(BARK.map[1023], None);
+
+        assert_eq!(
+            BARK.bin_counts.iter().sum::<usize>(),
+            BARK.map.iter().flatten().count()
+        );
+        assert!(BARK.bin_counts.iter().all(|&x| x > 0));
+    }
 
     #[test]
     fn test_bark_scale() {
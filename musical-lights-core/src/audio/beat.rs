@@ -0,0 +1,472 @@
+//! Beat and onset detection.
+//!
+//! Feed this one frame of bands at a time (from [`BarkBank::push_samples`], an [`AggregatedBinsBuilder`], or the raw
+//! [`FftOutputs`]). It finds transients and keeps a running tempo so that patterns can pulse on a beat clock instead of
+//! on raw loudness.
+//!
+//! 1. onset strength is how much all the bands went up since the last frame ([`SpectralFlux`])
+//! 2. an onset is when that strength jumps above a moving mean + deviation threshold
+//! 3. the tempo is the strongest autocorrelation lag of the recent onset strengths
+//! 4. the beat clock free-runs at that tempo and gets nudged towards the onsets
+//!
+//! Everything lives in fixed size arrays. Nothing is allocated.
+//!
+//! [`BarkBank::push_samples`]: crate::audio::BarkBank::push_samples
+//! [`AggregatedBinsBuilder`]: crate::audio::AggregatedBinsBuilder
+//!
+//! TODO: this has only been tuned with click tracks. play some real music through it
+use circular_buffer::CircularBuffer;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use crate::audio::{AggregatedBins, FftOutputs, SpectralFlux};
+
+/// slowest tempo that we look for
+const MIN_BPM: f32 = 60.0;
+
+/// fastest tempo that we look for. anything faster is probably a hi-hat and not the beat
+const MAX_BPM: f32 = 180.0;
+
+/// when two tempos score the same (like 60 and 120 on a click track), prefer the one closest to this
+const PREFERRED_BPM: f32 = 120.0;
+
+/// how wide the tempo preference is. in octaves
+const PREFERRED_BPM_OCTAVES: f32 = 1.0;
+
+/// how long the moving mean and deviation of the onset strength remember
+const THRESHOLD_S: f32 = 1.0;
+
+/// how many deviations above the mean counts as an onset
+const THRESHOLD_DEVIATIONS: f32 = 1.5;
+
+/// tiny flux is never an onset. without this, near silence would be full of onsets
+const MIN_STRENGTH: f32 = 0.01;
+
+/// onsets closer together than this are the same onset
+const MIN_ONSET_GAP_S: f32 = 0.1;
+
+/// how quickly the bpm follows a new estimate. this is scaled by the confidence of that estimate
+const BPM_ALPHA: f32 = 0.1;
+
+/// how hard an onset pulls the beat clock towards it. 0.0 is free running. 1.0 snaps to every onset
+const PHASE_PULL: f32 = 0.2;
+
+/// the result of one frame of beat tracking
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BeatTick {
+    /// a transient was detected this frame
+    pub onset: bool,
+    /// how much the bands went up this frame
+    pub strength: f32,
+    /// the beat clock ticked this frame. until there is a tempo, this is the same as `onset`
+    pub beat: bool,
+    /// 0.0 right on a beat and counting up towards 1.0 right before the next one
+    pub phase: f32,
+    /// running tempo estimate. 0.0 until enough frames have been seen
+    pub bpm: f32,
+    /// 0.0 is no idea. 1.0 is perfectly periodic
+    pub confidence: f32,
+}
+
+/// Onset detection and tempo tracking.
+///
+/// BANDS = number of values in each frame
+/// HISTORY = number of frames of onset strength to keep for the tempo. this needs to hold at least two beats at 60 BPM. ~4 seconds works well
+pub struct BeatDetector<const BANDS: usize, const HISTORY: usize> {
+    fps: f32,
+    /// compares each frame with the one before it
+    flux: SpectralFlux<BANDS>,
+    /// onset strength of the most recent frames
+    history: CircularBuffer<HISTORY, f32>,
+    /// smoothed and zero-mean copy of `history`. this is kept here so it doesn't go on the stack
+    scratch: [f32; HISTORY],
+    /// shortest beat period (in frames) that we look for
+    min_lag: usize,
+    /// longest beat period (in frames) that we look for
+    max_lag: usize,
+    min_onset_gap: u32,
+    frames_since_onset: u32,
+    threshold_alpha: f32,
+    /// moving mean of the onset strength
+    mean: f32,
+    /// moving mean of the onset strength squared. used with `mean` for the deviation
+    mean_square: f32,
+    bpm: f32,
+    confidence: f32,
+    phase: f32,
+}
+
+impl<const BANDS: usize, const HISTORY: usize> BeatDetector<BANDS, HISTORY> {
+    /// `fps_target` is how often a frame will be pushed. This is probably the same number given to [`BarkBank::new`].
+    ///
+    /// [`BarkBank::new`]: crate::audio::BarkBank::new
+    pub fn new(fps_target: f32) -> Self {
+        assert!(fps_target > 0.0);
+
+        let min_lag = (fps_target * 60.0 / MAX_BPM).floor() as usize;
+        let max_lag = (fps_target * 60.0 / MIN_BPM).ceil() as usize;
+
+        // we look one lag to either side of the best one
+        assert!(min_lag >= 2, "fps_target is too low to track beats");
+        assert!(
+            HISTORY > 2 * max_lag,
+            "HISTORY is too short to hold two beats at the slowest tempo"
+        );
+
+        let min_onset_gap = (fps_target * MIN_ONSET_GAP_S).ceil() as u32;
+
+        let threshold_alpha = (-1.0 / (fps_target * THRESHOLD_S)).exp();
+
+        Self {
+            fps: fps_target,
+            flux: SpectralFlux::new(),
+            history: CircularBuffer::new(),
+            scratch: [0.0; HISTORY],
+            min_lag,
+            max_lag,
+            min_onset_gap,
+            frames_since_onset: min_onset_gap,
+            threshold_alpha,
+            mean: 0.0,
+            mean_square: 0.0,
+            bpm: 0.0,
+            confidence: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    /// Track one frame of bands. The bands should be on a perceptual scale (normalized like [`BarkBank`] or decibels).
+    ///
+    /// [`BarkBank`]: crate::audio::BarkBank
+    pub fn push_bands(&mut self, bands: &AggregatedBins<BANDS>) -> BeatTick {
+        let strength = self.flux.bands_novelty(bands);
+
+        self.push_onset_strength(strength)
+    }
+
+    /// Track one frame straight from the FFT. The amplitudes are log compressed so that loud bins don't drown out everything else.
+    pub fn push_spectrum(&mut self, spectrum: &FftOutputs<'_, BANDS>) -> BeatTick {
+        let strength = self.flux.novelty(spectrum);
+
+        self.push_onset_strength(strength)
+    }
+
+    /// Track one frame of an onset strength that was calculated somewhere else.
+    pub fn push_onset_strength(&mut self, strength: f32) -> BeatTick {
+        // a bad frame from the mic shouldn't poison the moving averages
+        let strength = if strength.is_finite() {
+            strength.max(0.0)
+        } else {
+            0.0
+        };
+
+        // compare with the threshold before this frame is part of it
+        let deviation = (self.mean_square - self.mean * self.mean).max(0.0).sqrt();
+        let threshold = (self.mean + THRESHOLD_DEVIATIONS * deviation).max(MIN_STRENGTH);
+
+        self.frames_since_onset = self.frames_since_onset.saturating_add(1);
+
+        let onset = strength > threshold && self.frames_since_onset >= self.min_onset_gap;
+
+        if onset {
+            self.frames_since_onset = 0;
+        }
+
+        let a = self.threshold_alpha;
+        self.mean = a * self.mean + (1.0 - a) * strength;
+        self.mean_square = a * self.mean_square + (1.0 - a) * strength * strength;
+
+        self.history.push_back(strength);
+
+        // TODO: this doesn't need to run every frame. every few frames would save a lot of cycles on the small chips
+        self.update_tempo();
+
+        let beat = self.update_phase(onset);
+
+        BeatTick {
+            onset,
+            strength,
+            beat,
+            phase: self.phase,
+            bpm: self.bpm,
+            confidence: self.confidence,
+        }
+    }
+
+    /// running tempo estimate in beats per minute. roughly 60 to 180. 0.0 until the onset history has filled up once
+    #[inline]
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    /// how periodic the recent onsets are. 0.0 (no idea, or silence) to 1.0 (perfectly periodic)
+    #[inline]
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// pick the most periodic lag out of the onset strength history
+    fn update_tempo(&mut self) {
+        if !self.history.is_full() {
+            return;
+        }
+
+        // smooth the history a little. click tracks that don't line up perfectly with the frame rate jitter by a frame
+        // and the jitter makes the lag of two beats look better than the lag of one
+        let mut previous = self.history[0];
+        for (i, x) in self.scratch.iter_mut().enumerate() {
+            let current = self.history[i];
+            let next = self.history.get(i + 1).copied().unwrap_or(current);
+
+            *x = 0.25 * previous + 0.5 * current + 0.25 * next;
+
+            previous = current;
+        }
+
+        let mean = self.scratch.iter().sum::<f32>() / HISTORY as f32;
+        for x in self.scratch.iter_mut() {
+            *x -= mean;
+        }
+
+        let energy = autocorrelation(&self.scratch, 0);
+
+        if energy <= f32::EPSILON {
+            // silence. keep the old tempo but stop trusting it
+            self.confidence = 0.0;
+            return;
+        }
+
+        let mut best_lag = self.min_lag;
+        let mut best_score = f32::MIN;
+        let mut best_correlation = 0.0;
+
+        for lag in self.min_lag..=self.max_lag {
+            let correlation = autocorrelation(&self.scratch, lag);
+
+            let score = correlation * tempo_preference(60.0 * self.fps / lag as f32);
+
+            if score > best_score {
+                best_lag = lag;
+                best_score = score;
+                best_correlation = correlation;
+            }
+        }
+
+        // fit a parabola through the neighbors to get a fraction of a frame more resolution
+        let before = autocorrelation(&self.scratch, best_lag - 1);
+        let after = autocorrelation(&self.scratch, best_lag + 1);
+        let curvature = before - 2.0 * best_correlation + after;
+
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        let bpm = 60.0 * self.fps / (best_lag as f32 + offset);
+
+        self.confidence = (best_correlation / energy).clamp(0.0, 1.0);
+
+        if self.bpm == 0.0 {
+            self.bpm = bpm;
+        } else {
+            let alpha = BPM_ALPHA * self.confidence;
+            self.bpm = (1.0 - alpha) * self.bpm + alpha * bpm;
+        }
+    }
+
+    /// advance the beat clock. returns true if it ticked
+    fn update_phase(&mut self, onset: bool) -> bool {
+        if self.bpm == 0.0 {
+            // no tempo yet. every onset is a beat
+            return onset;
+        }
+
+        self.phase += self.bpm / (60.0 * self.fps);
+
+        let beat = self.phase >= 1.0;
+
+        if beat {
+            self.phase -= 1.0;
+        }
+
+        if onset {
+            // an onset should land right on the beat (phase 0.0). early onsets pull the clock forward. late ones hold it back
+            let error = if self.phase > 0.5 {
+                self.phase - 1.0
+            } else {
+                self.phase
+            };
+
+            self.phase -= error * PHASE_PULL;
+        }
+
+        beat
+    }
+}
+
+/// biased autocorrelation. longer lags have fewer overlapping frames so they score a little lower
+#[inline]
+fn autocorrelation(x: &[f32], lag: usize) -> f32 {
+    x.iter().zip(x[lag..].iter()).map(|(a, b)| a * b).sum()
+}
+
+/// log-gaussian weight that is 1.0 at [`PREFERRED_BPM`]
+#[inline]
+fn tempo_preference(bpm: f32) -> f32 {
+    let octaves = (bpm / PREFERRED_BPM).log2() / PREFERRED_BPM_OCTAVES;
+
+    (-0.5 * octaves * octaves).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::BarkBank;
+
+    const FPS: f32 = 55.5;
+
+    const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+    /// small and deterministic noise so the tests don't need a rng crate
+    struct XorShift(u32);
+
+    impl XorShift {
+        fn next_f32(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+
+            self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+        }
+    }
+
+    /// bands that jump to 1.0 on every click and then decay. returns all the ticks and how many clicks there were
+    fn band_click_track(bpm: f32, seconds: f32) -> ([BeatTick; 2048], usize) {
+        let mut detector = BeatDetector::<20, 256>::new(FPS);
+        let mut noise = XorShift(0x1234_5678);
+
+        let frames = (seconds * FPS) as usize;
+        let frames_per_click = 60.0 * FPS / bpm;
+
+        let mut ticks = [BeatTick::default(); 2048];
+        let mut bands = AggregatedBins::<20>::new();
+        let mut clicks = 0;
+        let mut next_click = 0.0;
+
+        for tick in ticks.iter_mut().take(frames) {
+            let click = next_click < 1.0;
+
+            if click {
+                clicks += 1;
+                next_click += frames_per_click;
+            }
+            next_click -= 1.0;
+
+            for x in bands.0.iter_mut() {
+                *x = if click { 1.0 } else { *x * 0.7 };
+                *x = (*x + noise.next_f32() * 0.02).clamp(0.0, 1.0);
+            }
+
+            *tick = detector.push_bands(&bands);
+        }
+
+        (ticks, clicks)
+    }
+
+    #[test]
+    fn test_click_track_120() {
+        let seconds = 20.0;
+        let (ticks, clicks) = band_click_track(120.0, seconds);
+        let ticks = &ticks[..(seconds * FPS) as usize];
+
+        let onsets = ticks.iter().filter(|x| x.onset).count();
+        assert_eq!(onsets, clicks);
+
+        let last = ticks.last().unwrap();
+        assert!((last.bpm - 120.0).abs() < 2.0, "bpm: {}", last.bpm);
+        assert!(last.confidence > 0.5, "confidence: {}", last.confidence);
+
+        // once locked, the beat clock should tick once per click
+        let last_10_s = &ticks[ticks.len() - (10.0 * FPS) as usize..];
+        let beats = last_10_s.iter().filter(|x| x.beat).count();
+        assert!((19..=21).contains(&beats), "beats: {beats}");
+
+        // and the clock should tick at (or within a frame of) the onsets
+        for (i, tick) in last_10_s.iter().enumerate().skip(1) {
+            if tick.onset {
+                assert!(
+                    tick.beat
+                        || last_10_s[i - 1].beat
+                        || last_10_s.get(i + 1).is_some_and(|x| x.beat),
+                    "onset at {i} is not near a beat"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_click_track_90() {
+        let seconds = 20.0;
+        let (ticks, clicks) = band_click_track(90.0, seconds);
+        let ticks = &ticks[..(seconds * FPS) as usize];
+
+        let onsets = ticks.iter().filter(|x| x.onset).count();
+        assert_eq!(onsets, clicks);
+
+        let last = ticks.last().unwrap();
+        assert!((last.bpm - 90.0).abs() < 2.0, "bpm: {}", last.bpm);
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut detector = BeatDetector::<20, 256>::new(FPS);
+
+        let bands = AggregatedBins::<20>::new();
+
+        for _ in 0..1000 {
+            let tick = detector.push_bands(&bands);
+
+            assert!(!tick.onset);
+            assert!(!tick.beat);
+            assert_eq!(tick.bpm, 0.0);
+        }
+    }
+
+    /// clicks of noise through the filter bank. this is close to what the firmware does
+    #[test]
+    fn test_bark_bank_click_track() {
+        const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE_HZ / FPS) as usize;
+
+        let mut bank = BarkBank::new(FPS, SAMPLE_RATE_HZ);
+        let mut detector = BeatDetector::<20, 256>::new(FPS);
+        let mut noise = XorShift(0x8765_4321);
+
+        let bpm = 128.0;
+        let samples_per_click = (60.0 * SAMPLE_RATE_HZ / bpm) as usize;
+        let click_samples = (0.02 * SAMPLE_RATE_HZ) as usize;
+
+        let mut samples = [0.0; SAMPLES_PER_FRAME];
+        let mut n = 0;
+        let mut last = BeatTick::default();
+
+        for _ in 0..(20.0 * FPS) as usize {
+            for x in samples.iter_mut() {
+                let loud = n % samples_per_click < click_samples;
+
+                *x = noise.next_f32() * if loud { 0.5 } else { 0.001 };
+
+                n += 1;
+            }
+
+            let bands = bank.push_samples(&samples);
+
+            last = detector.push_bands(&bands);
+        }
+
+        assert!((last.bpm - bpm).abs() < 2.0, "bpm: {}", last.bpm);
+        assert!(last.confidence > 0.3, "confidence: {}", last.confidence);
+    }
+}
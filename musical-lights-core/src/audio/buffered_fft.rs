@@ -17,7 +17,37 @@ use crate::{
 use circular_buffer::CircularBuffer;
 use num::Complex;
 
+/// How much one FFT overlaps with the next one.
+///
+/// More overlap means more FFTs (and more CPU), but fast transients don't fall into the quiet edges of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Overlap {
+    /// every sample is used in exactly one FFT
+    None,
+    /// 50%. the usual choice for a Hann window
+    Half,
+    /// 75%
+    ThreeQuarters,
+    /// move the window this many samples between FFTs
+    Hop(usize),
+}
+
+impl Overlap {
+    /// how many new samples there are between FFTs
+    pub const fn hop(self, fft_in: usize) -> usize {
+        match self {
+            Self::None => fft_in,
+            Self::Half => fft_in / 2,
+            Self::ThreeQuarters => fft_in / 4,
+            Self::Hop(x) => x,
+        }
+    }
+}
+
 /// put a circular buffer in front of an FFT. Use windowing to make the middle of the middle window more important.
+///
+/// Everything is calculated in [`Self::new`], so this can go in a `static`.
 pub struct BufferedFFT<
     const SAMPLE_IN: usize,
     const FFT_IN: usize,
@@ -29,14 +59,18 @@ pub struct BufferedFFT<
     sample_buf: CircularBuffer<FFT_IN, f32>,
     /// TODO: this should be the existing FFT object so we can reuse that code. it wasn't built with buffers in mind most of the time though. not a terrible refactor
     fft_in_buf: [f32; FFT_IN],
+    /// the inputs are multiplied by `WI::WINDOW`
     input_window: PhantomData<WI>,
-    scale_inputs: [f32; FFT_IN],
-    /// scaling to correct for the windowing function and equal loudness countours.
+    /// this undoes the reduction from the window. 1 / coherent gain
+    window_scaling: f32,
+    /// scaling to correct for equal loudness countours.
     /// Collapses to a single-sided spectrum (bins 1…N/2-1) by doubling power there; leave DC (k = 0) and Nyquist (k = N/2) unchanged.
     /// TODO: double check this. too much cargo culting
-    /// TODO: WE should just own weights itself
     weighting: WE,
-    scale_outputs: [f32; FFT_OUT],
+    /// new samples between FFTs when using [`Self::push_samples_and_fft`]
+    hop: usize,
+    /// samples pushed since the last FFT
+    pending: usize,
 }
 
 impl<
@@ -47,67 +81,63 @@ impl<
     WE: Weighting<FFT_OUT>,
 > BufferedFFT<SAMPLE_IN, FFT_IN, FFT_OUT, WI, WE>
 {
-    /// Use this when you want the buffered fft to be statically allocated.
-    /// You MUST call `init` on this before using it!
-    /// TODO: figure out how to call init on this from const. or at least how to make sure init gets called
-    pub const fn uninit(weighting: WE) -> Self {
+    /// The window is a const table and the weighting builds its table when it is created, so there is nothing left to
+    /// initialize later. Use this in a `static` or on the stack.
+    pub const fn new(weighting: WE) -> Self {
         assert!(SAMPLE_IN > 0);
+        assert!(SAMPLE_IN <= FFT_IN);
         assert!(FFT_IN / 2 == FFT_OUT);
-        assert!(FFT_IN.is_multiple_of(SAMPLE_IN));
 
         Self {
             sample_buf: CircularBuffer::new(),
             fft_in_buf: [0.0; FFT_IN],
             input_window: PhantomData::<WI>,
-            scale_inputs: [1.0; FFT_IN],
+            window_scaling: WI::OUTPUT_SCALING,
             weighting,
-            scale_outputs: [1.0; FFT_OUT],
+            hop: SAMPLE_IN,
+            pending: 0,
         }
     }
 
-    /// create an initialized buffered fft.
-    /// useful if you don't need this statically allocated.
-    /// `init` is called for you.
-    /// TODO: refactor the window to be const and then we can make this const
-    pub fn new(weighting: WE) -> Self {
-        let mut x = Self::uninit(weighting);
-
-        x.init();
-
-        x
+    /// Change how far the window moves between FFTs. The default is one FFT every `SAMPLE_IN` samples.
+    ///
+    /// This only matters for [`Self::push_samples_and_fft`].
+    pub const fn with_overlap(mut self, overlap: Overlap) -> Self {
+        self.set_overlap(overlap);
+        self
     }
 
-    /// we will crash if the fft is called before the sample buffer is full! Call init to fill the buffer and do other necessary setup.
-    /// TODO: use types to make sure this function is called after uninit!
-    /// TODO: need some tests on these outputs
-    pub fn init(&mut self) {
-        self.sample_buf.fill(0.0);
+    /// [`Self::with_overlap`] while running. The hop becomes `overlap.hop(FFT_IN)` samples.
+    ///
+    /// Samples that are already buffered stay in the window, but the count towards the next FFT starts over. The next
+    /// FFT from [`Self::push_samples_and_fft`] is a whole new hop away.
+    pub const fn set_overlap(&mut self, overlap: Overlap) {
+        let hop = overlap.hop(FFT_IN);
 
-        // the inputs are reduced by the windowing function
-        WI::apply_windows(&mut self.scale_inputs);
+        assert!(hop > 0);
+        assert!(hop <= FFT_IN);
 
-        // this undoes the reduction from the window scaling
-        let window_output_scaling = WI::output_scaling();
-
-        self.weighting.curve_buf(&mut self.scale_outputs);
-
-        // TODO: i think we should skip the last bin, but that's not looking right
-        for we in self.scale_outputs.iter_mut() {
-            *we *= window_output_scaling;
-        }
+        self.hop = hop;
+        self.pending = 0;
+    }
 
-        self.scale_outputs[0] = 1.0;
+    /// new samples between FFTs
+    pub const fn hop(&self) -> usize {
+        self.hop
     }
 
     /// fill the fft buffer with the latest samples and then apply the windowing function
     /// remember to apply window scaling later!
     fn fill_fft_in_buf(&mut self) {
         // first, we load buffer up with the samples (TODO: move this to a helper function?)
+        // until the buffer has filled up once, the oldest samples are silence
         let (a, b) = self.sample_buf.as_slices();
-        self.fft_in_buf[..a.len()].copy_from_slice(a);
-        self.fft_in_buf[a.len()..].copy_from_slice(b);
+        let silence = FFT_IN - a.len() - b.len();
+        self.fft_in_buf[..silence].fill(0.0);
+        self.fft_in_buf[silence..silence + a.len()].copy_from_slice(a);
+        self.fft_in_buf[silence + a.len()..].copy_from_slice(b);
 
-        for (x, wi) in self.fft_in_buf.iter_mut().zip(self.scale_inputs.iter()) {
+        for (x, wi) in self.fft_in_buf.iter_mut().zip(WI::WINDOW.iter()) {
             *x *= wi;
         }
     }
@@ -115,59 +145,118 @@ impl<
     pub fn push_samples(&mut self, samples: &Samples<SAMPLE_IN>) {
         self.sample_buf.extend_from_slice(&samples.0)
     }
-}
 
-/// TODO: replace this with some macros
-const HARD_CODED_FFT_INPUTS: usize = 4096;
-const HARD_CODED_FFT_OUTPUTS: usize = HARD_CODED_FFT_INPUTS / 2;
+    /// push samples until the next hop. returns true if it is time for an FFT. `samples` is advanced past what was used
+    fn push_until_hop(&mut self, samples: &mut &[f32]) -> bool {
+        let n = (self.hop - self.pending).min(samples.len());
 
-/// TODO: macro for this so we can support multiple rfft sizes. or maybe a different library that does more at runtime?
-impl<
-    const SAMPLE_IN: usize,
-    WI: Window<HARD_CODED_FFT_INPUTS>,
-    WE: Weighting<HARD_CODED_FFT_OUTPUTS>,
-> BufferedFFT<SAMPLE_IN, HARD_CODED_FFT_INPUTS, HARD_CODED_FFT_OUTPUTS, WI, WE>
-{
-    /// TODO: not sure what type to put here for the output? WeightedOutputs?
-    /// TODO: what should this function be called?
-    pub fn fft(&mut self) -> FftOutputs<'_, HARD_CODED_FFT_OUTPUTS> {
-        self.fill_fft_in_buf();
-
-        // TODO: yield here with a specific compile time feature
-        // TODO: test if we need this and where
-        #[cfg(feature = "std")]
-        yield_now();
-
-        let spectrum = microfft::real::rfft_4096(&mut self.fft_in_buf);
-
-        // TODO: yield here with a specific compile time feature
-        #[cfg(feature = "std")]
-        yield_now();
-
-        // from the README of microfft:
-        // > since the real-valued coefficient at the Nyquist frequency is packed into the
-        //>  imaginary part of the DC bin, it must be cleared before computing the amplitudes
-        // TODO: what does this even mean?
-        // TODO: print this once per second. need an every_n_milliseconds macro like fastled has
-        trace!(
-            "real-valued coefficient at nyquist frequency: {}",
-            spectrum[0].im
-        );
-        spectrum[0].im = 0.0;
-
-        trace!("dc bin: {}", spectrum[0].re);
-
-        // TODO: this is causing a stack overflow. can't we just give more task size?
-        // correct for the windowing function
-        // TODO: is there a simd or something for this?
-        // TODO: doing this here uses a bunch of staack space. maybe better to do after we make the conversion to magnitude
-        for (s, we) in spectrum.iter_mut().zip(self.scale_outputs) {
-            *s *= we;
+        let (now, later) = samples.split_at(n);
+
+        self.sample_buf.extend_from_slice(now);
+        self.pending += n;
+
+        *samples = later;
+
+        if self.pending == self.hop {
+            self.pending = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Implement `fft` for one of the real FFT sizes in microfft.
+///
+/// microfft only has a function per size (no runtime sizes), so we need one impl per size too.
+macro_rules! impl_fft {
+    ($fft_in:literal, $rfft:path) => {
+        impl<const SAMPLE_IN: usize, WI: Window<$fft_in>, WE: Weighting<{ $fft_in / 2 }>>
+            BufferedFFT<SAMPLE_IN, $fft_in, { $fft_in / 2 }, WI, WE>
+        {
+            /// TODO: not sure what type to put here for the output? WeightedOutputs?
+            /// TODO: what should this function be called?
+            pub fn fft(&mut self) -> FftOutputs<'_, { $fft_in / 2 }> {
+                self.fill_fft_in_buf();
+
+                // TODO: yield here with a specific compile time feature
+                // TODO: test if we need this and where
+                #[cfg(feature = "std")]
+                yield_now();
+
+                let spectrum = $rfft(&mut self.fft_in_buf);
+
+                // TODO: yield here with a specific compile time feature
+                #[cfg(feature = "std")]
+                yield_now();
+
+                scale_spectrum(spectrum, self.weighting.weights(), self.window_scaling);
+
+                FftOutputs { spectrum }
+            }
+
+            /// Push any number of samples. `f` gets the spectrum every time another hop of samples has arrived.
+            ///
+            /// The I2S DMA gives us however many samples it has, so leftovers are kept until the next call.
+            /// Returns how many FFTs were run.
+            pub fn push_samples_and_fft(
+                &mut self,
+                mut samples: &[f32],
+                mut f: impl FnMut(FftOutputs<'_, { $fft_in / 2 }>),
+            ) -> usize {
+                let mut ffts = 0;
+
+                while !samples.is_empty() {
+                    if self.push_until_hop(&mut samples) {
+                        f(self.fft());
+                        ffts += 1;
+                    }
+                }
+
+                ffts
+            }
         }
+    };
+}
 
-        // correct for the weighting function
-        // TODO: i'm really unsure if we should be doing this now or later. i think a-weighting is actually the wrong thing to use since we aren't measuring in SPL
-        FftOutputs { spectrum }
+impl_fft!(64, microfft::real::rfft_64);
+impl_fft!(128, microfft::real::rfft_128);
+impl_fft!(256, microfft::real::rfft_256);
+impl_fft!(512, microfft::real::rfft_512);
+impl_fft!(1024, microfft::real::rfft_1024);
+impl_fft!(2048, microfft::real::rfft_2048);
+impl_fft!(4096, microfft::real::rfft_4096);
+
+/// clean up the raw output of microfft and correct it for the windowing and weighting functions
+#[inline]
+fn scale_spectrum<const FFT_OUT: usize>(
+    spectrum: &mut [Complex<f32>; FFT_OUT],
+    weights: &[f32; FFT_OUT],
+    window_scaling: f32,
+) {
+    // from the README of microfft:
+    // > since the real-valued coefficient at the Nyquist frequency is packed into the
+    //>  imaginary part of the DC bin, it must be cleared before computing the amplitudes
+    // TODO: what does this even mean?
+    // TODO: print this once per second. need an every_n_milliseconds macro like fastled has
+    trace!(
+        "real-valued coefficient at nyquist frequency: {}",
+        spectrum[0].im
+    );
+    spectrum[0].im = 0.0;
+
+    trace!("dc bin: {}", spectrum[0].re);
+
+    // TODO: this is causing a stack overflow. can't we just give more task size?
+    // correct for the windowing function
+    // TODO: is there a simd or something for this?
+    // TODO: doing this here uses a bunch of staack space. maybe better to do after we make the conversion to magnitude
+    // correct for the weighting function
+    // TODO: i'm really unsure if we should be doing this now or later. i think a-weighting is actually the wrong thing to use since we aren't measuring in SPL
+    // TODO: i think we should skip the last bin, but that's not looking right
+    // the dc bin is left alone
+    for (s, we) in spectrum.iter_mut().zip(weights).skip(1) {
+        *s *= we * window_scaling;
     }
 }
 
@@ -180,6 +269,12 @@ pub struct FftOutputs<'fft, const FFT_OUTPUT: usize> {
 }
 
 impl<'a, const FFT_OUTPUT: usize> FftOutputs<'a, FFT_OUTPUT> {
+    /// wrap a spectrum that has already been scaled. [`BufferedFFT::fft`] is usually what makes these
+    #[inline]
+    pub const fn new(spectrum: &'a [Complex<f32>; FFT_OUTPUT]) -> Self {
+        Self { spectrum }
+    }
+
     /// TODO: the weights aren't included! is that okay?
     #[inline]
     pub fn spectrum(&self) -> &[Complex<f32>; FFT_OUTPUT] {
@@ -258,6 +353,10 @@ pub const fn frequency_to_bin(frequency: f32, sample_rate_hz: f32, num_bins: usi
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        audio::{AWeighting, FlatWeighting},
+        windows::HanningWindow,
+    };
 
     #[test]
     fn test_extend_from_slice() {
@@ -266,11 +365,209 @@ mod tests {
         assert_eq!(buf, [3, 4, 5, 6, 7]);
     }
 
+    /// a sine that is centered on a bin should only show up in that bin and the two next to it
     #[test]
     fn test_sin_waves() {
-        todo!(
-            "set up a small buffered fft with a known frequency and then make sure we get expected values out of it"
-        );
+        const FFT_IN: usize = 1024;
+        const FFT_OUT: usize = FFT_IN / 2;
+        const SAMPLES: usize = 256;
+
+        let sample_rate_hz = 44_100.0;
+        let bin = 64;
+        let frequency = bin_to_frequency(bin, sample_rate_hz, FFT_OUT);
+
+        let spectrum = |amplitude: f32| -> [f32; FFT_OUT] {
+            let mut fft = BufferedFFT::<
+                SAMPLES,
+                FFT_IN,
+                FFT_OUT,
+                HanningWindow<FFT_IN>,
+                FlatWeighting<FFT_OUT>,
+            >::new(FlatWeighting);
+
+            // fill the whole buffer
+            for chunk in 0..FFT_IN / SAMPLES {
+                let samples = Samples(core::array::from_fn(|i| {
+                    let t = (chunk * SAMPLES + i) as f32 / sample_rate_hz;
+
+                    amplitude * (2.0 * core::f32::consts::PI * frequency * t).sin()
+                }));
+
+                fft.push_samples(&samples);
+            }
+
+            let mut out = [0.0; FFT_OUT];
+            for (o, x) in out.iter_mut().zip(fft.fft().iter_amplitude()) {
+                *o = x;
+            }
+            out
+        };
+
+        let loud = spectrum(1.0);
+        let quiet = spectrum(0.5);
+
+        // TODO: should a full scale sine be 1.0 here? the scaling constants keep changing, so this at least notices
+        assert!((loud[bin] - FFT_IN as f32).abs() < 0.1, "{}", loud[bin]);
+
+        // the hanning window spreads a centered sine into exactly 3 bins. the neighbors are half as tall
+        assert!((loud[bin - 1] / loud[bin] - 0.5).abs() < 0.01);
+        assert!((loud[bin + 1] / loud[bin] - 0.5).abs() < 0.01);
+
+        for (i, &x) in loud.iter().enumerate() {
+            if i.abs_diff(bin) > 1 {
+                assert!(x < loud[bin] * 1e-3, "{i}: {x}");
+            }
+        }
+
+        // half the amplitude in is half the amplitude out
+        for (l, q) in loud.iter().zip(quiet.iter()) {
+            assert!((q * 2.0 - l).abs() <= loud[bin] * 1e-4);
+        }
+    }
+
+    /// every size should put a sine wave that is centered on a bin into that bin
+    #[test]
+    fn test_fft_sizes() {
+        fn peak_bin<const FFT_OUT: usize>(fft_outputs: FftOutputs<'_, FFT_OUT>) -> usize {
+            fft_outputs
+                .iter_amplitude()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+                .0
+        }
+
+        macro_rules! check_size {
+            ($fft_in:literal) => {{
+                const FFT_OUT: usize = $fft_in / 2;
+
+                let sample_rate_hz = 44_100.0;
+                let bin = FFT_OUT / 4;
+                let frequency = bin_to_frequency(bin, sample_rate_hz, FFT_OUT);
+
+                let mut fft = BufferedFFT::<
+                    $fft_in,
+                    $fft_in,
+                    FFT_OUT,
+                    HanningWindow<$fft_in>,
+                    FlatWeighting<FFT_OUT>,
+                >::new(FlatWeighting);
+
+                let samples = Samples(core::array::from_fn(|i| {
+                    (2.0 * core::f32::consts::PI * frequency * i as f32 / sample_rate_hz).sin()
+                }));
+
+                fft.push_samples(&samples);
+
+                assert_eq!(peak_bin(fft.fft()), bin, "size {}", $fft_in);
+            }};
+        }
+
+        check_size!(64);
+        check_size!(128);
+        check_size!(256);
+        check_size!(512);
+        check_size!(1024);
+        check_size!(2048);
+        check_size!(4096);
+    }
+
+    #[test]
+    fn test_overlap() {
+        assert_eq!(Overlap::None.hop(1024), 1024);
+        assert_eq!(Overlap::Half.hop(1024), 512);
+        assert_eq!(Overlap::ThreeQuarters.hop(1024), 256);
+        assert_eq!(Overlap::Hop(100).hop(1024), 100);
+
+        let fft =
+            BufferedFFT::<64, 256, 128, HanningWindow<256>, FlatWeighting<128>>::new(FlatWeighting);
+
+        // one fft per push unless told otherwise
+        assert_eq!(fft.hop(), 64);
+        assert_eq!(fft.with_overlap(Overlap::Half).hop(), 128);
+    }
+
+    /// odd sized reads still give an fft every hop, and every fft sees the most recent FFT_IN samples
+    #[test]
+    fn test_push_samples_and_fft() {
+        const FFT_IN: usize = 256;
+        const FFT_OUT: usize = FFT_IN / 2;
+
+        type TestFFT =
+            BufferedFFT<FFT_IN, FFT_IN, FFT_OUT, HanningWindow<FFT_IN>, FlatWeighting<FFT_OUT>>;
+
+        let signal: Vec<f32> = (0..1000)
+            .map(|i| (i as f32 * i as f32 * 0.001).sin())
+            .collect();
+
+        for overlap in [
+            Overlap::None,
+            Overlap::Half,
+            Overlap::ThreeQuarters,
+            Overlap::Hop(100),
+        ] {
+            let hop = overlap.hop(FFT_IN);
+
+            let mut fft = TestFFT::new(FlatWeighting).with_overlap(overlap);
+
+            let mut spectra = vec![];
+            let mut ffts = 0;
+
+            // a read size that doesn't line up with anything
+            for chunk in signal.chunks(37) {
+                ffts += fft.push_samples_and_fft(chunk, |x| {
+                    spectra.push(x.iter_amplitude().collect::<Vec<_>>())
+                });
+            }
+
+            assert_eq!(ffts, signal.len() / hop, "{overlap:?}");
+            assert_eq!(spectra.len(), ffts);
+
+            for (i, spectrum) in spectra.iter().enumerate() {
+                let end = (i + 1) * hop;
+
+                // the same window, all in one push. zeros before the signal started
+                let window = Samples(core::array::from_fn(|j| {
+                    (end + j).checked_sub(FFT_IN).map_or(0.0, |k| signal[k])
+                }));
+
+                let mut expected = TestFFT::new(FlatWeighting);
+                expected.push_samples(&window);
+
+                for (a, e) in spectrum.iter().zip(expected.fft().iter_amplitude()) {
+                    assert!((a - e).abs() < 1e-3, "{overlap:?} fft {i}: {a} != {e}");
+                }
+            }
+        }
+    }
+
+    /// nothing needs to be initialized at runtime. not even the sample buffer
+    #[test]
+    fn test_static() {
+        type TestFFT = BufferedFFT<64, 256, 128, HanningWindow<256>, AWeighting<128>>;
+
+        static FFT: std::sync::Mutex<TestFFT> =
+            std::sync::Mutex::new(TestFFT::new(AWeighting::new(44_100.0)));
+
+        let mut fft = FFT.lock().unwrap();
+
+        // before the buffer is full, the missing samples are silence
+        assert!(fft.fft().iter_amplitude().all(|x| x == 0.0));
+
+        let samples = Samples(core::array::from_fn(|i| (i as f32 * 0.3).sin()));
+
+        fft.push_samples(&samples);
+
+        let mut expected = TestFFT::new(AWeighting::new(44_100.0));
+        expected.push_samples(&samples);
+
+        for (a, e) in fft
+            .fft()
+            .iter_amplitude()
+            .zip(expected.fft().iter_amplitude())
+        {
+            assert_eq!(a, e);
+        }
     }
 
     #[test]
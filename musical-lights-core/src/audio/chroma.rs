@@ -0,0 +1,139 @@
+//! Fold the spectrum into the 12 pitch classes of equal temperament.
+//!
+//! Every C (no matter the octave) goes into bin 0, every C# into bin 1, ... every B into bin 11.
+//! This shows the key and harmony of the music instead of just how loud the bass is.
+use super::amplitudes::{AggregatedBins, AggregatedBinsBuilder};
+use crate::audio::{bin_to_frequency, frequency_to_midi};
+
+pub const CHROMA_OUT: usize = 12;
+
+/// C2. below this the FFT bins are wider than a semitone so they smear across multiple pitch classes
+/// TODO: this depends on the FFT size. 4096 at 44.1 kHz is still too wide here
+const DEFAULT_MIN_FREQ: f32 = 65.41;
+
+/// C8. the top of a piano. above this is mostly cymbals and noise
+const DEFAULT_MAX_FREQ: f32 = 4186.0;
+
+pub struct ChromaBuilder<const FFT_OUT: usize> {
+    map: [Option<usize>; FFT_OUT],
+}
+
+/// TODO: should this be a trait instead?
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(transparent)]
+pub struct ChromaAmplitudes(pub AggregatedBins<CHROMA_OUT>);
+
+impl<const FFT_OUT: usize> ChromaBuilder<FFT_OUT> {
+    pub fn new(sample_rate_hz: f32) -> Self {
+        Self::new_with_range(DEFAULT_MIN_FREQ, DEFAULT_MAX_FREQ, sample_rate_hz)
+    }
+
+    /// The whole map is built here.
+    /// TODO: this needs a log2 so it can't be const yet
+    pub fn new_with_range(min_freq: f32, max_freq: f32, sample_rate_hz: f32) -> Self {
+        let mut map = [None; FFT_OUT];
+
+        for (i, x) in map.iter_mut().enumerate() {
+            let f = bin_to_frequency(i, sample_rate_hz, FFT_OUT);
+
+            *x = if f < min_freq || f > max_freq {
+                None
+            } else {
+                chroma_class(f)
+            };
+        }
+
+        Self { map }
+    }
+}
+
+impl<const FFT_OUT: usize> AggregatedBinsBuilder<FFT_OUT, CHROMA_OUT> for ChromaBuilder<FFT_OUT> {
+    type Output = ChromaAmplitudes;
+
+    #[inline]
+    fn as_inner_mut<'a>(&self, output: &'a mut Self::Output) -> &'a mut [f32; CHROMA_OUT] {
+        &mut output.0.0
+    }
+
+    #[inline]
+    fn bin_map(&self) -> &[Option<usize>; FFT_OUT] {
+        &self.map
+    }
+}
+
+/// turn a frequency into a pitch class. 0 = C, 1 = C#, ... 9 = A, 11 = B
+///
+/// TODO: a bin near the edge of two notes should probably be split between them
+pub fn chroma_class(f: f32) -> Option<usize> {
+    if f <= 0.0 {
+        return None;
+    }
+
+    let midi = frequency_to_midi(f).round();
+
+    // rem_euclid so that notes below midi 0 still wrap to the right class
+    Some((midi as i32).rem_euclid(CHROMA_OUT as i32) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+
+    use super::*;
+    use crate::audio::{FftOutputs, frequency_to_bin};
+
+    #[test]
+    fn test_chroma_class() {
+        assert_eq!(chroma_class(-1.0), None);
+        assert_eq!(chroma_class(0.0), None);
+        assert_eq!(chroma_class(261.63), Some(0));
+        assert_eq!(chroma_class(277.18), Some(1));
+        assert_eq!(chroma_class(440.0), Some(9));
+        // a little flat still rounds to A
+        assert_eq!(chroma_class(435.0), Some(9));
+        assert_eq!(chroma_class(880.0), Some(9));
+        assert_eq!(chroma_class(110.0), Some(9));
+        assert_eq!(chroma_class(493.88), Some(11));
+        // below midi 0
+        assert_eq!(chroma_class(7.72), Some(11));
+    }
+
+    #[test]
+    fn test_chroma_builder() {
+        const BINS: usize = 2048;
+
+        let sample_rate_hz = 44_100.0;
+
+        let builder = ChromaBuilder::<BINS>::new(sample_rate_hz);
+
+        let map = builder.bin_map();
+
+        // too low and too high to use
+        assert_eq!(map[0], None);
+        assert_eq!(map[frequency_to_bin(10_000.0, sample_rate_hz, BINS)], None);
+
+        // light up A3, A4, and E5
+        let mut spectrum = [Complex::new(0.0, 0.0); BINS];
+        for f in [220.0, 440.0, 659.26] {
+            spectrum[frequency_to_bin(f, sample_rate_hz, BINS)] = Complex::new(1.0, 0.0);
+        }
+
+        let loudness = builder.loudness(&FftOutputs::new(&spectrum));
+
+        let (loudest, _) = loudness
+            .0
+            .0
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+
+        assert_eq!(loudest, 9);
+
+        // both A bins went into the same class, so it should be louder than E
+        assert!(loudness.0.0[9] > loudness.0.0[4]);
+        assert!(loudness.0.0[4].is_finite());
+        assert!(!loudness.0.0[0].is_finite());
+    }
+}
@@ -0,0 +1,337 @@
+//! Constant-Q transform. Musically spaced bins with the same number of bins in every octave.
+//!
+//! An FFT's bins are all the same width in Hz. A 4096 FFT at 44.1 kHz has ~10 Hz bins, which is 1/4 of an octave at
+//! 40 Hz but 1/200 of an octave at 4 kHz. The bass all smears into a couple bins while the treble gets way more bins than
+//! it needs. Here every bin is the same fraction of an octave, so every note (even down at E1) gets its own column.
+//!
+//! This is the multi-rate version. Every octave down is decimated by 2 from the octave above it, so the same kernels
+//! work for every octave and the long windows that the bass needs don't cost any more than the treble.
+//!
+//! Samples -> ConstantQ -> ConstantQAmplitudes
+//!
+//! The lower octaves are slower to react. The lowest bin has to see ~17 cycles (with 12 bins per octave) before it can
+//! tell notes apart, which is about half a second at 32 Hz. That's just how it is. Don't use these for beat detection.
+//!
+//! TODO: the decimators delay each octave a little more than the one above it
+use circular_buffer::CircularBuffer;
+use num::Complex;
+
+use super::Decimator;
+use crate::windows::const_cos;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// taps for the 2:1 decimators between octaves. the notes we care about are way below the new nyquist, so this can be
+/// pretty sloppy
+const DECIMATOR_TAPS: usize = 16;
+
+/// One bin in the top octave. The same kernel is used for every octave
+#[derive(Clone, Copy, Debug)]
+struct Kernel {
+    /// how many samples this bin looks at. lower bins need more
+    len: usize,
+    /// multiply by this every sample to spin down to the bin's frequency
+    carrier: Complex<f32>,
+    /// multiply by this every sample for the hann window's cosine
+    window: Complex<f32>,
+}
+
+/// BINS_PER_OCTAVE bins in each of OCTAVES octaves. Starting at `min_freq`.
+///
+/// LEN is how many samples each octave keeps. It has to be longer than the lowest bin's kernel. [`Self::new`] panics
+/// and says how long it needs to be if it isn't. 512 is enough for 12 bins per octave at 44.1 kHz. 1024 for 24.
+///
+/// RAM is about `OCTAVES * LEN * 4` bytes. CPU is about `OCTAVES * BINS_PER_OCTAVE * LEN` complex multiplies per frame.
+pub struct ConstantQ<const BINS_PER_OCTAVE: usize, const OCTAVES: usize, const LEN: usize> {
+    min_freq: f32,
+    kernels: [Kernel; BINS_PER_OCTAVE],
+    /// `history[0]` is at the full sample rate and is the top octave. each one after it is at half the rate
+    history: [CircularBuffer<LEN, f32>; OCTAVES],
+    /// `decimators[d]` feeds `history[d + 1]`. the last one is never used
+    decimators: [Decimator<DECIMATOR_TAPS>; OCTAVES],
+}
+
+/// Amplitudes for each bin. `0[0][0]` is `min_freq`. A full scale sine right on a bin is 1.0
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConstantQAmplitudes<const BINS_PER_OCTAVE: usize, const OCTAVES: usize>(
+    pub [[f32; BINS_PER_OCTAVE]; OCTAVES],
+);
+
+impl<const BINS_PER_OCTAVE: usize, const OCTAVES: usize> Default
+    for ConstantQAmplitudes<BINS_PER_OCTAVE, OCTAVES>
+{
+    fn default() -> Self {
+        Self([[0.0; BINS_PER_OCTAVE]; OCTAVES])
+    }
+}
+
+impl<const BINS_PER_OCTAVE: usize, const OCTAVES: usize>
+    ConstantQAmplitudes<BINS_PER_OCTAVE, OCTAVES>
+{
+    /// every bin from low to high
+    pub fn as_flattened(&self) -> &[f32] {
+        self.0.as_flattened()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &f32> {
+        self.as_flattened().iter()
+    }
+
+    /// the bin with the most energy. None if everything is silent
+    pub fn loudest(&self) -> Option<usize> {
+        self.iter()
+            .enumerate()
+            .filter(|(_, x)| **x > 0.0)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+    }
+}
+
+impl<const BINS_PER_OCTAVE: usize, const OCTAVES: usize, const LEN: usize>
+    ConstantQ<BINS_PER_OCTAVE, OCTAVES, LEN>
+{
+    /// `min_freq` is the center of the lowest bin. For notes, use [`midi_to_frequency`](crate::audio::midi_to_frequency)
+    pub fn new(min_freq: f32, sample_rate_hz: f32) -> Self {
+        assert!(BINS_PER_OCTAVE > 0);
+        assert!(OCTAVES > 0);
+        assert!(min_freq > 0.0);
+
+        // every bin is this many times its own bandwidth
+        let q = 1.0 / (2.0f32.powf(1.0 / BINS_PER_OCTAVE as f32) - 1.0);
+
+        let top_octave_hz = min_freq * (1 << (OCTAVES - 1)) as f32;
+
+        let kernels = core::array::from_fn(|b| {
+            let hz = top_octave_hz * 2.0f32.powf(b as f32 / BINS_PER_OCTAVE as f32);
+
+            assert!(
+                hz < sample_rate_hz / 2.0,
+                "the top bin is above the nyquist frequency"
+            );
+
+            let len = (q * sample_rate_hz / hz).ceil() as usize;
+
+            assert!(
+                len <= LEN,
+                "LEN is too short. it needs to be at least {}",
+                len
+            );
+
+            Kernel {
+                len,
+                carrier: phasor(-core::f64::consts::TAU * hz as f64 / sample_rate_hz as f64),
+                window: phasor(core::f64::consts::TAU / len as f64),
+            }
+        });
+
+        let decimators = core::array::from_fn(|d| {
+            let hz = sample_rate_hz / (1 << d) as f32;
+
+            Decimator::new(hz, hz / 2.0)
+        });
+
+        Self {
+            min_freq,
+            kernels,
+            history: core::array::from_fn(|_| CircularBuffer::new()),
+            decimators,
+        }
+    }
+
+    pub const fn min_freq(&self) -> f32 {
+        self.min_freq
+    }
+
+    /// center frequency of bin `i`. `i` counts up from the lowest bin through all the octaves
+    pub fn frequency(&self, i: usize) -> f32 {
+        self.min_freq * 2.0f32.powf(i as f32 / BINS_PER_OCTAVE as f32)
+    }
+
+    /// forget all the old samples
+    pub fn reset(&mut self) {
+        for history in self.history.iter_mut() {
+            history.clear();
+        }
+
+        for decimator in self.decimators.iter_mut() {
+            decimator.reset();
+        }
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        for &x in samples {
+            self.push(x);
+        }
+    }
+
+    /// add one sample. every other sample goes down to the next octave, every fourth to the one after that, etc.
+    #[inline]
+    pub fn push(&mut self, x: f32) {
+        let mut x = Some(x);
+
+        for d in 0..OCTAVES {
+            let Some(y) = x.take() else {
+                break;
+            };
+
+            self.history[d].push_back(y);
+
+            if d + 1 < OCTAVES {
+                self.decimators[d].push(y, |z| x = Some(z));
+            }
+        }
+    }
+
+    pub fn amplitudes(&self) -> ConstantQAmplitudes<BINS_PER_OCTAVE, OCTAVES> {
+        let mut output = ConstantQAmplitudes::default();
+
+        self.amplitudes_into(&mut output);
+
+        output
+    }
+
+    /// Run the kernels over the newest samples. Call this once per frame, not every sample.
+    pub fn amplitudes_into(&self, output: &mut ConstantQAmplitudes<BINS_PER_OCTAVE, OCTAVES>) {
+        for (d, history) in self.history.iter().enumerate() {
+            // the top octave is the last row
+            let row = &mut output.0[OCTAVES - 1 - d];
+
+            for (y, kernel) in row.iter_mut().zip(self.kernels.iter()) {
+                *y = kernel.run(history);
+            }
+        }
+    }
+
+    /// dBFS for every bin
+    pub fn loudness_into(&self, output: &mut ConstantQAmplitudes<BINS_PER_OCTAVE, OCTAVES>) {
+        self.amplitudes_into(output);
+
+        for x in output.0.as_flattened_mut() {
+            *x = 20. * x.log10();
+        }
+    }
+}
+
+impl Kernel {
+    /// hann windowed. only looks at the newest `len` samples
+    #[inline]
+    fn run<const LEN: usize>(&self, history: &CircularBuffer<LEN, f32>) -> f32 {
+        let skip = history.len().saturating_sub(self.len);
+
+        let mut carrier = Complex::new(1.0, 0.0);
+        let mut window = Complex::new(1.0, 0.0);
+
+        let mut sum = Complex::new(0.0, 0.0);
+
+        for &x in history.iter().skip(skip) {
+            let w = 0.5 - 0.5 * window.re;
+
+            sum += carrier * (x * w);
+
+            carrier *= self.carrier;
+            window *= self.window;
+        }
+
+        // a hann window sums to len / 2 and a real sine only has half its amplitude at the positive frequency
+        sum.norm() * 4.0 / self.len as f32
+    }
+}
+
+/// e^(i * radians). micromath's sin and cos aren't accurate enough to spin for hundreds of samples
+const fn phasor(radians: f64) -> Complex<f32> {
+    Complex::new(
+        const_cos(radians) as f32,
+        const_cos(radians - core::f64::consts::FRAC_PI_2) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{
+        AggregatedBinsBuilder, BarkScaleBuilder, frequency_to_bin, midi_to_frequency,
+    };
+
+    const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+    /// C1 to B7
+    type Cqt = ConstantQ<12, 7, 512>;
+
+    fn sines(hz: &[f32], seconds: f32) -> Vec<f32> {
+        (0..(SAMPLE_RATE_HZ * seconds) as usize)
+            .map(|i| {
+                hz.iter()
+                    .map(|hz| (core::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE_HZ).sin())
+                    .sum::<f32>()
+                    / hz.len() as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_one_note_per_bin() {
+        let mut cqt = Cqt::new(midi_to_frequency(24.0), SAMPLE_RATE_HZ);
+
+        assert!(
+            (cqt.frequency(12) - 65.41).abs() < 0.1,
+            "{}",
+            cqt.frequency(12)
+        );
+
+        // A1, A3, A5. one in a low octave, one in the middle, one in the top
+        for midi in [33, 57, 81] {
+            let hz = midi_to_frequency(midi as f32);
+
+            cqt.reset();
+            cqt.push_samples(&sines(&[hz], 1.0));
+
+            let amplitudes = cqt.amplitudes();
+
+            let expected = midi - 24;
+            assert_eq!(amplitudes.loudest(), Some(expected), "{hz} Hz");
+
+            let level = amplitudes.as_flattened()[expected];
+            assert!((level - 1.0).abs() < 0.05, "{hz} Hz: {level}");
+
+            // the hann window puts the next notes over at about -6 dB. two notes over is quiet
+            let bins = amplitudes.as_flattened();
+            for (offset, max) in [(1, 0.6), (2, 0.1)] {
+                assert!(bins[expected + offset] < max * level, "{hz} Hz: {bins:?}");
+                assert!(bins[expected - offset] < max * level, "{hz} Hz: {bins:?}");
+            }
+        }
+    }
+
+    /// E1 and F1 are a semitone apart. the bark scale puts them in the same band. here they each get their own
+    #[test]
+    fn test_bass_notes_are_separate() {
+        let e1 = midi_to_frequency(28.0);
+        let f1 = midi_to_frequency(29.0);
+
+        let bark = BarkScaleBuilder::<2048>::new(SAMPLE_RATE_HZ);
+        let bark_map = bark.bin_map();
+        assert_eq!(
+            bark_map[frequency_to_bin(e1, SAMPLE_RATE_HZ, 2048)],
+            bark_map[frequency_to_bin(f1, SAMPLE_RATE_HZ, 2048)],
+        );
+
+        let mut cqt = Cqt::new(midi_to_frequency(24.0), SAMPLE_RATE_HZ);
+        cqt.push_samples(&sines(&[e1, f1], 1.0));
+
+        let amplitudes = cqt.amplitudes();
+        let bins = amplitudes.as_flattened();
+
+        // both notes are peaks. D#1 and F#1 around them are quieter
+        assert!(bins[4] > bins[3], "{:?}", &bins[..8]);
+        assert!(bins[5] > bins[6], "{:?}", &bins[..8]);
+        assert!(bins[4] > 0.3 && bins[5] > 0.3, "{:?}", &bins[..8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "LEN is too short")]
+    fn test_len_too_short() {
+        ConstantQ::<24, 7, 512>::new(midi_to_frequency(24.0), SAMPLE_RATE_HZ);
+    }
+}
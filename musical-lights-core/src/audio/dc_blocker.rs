@@ -0,0 +1,132 @@
+//! Remove the DC offset (and any rumble) before analysing.
+//!
+//! Analog mics sit on a bias voltage and that bias drifts with temperature and the battery. Even I2S mics have a little
+//! offset. A DC offset shows up as a huge bin 0 in the FFT, leaks into the bass bins through the window, and throws off
+//! the AGC and loudness meters.
+//!
+//! Samples -> DcBlocker -> Agc -> ...
+use super::Samples;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// One-pole high-pass filter. `y[n] = x[n] - x[n-1] + r * y[n-1]`
+///
+/// A cutoff around 10-20 Hz only removes the offset. Turn it up to 60-100 Hz to also remove wind and handling noise from
+/// a mic that is sewn into a jacket. This is a gentle 6 dB/octave slope, so it won't cut into the kick drum much.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DcBlocker {
+    /// the pole. closer to 1.0 is a lower cutoff
+    r: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl DcBlocker {
+    pub fn new(cutoff_hz: f32, sample_rate_hz: f32) -> Self {
+        assert!(cutoff_hz > 0.0);
+        assert!(cutoff_hz < sample_rate_hz / 2.0);
+
+        Self {
+            r: (-core::f32::consts::TAU * cutoff_hz / sample_rate_hz).exp(),
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    /// Start from `x` instead of from 0.0. Without this, the first block after startup has a big step in it while the
+    /// filter settles. Use the first sample (or the expected bias) from the mic.
+    pub fn reset_to(&mut self, x: f32) {
+        self.last_input = x;
+        self.last_output = 0.0;
+    }
+
+    pub fn reset(&mut self) {
+        self.reset_to(0.0);
+    }
+
+    #[inline]
+    pub fn run(&mut self, x: f32) -> f32 {
+        let y = x - self.last_input + self.r * self.last_output;
+
+        self.last_input = x;
+        self.last_output = y;
+
+        y
+    }
+
+    pub fn process_samples<const S: usize>(&mut self, samples: &mut Samples<S>) {
+        self.process(&mut samples.0);
+    }
+
+    /// filter the samples in place
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for x in samples.iter_mut() {
+            *x = self.run(*x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE_HZ: f32 = 48_000.0;
+
+    fn sine(hz: f32, offset: f32) -> [f32; 4800] {
+        core::array::from_fn(|i| {
+            offset + 0.5 * (core::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE_HZ).sin()
+        })
+    }
+
+    fn rms(x: &[f32]) -> f32 {
+        (x.iter().map(|x| x * x).sum::<f32>() / x.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_removes_offset() {
+        let mut dc = DcBlocker::new(20.0, SAMPLE_RATE_HZ);
+
+        let mut samples = Samples(sine(1_000.0, 0.3));
+        dc.reset_to(samples.0[0]);
+
+        // a few blocks to settle
+        for _ in 0..5 {
+            samples = Samples(sine(1_000.0, 0.3));
+            dc.process_samples(&mut samples);
+        }
+
+        let mean = samples.0.iter().sum::<f32>() / samples.0.len() as f32;
+        assert!(mean.abs() < 1e-3, "{mean}");
+
+        // 1 kHz goes through untouched. 0.354 is the rms of a 0.5 sine
+        let level = rms(&samples.0);
+        assert!((level - 0.354).abs() < 1e-3, "{level}");
+    }
+
+    #[test]
+    fn test_cutoff() {
+        for (cutoff_hz, hz, expected) in [
+            // -3 dB at the cutoff
+            (80.0, 80.0, core::f32::consts::FRAC_1_SQRT_2),
+            // an octave down is about -7 dB
+            (80.0, 40.0, 0.447),
+            (80.0, 2_000.0, 1.0),
+        ] {
+            let mut dc = DcBlocker::new(cutoff_hz, SAMPLE_RATE_HZ);
+
+            let mut samples = sine(hz, 0.0);
+            for _ in 0..5 {
+                samples = sine(hz, 0.0);
+                dc.process(&mut samples);
+            }
+
+            let gain = rms(&samples) / 0.354;
+            assert!(
+                (gain - expected).abs() < 0.02,
+                "{hz} Hz: {gain} != {expected}"
+            );
+        }
+    }
+}
@@ -1,14 +1,16 @@
-//! TODO: i don't like this very much
+//! TODO: i don't like this very much. use [`Agc`](super::Agc) instead.
 
 /// limit how fast a value can decrease
 /// TODO: have it decelerate like with gravity
 /// TODO: think more about this
+#[deprecated(note = "use Agc on the samples instead")]
 pub struct DownResistanceBuilder<const N: usize> {
     /// max rate that a value can decrease
     rate: f32,
     buffer: [f32; N],
 }
 
+#[allow(deprecated)]
 impl<const N: usize> DownResistanceBuilder<N> {
     pub fn new(rate: f32) -> Self {
         Self {
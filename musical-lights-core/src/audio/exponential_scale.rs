@@ -1,6 +1,5 @@
 use super::amplitudes::{AggregatedBins, AggregatedBinsBuilder};
 use crate::audio::frequency_to_bin;
-use crate::logging::info;
 
 #[allow(unused_imports)]
 use micromath::F32Ext;
@@ -10,9 +9,6 @@ use micromath::F32Ext;
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ExponentialScaleBuilder<const IN: usize, const OUT: usize> {
-    /// TODO: these used to be args on new, but its easier to keep them here. maybe we can think of a better const builder pattern
-    min_freq: f32,
-    max_freq: f32,
     /// index is the input id. the value is the output id. if none, the input is ignored
     /// TODO: do something fancy with ranges instead
     map: [Option<usize>; IN],
@@ -26,87 +22,74 @@ pub struct ExponentialScaleAmplitudes<const OUT: usize>(pub AggregatedBins<OUT>)
 
 /// bins in, bands/channels out
 impl<const IN: usize, const OUT: usize> ExponentialScaleBuilder<IN, OUT> {
-    pub const fn uninit(min_freq: f32, max_freq: f32) -> Self {
-        Self {
-            min_freq,
-            max_freq,
-            map: [None; IN],
-        }
-    }
-
-    pub fn new(min_freq: f32, max_freq: f32, sample_rate_hz: f32) -> Self {
-        let mut x = Self::uninit(min_freq, max_freq);
-        x.init(sample_rate_hz);
-        x
-    }
-}
-
-impl<const IN: usize, const OUT: usize> AggregatedBinsBuilder<IN, OUT>
-    for ExponentialScaleBuilder<IN, OUT>
-{
-    type Output = ExponentialScaleAmplitudes<OUT>;
-
-    #[inline]
-    fn as_inner_mut<'a>(&self, output: &'a mut Self::Output) -> &'a mut [f32; OUT] {
-        &mut output.0.0
-    }
-
-    #[inline]
-    fn bin_map(&self) -> &[Option<usize>; IN] {
-        &self.map
-    }
-
-    /// TODO: this needs to be const and done during the new.
-    /// TODO: how can we use types to be sure this init gets called
-    fn init(&mut self, sample_rate_hz: f32) {
+    /// The whole map is built here. This is const so that it can go in a `static`
+    pub const fn new(min_freq: f32, max_freq: f32, sample_rate_hz: f32) -> Self {
         assert!(
-            sample_rate_hz / 2.0 >= self.max_freq,
+            sample_rate_hz / 2.0 >= max_freq,
             "sample rate too low. must be at least double the maximum frequency"
         );
 
         // always skip the very first bin. it is too noisy
         // TODO: actually the very first bin isn't noise. its the average across all bins (i think)
-        let min_bin = frequency_to_bin(self.min_freq, sample_rate_hz, IN);
+        let min_bin = frequency_to_bin(min_freq, sample_rate_hz, IN);
 
         // TODO: off by 1?
-        let max_bin = frequency_to_bin(self.max_freq, sample_rate_hz, IN) + 1;
-
-        // TODO: this info doesn't show on start for some reason.
-        let frequency_resolution = sample_rate_hz / 2.0 / (IN as f32);
-        info!("frequency resolution = {}", frequency_resolution);
+        let max_bin = frequency_to_bin(max_freq, sample_rate_hz, IN) + 1;
 
-        let e = find_e(OUT as u32, min_bin as u32, max_bin as u32).unwrap();
-        info!("E = {}", e);
+        let Some(e) = find_e(OUT as u32, min_bin as u32, max_bin as u32) else {
+            panic!("no exponent fits this many bands between min_freq and max_freq");
+        };
 
         // TODO: use end_bins instead of map? less RAM but more complicated code
-        // TODO: this is big and might cause a stack overflow! need to take a buffer or refactor
-        let mut end_bins = [0; OUT];
+        let mut map = [None; IN];
 
         let mut count = min_bin;
-        for (b, end_bin) in end_bins.iter_mut().enumerate() {
-            let n = e.powi(b as i32);
+        let mut start_bin = min_bin;
 
-            let d = n.ceil() as usize;
+        let mut b = 0;
+        while b < OUT {
+            let n = const_powf(e, b as i32);
+
+            // ceil isn't const
+            let mut d = n as usize;
+            if (d as f32) < n {
+                d += 1;
+            }
 
             count += d;
 
             // TODO: is this where max_bin should be checked? we shouldn't be that far over, but we should test more if its a lot over
-            *end_bin = count.min(max_bin);
+            let end_bin = if count < max_bin { count } else { max_bin };
+
+            let mut i = start_bin;
+            while i < end_bin && i < IN {
+                map[i] = Some(b);
+                i += 1;
+            }
+
+            start_bin = end_bin;
+            b += 1;
         }
 
         // TODO: what if end_bin is < max_bin?
 
-        let mut start_bin = min_bin;
-        for (b, &end_bin) in end_bins.iter().enumerate() {
-            info!("{} = bins {}..{}", b, start_bin, end_bin);
+        Self { map }
+    }
+}
 
-            // TODO: should take or skip be first? if we do skip then take, take needs to be the number of bins, not the final index - 1
-            for x in self.map.iter_mut().take(end_bin).skip(start_bin) {
-                *x = Some(b);
-            }
+impl<const IN: usize, const OUT: usize> AggregatedBinsBuilder<IN, OUT>
+    for ExponentialScaleBuilder<IN, OUT>
+{
+    type Output = ExponentialScaleAmplitudes<OUT>;
 
-            start_bin = end_bin;
-        }
+    #[inline]
+    fn as_inner_mut<'a>(&self, output: &'a mut Self::Output) -> &'a mut [f32; OUT] {
+        &mut output.0.0
+    }
+
+    #[inline]
+    fn bin_map(&self) -> &[Option<usize>; IN] {
+        &self.map
     }
 }
 
@@ -2,16 +2,19 @@
 //!
 //! This is an alternative to the code in [`BufferedFFT`].
 use crate::{
-    audio::AggregatedBins,
+    audio::{AggregatedBins, iso226_weighting},
     logging::{error, trace},
     remap,
 };
+#[cfg(not(feature = "fixed-point"))]
+use biquad::{Biquad, DirectForm2Transposed};
 use biquad::{
-    Biquad, DirectForm2Transposed,
     coefficients::{Coefficients, Type},
     frequency::ToHertz,
 };
 use core::{array, cmp::Ordering};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 // use std::thread::yield_now;
 
 #[allow(unused_imports)]
@@ -25,25 +28,187 @@ const BASS_BANDS: usize = 5;
 /// combine the bottom 5 bands into a single bass band
 const BARKISH_BANDS: usize = BARK_BANDS - BASS_BANDS + 1;
 
-/// ≈⅓ Bark
-const Q_BOOST: f32 = 3.0;
+/// The original filter bank. 24 Bark bands with the bottom 5 merged into a single bass band.
+pub type BarkBank = FilterBank<BARK_BANDS, BARKISH_BANDS>;
 
 /// Zwicker / Traunmüller Bark band edges (Hz).
 ///
-/// TODO: instead of hard coding bark, have our exponential helper
+/// [`BandScale::Bark`] is close to this, but it stretches to fit any number of bands.
 const BARK_EDGES: [f32; BARK_BANDS + 1] = [
     0.0, 100.0, 200.0, 300.0, 400.0, 510.0, 630.0, 770.0, 920.0, 1080.0, 1270.0, 1480.0, 1720.0,
     2000.0, 2320.0, 2700.0, 3150.0, 3700.0, 4400.0, 5300.0, 6400.0, 7700.0, 9500.0, 12_000.0,
     15_500.0,
 ];
 
-/// Inverse 60‑phon equal‑loudness gains (ISO‑226 2023, smoothed).
+/// Equal loudness contour to use until [`FilterBank::set_phon`] is called.
 ///
-/// This is just the constants, so no licensing is required. Check out the paper though! It's really cool!
-const ISO60_GAIN: [f32; BARK_BANDS] = [
-    0.0891, 0.1259, 0.1585, 0.1995, 0.2985, 0.3548, 0.4217, 0.4729, 0.5311, 0.5964, 0.6309, 0.6683,
-    0.7079, 0.7499, 0.7943, 0.8412, 0.8909, 0.9433, 1.0000, 1.1220, 1.2589, 1.4125, 1.6768, 2.1060,
-];
+/// 60 phon is about the level of a conversation. A loud venue is closer to 80 or 90.
+const DEFAULT_PHON: f32 = 60.0;
+
+/// Where 0.0 is for each band.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FloorMode {
+    /// Every band tracks its own quietest level. A band that is always loud (like a whine from the speakers) fades to
+    /// 0.0 after a while.
+    PerBand,
+    /// Every band uses `fraction` of the average floor across all the bands. One loud high pitched whine raises the
+    /// floor a little for everything instead of hiding itself. This seems closer to how people hear.
+    Global { fraction: f32 },
+}
+
+/// Tuning for a [`FilterBank`]. The defaults are what the jacket has been using.
+///
+/// This is serializable so that it can be changed for a venue without reflashing. Missing fields use the defaults.
+///
+/// TODO: these were all picked by watching the lights. think more about them
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[serde(default)]
+pub struct BarkBankConfig {
+    /// how fast the peak goes up when it gets louder (seconds)
+    pub peak_attack_s: f32,
+    /// how fast the peak comes back down when it gets quieter (seconds)
+    pub peak_release_s: f32,
+    /// Where the peak starts. Not 0 because then everything starts too bright.
+    /// This is on the scale after the loudness exponent
+    pub peak_init: f32,
+    /// how fast the floor goes down when it gets quieter (seconds). 0 is instant
+    pub floor_fall_s: f32,
+    /// how fast the floor comes up when it gets louder (seconds)
+    pub floor_rise_s: f32,
+    pub floor_init: f32,
+    pub floor_mode: FloorMode,
+    /// The peak is always at least this many times the floor. This keeps a quiet room from getting stretched to full
+    /// brightness.
+    pub min_peak_ratio: f32,
+    /// Zwicker's exponent for perceived loudness. loudness = amplitude^0.23
+    pub loudness_exponent: f32,
+    /// Multiplies the Q of each band-pass filter. Higher is narrower. 3.0 is about ⅓ Bark
+    pub q_boost: f32,
+    /// Equal loudness contour. See [`FilterBank::set_phon`]
+    pub phon: f32,
+}
+
+impl Default for BarkBankConfig {
+    fn default() -> Self {
+        Self {
+            peak_attack_s: 0.022,
+            peak_release_s: 10.0,
+            peak_init: 2.0,
+            floor_fall_s: 0.0,
+            floor_rise_s: 10.0,
+            floor_init: 0.0,
+            floor_mode: FloorMode::PerBand,
+            min_peak_ratio: 2.0,
+            loudness_exponent: 0.23,
+            q_boost: 3.0,
+            phon: DEFAULT_PHON,
+        }
+    }
+}
+
+/// How to space the band edges of a [`FilterBank`].
+///
+/// Most of these stretch to fit any number of bands between a min and max frequency.
+/// The octave scales have fixed widths, so they only use the min frequency and go up from there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BandScale {
+    /// critical bands (Traunmüller's formula)
+    Bark,
+    /// equivalent rectangular bandwidth (Glasberg & Moore)
+    Erb,
+    /// evenly spaced on a log scale. like the [`ExponentialScaleBuilder`](crate::audio::ExponentialScaleBuilder)
+    Log,
+    /// the mel scale that everyone uses for machine learning
+    Mel,
+    /// 1 octave wide bands centered on the standard frequencies (..., 500, 1k, 2k, ...)
+    Octave,
+    /// ⅓ octave wide bands centered on the standard frequencies (..., 800, 1k, 1.25k, ...)
+    ThirdOctave,
+}
+
+#[derive(Clone, Copy, Debug, Error, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FilterBankError {
+    #[error(
+        "band {band} is centered at {center_hz} Hz. it needs to be below {top_hz} Hz (max_freq or nyquist)"
+    )]
+    BandTooHigh {
+        band: usize,
+        center_hz: f32,
+        top_hz: f32,
+    },
+}
+
+impl BandScale {
+    /// the frequency of edge `i` when there are `bands` bands. there are `bands + 1` edges.
+    pub fn edge(&self, i: usize, bands: usize, min_freq: f32, max_freq: f32) -> f32 {
+        match self {
+            Self::Octave => octave_edge(i, 1.0, min_freq),
+            Self::ThirdOctave => octave_edge(i, 3.0, min_freq),
+            _ => {
+                let lo = self.warp(min_freq);
+                let hi = self.warp(max_freq);
+
+                self.unwarp(lo + (hi - lo) * i as f32 / bands as f32)
+            }
+        }
+    }
+
+    /// frequency to this scale
+    fn warp(&self, f: f32) -> f32 {
+        match self {
+            Self::Bark => 26.81 * f / (1960.0 + f) - 0.53,
+            Self::Erb => 21.4 * (1.0 + 0.00437 * f).log10(),
+            Self::Log | Self::Octave | Self::ThirdOctave => f.log2(),
+            Self::Mel => 2595.0 * (1.0 + f / 700.0).log10(),
+        }
+    }
+
+    /// this scale to frequency
+    fn unwarp(&self, x: f32) -> f32 {
+        match self {
+            Self::Bark => 1960.0 * (x + 0.53) / (26.28 - x),
+            Self::Erb => (10.0f32.powf(x / 21.4) - 1.0) / 0.00437,
+            Self::Log | Self::Octave | Self::ThirdOctave => 2.0f32.powf(x),
+            Self::Mel => 700.0 * (10.0f32.powf(x / 2595.0) - 1.0),
+        }
+    }
+}
+
+/// IEC 61260 style band edges. the first band is the first one with a center at or above `min_freq`
+fn octave_edge(i: usize, fraction: f32, min_freq: f32) -> f32 {
+    let first = (fraction * (min_freq / 1000.0).log2()).ceil();
+
+    1000.0 * 2.0f32.powf((first + i as f32 - 0.5) / fraction)
+}
+
+impl BarkBankConfig {
+    fn peak_env(&self, fps: f32, value: f32) -> Envelope {
+        Envelope::new(self.peak_attack_s, self.peak_release_s, fps, value)
+    }
+
+    fn floor_env(&self, fps: f32, value: f32) -> Floor {
+        Floor::new(self.floor_fall_s, self.floor_rise_s, fps, value)
+    }
+}
+
+/// TODO: result type instead of unwrap?
+fn band_pass(sample_hz: f32, center_hz: f32, bandwidth_hz: f32, q_boost: f32) -> BiquadStage {
+    let q = (center_hz / bandwidth_hz) * q_boost;
+
+    let c = Coefficients::from_params(Type::BandPass, sample_hz.hz(), center_hz.hz(), q).unwrap();
+
+    BiquadStage::new(c)
+}
+
+/// center of a band. the lowest bark band starts at 0 so it can't use the geometric mean
+#[inline]
+fn band_center(lo: f32, hi: f32) -> f32 {
+    if lo > 0.0 { (lo * hi).sqrt() } else { hi * 0.5 }
+}
 
 /// Generic attack–release envelope.
 ///
@@ -63,8 +228,13 @@ pub struct Floor {
     rise: f32, // slow when it rises
 }
 
+#[cfg(not(feature = "fixed-point"))]
 type BiquadStage = DirectForm2Transposed<f32>;
 
+/// floats are way too slow on the M0 to run 48 biquads per sample
+#[cfg(feature = "fixed-point")]
+type BiquadStage = crate::audio::fixed::FixedBiquad;
+
 /// per‑bar state (no display value here. but maybe we should have it here)
 struct BandState {
     /// 1st biquad per band
@@ -75,6 +245,10 @@ struct BandState {
     peak_env: Envelope,
     /// keep track of the quietst this band has been over a long time
     floor_env: Floor,
+    /// center frequency of the band-pass filters
+    center_hz: f32,
+    /// distance between the band edges. this and the q boost set the filter's Q
+    bandwidth_hz: f32,
     /// equal loudness countour weighting
     /// TODO: probably 60‑phon weight is the best for this, but we should think more about it
     a_coeff: f32,
@@ -93,8 +267,15 @@ impl core::fmt::Debug for BandState {
     }
 }
 
-pub struct BarkBank {
-    bands: [BandState; BARK_BANDS],
+/// A bank of band-pass filters.
+///
+/// FILTERS = number of band-pass filters.
+/// BANDS = number of outputs. If this is less than FILTERS, the bottom filters are merged into a single bass band.
+pub struct FilterBank<const FILTERS: usize, const BANDS: usize> {
+    bands: [BandState; FILTERS],
+    config: BarkBankConfig,
+    sample_hz: f32,
+    fps_target: f32,
 }
 
 impl Floor {
@@ -122,11 +303,11 @@ impl Floor {
 
 impl BandState {
     /// `x` must be the sum of squares for all the samples divided by the number of samples in this block.
-    fn run(&mut self, mut x: f32) {
+    fn run(&mut self, mut x: f32, loudness_exponent: f32) {
         // RMS amplitude
         // apply equal loudness curve
         // Zwicker exponent for perceived loudness (TODO: i'm not sure about this. i think we want it here. we definitely want it somewhere in the pipeline)
-        x = (x.sqrt() * self.a_coeff).powf(0.23);
+        x = (x.sqrt() * self.a_coeff).powf(loudness_exponent);
 
         // TODO: should more of the above code be inside the run function? having it take the raw value makes sense to
 
@@ -179,60 +360,188 @@ impl Envelope {
 impl BarkBank {
     /// Build filters for a given sample rate.
     ///
-    /// TODO: some of the float math makes this not work with const
     /// TODO: result type instead of unwrap?
     pub fn new(fps_target: f32, sample_hz: f32) -> Self {
+        Self::new_with_edges(fps_target, sample_hz, &BARK_EDGES)
+    }
+}
+
+impl<const FILTERS: usize, const BANDS: usize> FilterBank<FILTERS, BANDS> {
+    /// Build filters with edges from one of the common scales.
+    ///
+    /// The octave scales are always the standard bands, starting at `min_freq`. Too many of them will go past
+    /// `max_freq` or half the sample rate. That is an error instead of a panic.
+    pub fn new_with_scale(
+        fps_target: f32,
+        sample_hz: f32,
+        scale: BandScale,
+        min_freq: f32,
+        max_freq: f32,
+    ) -> Result<Self, FilterBankError> {
+        assert!(min_freq > 0.0 && min_freq < max_freq);
+
+        let top_hz = max_freq.min(sample_hz / 2.0);
+
+        let edge = |i| scale.edge(i, FILTERS, min_freq, max_freq);
+
+        for band in 0..FILTERS {
+            let center_hz = band_center(edge(band), edge(band + 1));
+
+            if center_hz >= top_hz {
+                return Err(FilterBankError::BandTooHigh {
+                    band,
+                    center_hz,
+                    top_hz,
+                });
+            }
+        }
+
+        Ok(Self::new_with_edges_iter(
+            fps_target,
+            sample_hz,
+            (0..=FILTERS).map(edge),
+        ))
+    }
+
+    /// Build filters with custom band edges (Hz). There must be `FILTERS + 1` edges.
+    ///
+    /// TODO: some of the float math makes this not work with const
+    /// TODO: result type instead of unwrap?
+    pub fn new_with_edges(fps_target: f32, sample_hz: f32, edges: &[f32]) -> Self {
+        assert_eq!(edges.len(), FILTERS + 1, "need one more edge than filters");
+
+        Self::new_with_edges_iter(fps_target, sample_hz, edges.iter().copied())
+    }
+
+    fn new_with_edges_iter(
+        fps_target: f32,
+        sample_hz: f32,
+        mut edges: impl Iterator<Item = f32>,
+    ) -> Self {
         assert!(fps_target > 0.0 && sample_hz > 0.0);
+        assert!(
+            BANDS > 0 && BANDS <= FILTERS,
+            "can't have more bands than filters"
+        );
+
+        let config = BarkBankConfig::default();
+
+        let peak_env = config.peak_env(fps_target, config.peak_init);
+
+        let floor_env = config.floor_env(fps_target, config.floor_init);
 
-        let sample_hz = (sample_hz).hz();
+        let mut lo = edges.next().unwrap();
 
-        // TODO: think more about these timings and the init. we don't want to start at 0 because then it starts too bright
-        let peak_env = Envelope::new(0.022, 10.0, fps_target, 2.0);
+        let bands: [BandState; FILTERS] = array::from_fn(|_| {
+            let hi = edges.next().unwrap();
 
-        // TODO: think more about these timings and the init
-        let floor_env = Floor::new(0.0, 10.0, fps_target, 0.);
+            assert!(lo < hi, "band edges must increase");
 
-        let bands: [BandState; BARK_BANDS] = array::from_fn(|band| {
-            let (lo, hi) = (BARK_EDGES[band], BARK_EDGES[band + 1]);
-            let fc = if lo > 0.0 { (lo * hi).sqrt() } else { hi * 0.5 };
-            let q = (fc / (hi - lo)) * Q_BOOST;
-            let c = Coefficients::from_params(Type::BandPass, sample_hz, fc.hz(), q).unwrap();
+            let fc = band_center(lo, hi);
 
-            let filter = BiquadStage::new(c);
+            assert!(
+                fc < sample_hz / 2.0,
+                "bands must be centered below the nyquist frequency"
+            );
 
-            let a_coeff = ISO60_GAIN[band];
+            let bandwidth_hz = hi - lo;
+
+            lo = hi;
+
+            let filter = band_pass(sample_hz, fc, bandwidth_hz, config.q_boost);
+
+            let a_coeff = iso226_weighting(fc, config.phon);
 
             BandState {
                 filter1: filter,
                 filter2: filter,
                 peak_env,
                 floor_env,
+                center_hz: fc,
+                bandwidth_hz,
                 a_coeff,
                 value: 0.,
             }
         });
 
-        // let map = [
-        //     0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19,
-        // ];
+        Self {
+            bands,
+            config,
+            sample_hz,
+            fps_target,
+        }
+    }
 
-        Self { bands }
+    /// Use a different config and start from its initial levels
+    pub fn with_config(mut self, config: BarkBankConfig) -> Self {
+        self.set_config(config);
+        self.reset();
+        self
+    }
+
+    pub fn config(&self) -> &BarkBankConfig {
+        &self.config
+    }
+
+    /// Change the tuning while running. The current peak and floor levels are kept.
+    ///
+    /// The filters are only rebuilt if [`BarkBankConfig::q_boost`] changed.
+    pub fn set_config(&mut self, config: BarkBankConfig) {
+        let rebuild_filters = config.q_boost != self.config.q_boost;
+
+        for st in self.bands.iter_mut() {
+            st.peak_env = config.peak_env(self.fps_target, st.peak_env.value);
+            st.floor_env = config.floor_env(self.fps_target, st.floor_env.value);
+
+            if rebuild_filters {
+                let filter = band_pass(
+                    self.sample_hz,
+                    st.center_hz,
+                    st.bandwidth_hz,
+                    config.q_boost,
+                );
+
+                st.filter1 = filter;
+                st.filter2 = filter;
+            }
+        }
+
+        self.config = config;
+
+        self.set_phon(config.phon);
+    }
+
+    /// Forget the recent peaks and floors. Call this when moving to a new room.
+    pub fn reset(&mut self) {
+        for st in self.bands.iter_mut() {
+            st.peak_env.value = self.config.peak_init;
+            st.floor_env.value = self.config.floor_init;
+        }
+    }
+
+    /// Switch to the equal loudness contour for a different listening level.
+    ///
+    /// Quiet rooms need more bass and treble boosted to look balanced. Loud venues need less.
+    pub fn set_phon(&mut self, phon: f32) {
+        self.config.phon = phon;
+
+        for st in self.bands.iter_mut() {
+            st.a_coeff = iso226_weighting(st.center_hz, phon);
+        }
     }
 
     /// TODO: can't decide if pcm should be i16 or i24 or f32
-    /// Process one frame of `pcm` samples and return a fresh array of 20 normalized band outputs.
+    /// Process one frame of `pcm` samples and return a fresh array of normalized band outputs.
     /// 0.0 is the quietest sound heard recently. 1.0 is the loudest sound heard recently
-    pub fn push_samples(&mut self, pcm: &[f32]) -> AggregatedBins<BARKISH_BANDS> {
-        // 1) Accumulate raw power per analysis band (24 bands)
-        let mut tmp24 = [0.0f32; BARK_BANDS];
-
-        // TODO: can we make this a compile time check?
-        debug_assert_eq!(tmp24.len(), self.bands.len());
+    #[cfg(not(feature = "fixed-point"))]
+    pub fn push_samples(&mut self, pcm: &[f32]) -> AggregatedBins<BANDS> {
+        // 1) Accumulate raw power per analysis band
+        let mut tmp = [0.0f32; FILTERS];
 
         // do the division once. multiplication is faster on an esp32
         let inv_n = 1.0 / pcm.len() as f32;
 
-        for (st, t) in self.bands.iter_mut().zip(tmp24.iter_mut()) {
+        for (st, t) in self.bands.iter_mut().zip(tmp.iter_mut()) {
             for &x in pcm {
                 let y = st.filter2.run(st.filter1.run(x));
                 *t += y * y;
@@ -243,36 +552,113 @@ impl BarkBank {
             // yield_now();
         }
 
-        // 2) Update all 24 peak and floor envelopes
-        for (st, x) in self.bands.iter_mut().zip(tmp24.iter().copied()) {
-            st.run(x);
+        self.push_power(tmp)
+    }
+
+    /// Process one frame of `pcm` samples and return a fresh array of normalized band outputs.
+    /// 0.0 is the quietest sound heard recently. 1.0 is the loudest sound heard recently
+    ///
+    /// The `fixed-point` feature is on, so the samples are converted to Q30 and the filters are all integer math.
+    /// Use [`Self::push_samples_q15`] to skip the floats completely.
+    #[cfg(feature = "fixed-point")]
+    pub fn push_samples(&mut self, pcm: &[f32]) -> AggregatedBins<BANDS> {
+        const Q30_ONE: f32 = (1 << 30) as f32;
+
+        self.push_samples_q30(pcm, |x| (x * Q30_ONE) as i32)
+    }
+
+    /// [`Self::push_samples`] for Q15 samples (like from [`I2sFormat::parse_mono_to_q15`](crate::audio::I2sFormat::parse_mono_to_q15))
+    #[cfg(feature = "fixed-point")]
+    pub fn push_samples_q15(&mut self, pcm: &[crate::audio::fixed::Q15]) -> AggregatedBins<BANDS> {
+        self.push_samples_q30(pcm, |x| (x as i32) << 15)
+    }
+
+    /// the per-sample work is all integers. the power is converted to floats once per band per frame
+    ///
+    /// The samples are converted to Q30 once per chunk and then every band runs over that chunk. Converting inside the
+    /// band loop would do the (soft-float on an M0) conversion FILTERS times for every sample.
+    #[cfg(feature = "fixed-point")]
+    fn push_samples_q30<T: Copy>(
+        &mut self,
+        pcm: &[T],
+        to_q30: impl Fn(T) -> i32,
+    ) -> AggregatedBins<BANDS> {
+        // Q22 squared is Q44. that leaves room to sum a lot of samples in an i64
+        const POWER_SHIFT: u32 = 8;
+        const POWER_ONE: f32 = (1u64 << (2 * (30 - POWER_SHIFT))) as f32;
+
+        /// how many samples are converted at a time. small enough for the stack
+        const CHUNK: usize = 64;
+
+        // 1) Accumulate raw power per analysis band
+        let mut sums = [0i64; FILTERS];
+
+        let mut q30 = [0i32; CHUNK];
+
+        for chunk in pcm.chunks(CHUNK) {
+            let q30 = &mut q30[..chunk.len()];
+
+            for (q, &x) in q30.iter_mut().zip(chunk) {
+                *q = to_q30(x);
+            }
+
+            for (st, sum) in self.bands.iter_mut().zip(sums.iter_mut()) {
+                for &x in q30.iter() {
+                    let y = (st.filter2.run(st.filter1.run(x)) >> POWER_SHIFT) as i64;
+                    *sum += y * y;
+                }
+            }
+        }
+
+        let inv_n = 1.0 / (pcm.len() as f32 * POWER_ONE);
+
+        self.push_power(sums.map(|sum| sum as f32 * inv_n))
+    }
+
+    /// `power` is the mean square of each filter's output for this frame
+    fn push_power(&mut self, power: [f32; FILTERS]) -> AggregatedBins<BANDS> {
+        // the bottom filters that get merged into the bass band. 1 if nothing is merged
+        let bass_bands = FILTERS - BANDS + 1;
+
+        // 2) Update all the peak and floor envelopes
+        for (st, x) in self.bands.iter_mut().zip(power) {
+            st.run(x, self.config.loudness_exponent);
         }
 
-        // 3) Combine 24 → 20 outputs (first five summed as bass). Also normalize the bands so 1.0 is the loudest sound heard recently.
-        let mut output = [0.0f32; BARKISH_BANDS];
+        let global_floor = match self.config.floor_mode {
+            FloorMode::PerBand => None,
+            FloorMode::Global { fraction } => Some(
+                fraction * self.bands.iter().map(|x| x.floor_env.value).sum::<f32>()
+                    / FILTERS as f32,
+            ),
+        };
+
+        let floor_of = |st: &BandState| global_floor.unwrap_or(st.floor_env.value);
 
-        // calculate the bass band first by summing the first 5
+        let min_peak_ratio = self.config.min_peak_ratio;
+
+        // 3) Combine FILTERS → BANDS outputs (the bottom filters summed as bass). Also normalize the bands so 1.0 is the loudest sound heard recently.
+        let mut output = [0.0f32; BANDS];
+
+        // calculate the bass band first by summing the bottom filters
         // TODO: is adding after we've done the powf correct? it feels wrong to me. but its just one band. come back to this later
         // TODO: calculate t,b with one iter and fold?
         // TODO: saturating sub on t or is there no chance of underflow?
         // TODO: i think a should be some value larger than 0. I'm not sure what though. possibly something different for each band similar to the equal loudness contour
-        // TODO: i'm still not convinced a per-band floor is right. i think we want to subtract some fraction of the average across all bands. one loud high pitched whine making you not hear the rest seems like it matches human perception to me
-        let bass_val = self.bands[0..BASS_BANDS]
+        // TODO: i'm still not convinced a per-band floor is right. try FloorMode::Global at a party and compare
+        let bass_val = self.bands[0..bass_bands]
             .iter()
             .map(|x| x.value)
             .sum::<f32>();
 
-        let bass_floor = self.bands[0..BASS_BANDS]
-            .iter()
-            .map(|x| x.floor_env.value)
-            .sum::<f32>();
+        let bass_floor = self.bands[0..bass_bands].iter().map(floor_of).sum::<f32>();
 
         // TODO: think more about how to include the floor in here
-        let bass_peak = self.bands[0..BASS_BANDS]
+        let bass_peak = self.bands[0..bass_bands]
             .iter()
             .map(|x| x.peak_env.value)
             .sum::<f32>()
-            .max(bass_floor * 2.);
+            .max(bass_floor * min_peak_ratio);
 
         // // TODO: feature to opt into this?
         // yield_now();
@@ -280,20 +666,22 @@ impl BarkBank {
         output[0] = remap(bass_val, bass_floor, bass_peak, 0., 1.0);
 
         // calculate the rest of the bands.
-        for (st, out) in self.bands[BASS_BANDS..BARK_BANDS]
+        for (st, out) in self.bands[bass_bands..FILTERS]
             .iter()
             .zip(output.iter_mut().skip(1))
         {
-            let peak = st.peak_env.value.max(st.floor_env.value * 2.);
+            let floor = floor_of(st);
+
+            let peak = st.peak_env.value.max(floor * min_peak_ratio);
 
             // TODO: see todos above about the merged values. some apply here too
-            *out = remap(st.value, st.floor_env.value, peak, 0.0, 1.0);
+            *out = remap(st.value, floor, peak, 0.0, 1.0);
         }
 
         trace!("band 1: {:?}", self.bands[1]);
 
         // TODO: DEBUGGING! REMOVE BEFORE FLIGHT!
-        // output = [0.0; BARKISH_BANDS];
+        // output = [0.0; BANDS];
 
         AggregatedBins(output)
     }
@@ -363,4 +751,299 @@ mod tests {
         // assert_eq!(b.bands.len(), BARK_BANDS);
         // assert_eq!(b.filter2.len(), BARK_BANDS);
     }
+
+    #[test]
+    fn test_scale_edges() {
+        for scale in [
+            BandScale::Bark,
+            BandScale::Erb,
+            BandScale::Log,
+            BandScale::Mel,
+            BandScale::Octave,
+            BandScale::ThirdOctave,
+        ] {
+            for bands in [8, 20, 40] {
+                let edges: Vec<f32> = (0..=bands)
+                    .map(|i| scale.edge(i, bands, 40.0, 16_000.0))
+                    .collect();
+
+                assert!(
+                    edges.windows(2).all(|x| x[0] < x[1]),
+                    "{scale:?}: {edges:?}"
+                );
+
+                if !matches!(scale, BandScale::Octave | BandScale::ThirdOctave) {
+                    assert!((edges[0] - 40.0).abs() < 0.1, "{scale:?}: {edges:?}");
+                    assert!(
+                        (edges[bands] - 16_000.0).abs() < 2.0,
+                        "{scale:?}: {edges:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_octave_edges() {
+        // 1 kHz is a center frequency for both
+        let octave: Vec<f32> = (0..=3)
+            .map(|i| BandScale::Octave.edge(i, 3, 500.0, 0.0))
+            .collect();
+
+        assert!((octave[0] - 353.55).abs() < 0.01, "{octave:?}");
+        assert!((octave[1] - 707.11).abs() < 0.01, "{octave:?}");
+        assert!((octave[2] - 1414.21).abs() < 0.01, "{octave:?}");
+
+        let third: Vec<f32> = (0..=1)
+            .map(|i| BandScale::ThirdOctave.edge(i, 1, 1000.0, 0.0))
+            .collect();
+
+        assert!((third[0] - 890.9).abs() < 0.1, "{third:?}");
+        assert!((third[1] - 1122.5).abs() < 0.1, "{third:?}");
+    }
+
+    /// the octave scales don't stretch to fit, so too many bands used to go past nyquist and panic
+    #[test]
+    fn test_octave_banks() {
+        // 31.5 Hz to 4 kHz
+        FilterBank::<8, 8>::new_with_scale(100.0, 16_000.0, BandScale::Octave, 20.0, 20_000.0)
+            .unwrap();
+        // 31.5 Hz to 16 kHz
+        FilterBank::<10, 10>::new_with_scale(100.0, 44_100.0, BandScale::Octave, 20.0, 20_000.0)
+            .unwrap();
+        // 125 Hz to 6.3 kHz
+        FilterBank::<18, 18>::new_with_scale(
+            100.0,
+            16_000.0,
+            BandScale::ThirdOctave,
+            100.0,
+            20_000.0,
+        )
+        .unwrap();
+        // 31.5 Hz to 16 kHz
+        FilterBank::<28, 28>::new_with_scale(
+            100.0,
+            44_100.0,
+            BandScale::ThirdOctave,
+            25.0,
+            20_000.0,
+        )
+        .unwrap();
+
+        // the 16 kHz octave is past nyquist
+        let err = FilterBank::<10, 10>::new_with_scale(
+            100.0,
+            16_000.0,
+            BandScale::Octave,
+            20.0,
+            20_000.0,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(err, FilterBankError::BandTooHigh { band: 9, top_hz, .. } if top_hz == 8_000.0),
+            "{err:?}"
+        );
+
+        // max_freq counts too
+        let err = FilterBank::<28, 28>::new_with_scale(
+            100.0,
+            44_100.0,
+            BandScale::ThirdOctave,
+            25.0,
+            10_000.0,
+        )
+        .err()
+        .unwrap();
+        assert!(
+            matches!(err, FilterBankError::BandTooHigh { band: 25, top_hz, .. } if top_hz == 10_000.0),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn test_set_phon() {
+        let mut bank = BarkBank::new(100.0, 48_000.);
+
+        // band 8 is centered near 1 kHz where the contours are all about the same
+        let bass = bank.bands[0].a_coeff;
+        let mid = bank.bands[8].a_coeff;
+
+        assert!(bass < mid);
+        assert!((mid - 1.0).abs() < 0.1, "{mid}");
+
+        bank.set_phon(90.0);
+
+        assert!(bank.bands[0].a_coeff > bass);
+        assert!((bank.bands[8].a_coeff - mid).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_mel_bank_sine() {
+        const BANDS: usize = 40;
+        const SAMPLE_RATE_HZ: f32 = 48_000.0;
+        const FPS: f32 = 100.0;
+
+        let mut bank = FilterBank::<BANDS, BANDS>::new_with_scale(
+            FPS,
+            SAMPLE_RATE_HZ,
+            BandScale::Mel,
+            40.0,
+            16_000.0,
+        )
+        .unwrap();
+
+        let expected = (0..BANDS)
+            .position(|i| BandScale::Mel.edge(i + 1, BANDS, 40.0, 16_000.0) > 1_000.0)
+            .unwrap();
+
+        let frame = (SAMPLE_RATE_HZ / FPS) as usize;
+
+        let mut output = AggregatedBins::new();
+
+        for n in 0..20 {
+            let pcm: Vec<f32> = (0..frame)
+                .map(|i| {
+                    let t = (n * frame + i) as f32 / SAMPLE_RATE_HZ;
+                    0.5 * (2.0 * core::f32::consts::PI * 1_000.0 * t).sin()
+                })
+                .collect();
+
+            output = bank.push_samples(&pcm);
+        }
+
+        let loudest = output
+            .0
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+
+        assert_eq!(loudest, expected, "{:?}", output.0);
+    }
+
+    /// the q15 samples skip the float conversion, but should give the same result
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_push_samples_q15() {
+        let mut a = BarkBank::new(100.0, 48_000.);
+        let mut b = BarkBank::new(100.0, 48_000.);
+
+        for n in 0..20 {
+            let pcm: Vec<f32> = (0..480)
+                .map(|i| 0.5 * ((n * 480 + i) as f32 * 0.13).sin())
+                .collect();
+
+            let q15: Vec<i16> = pcm.iter().map(|&x| (x * 32768.0) as i16).collect();
+
+            let a = a.push_samples(&pcm);
+            let b = b.push_samples_q15(&q15);
+
+            for (a, b) in a.0.iter().zip(b.0.iter()) {
+                assert!((a - b).abs() < 1e-3, "{a} != {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bass_merge() {
+        let mut bank =
+            FilterBank::<8, 6>::new_with_scale(100.0, 48_000.0, BandScale::Log, 50.0, 8_000.0)
+                .unwrap();
+
+        let output: AggregatedBins<6> = bank.push_samples(&[0.1; 480]);
+
+        assert!(output.0.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_config_roundtrip() {
+        let config = BarkBankConfig {
+            floor_mode: FloorMode::Global { fraction: 0.5 },
+            q_boost: 2.0,
+            ..Default::default()
+        };
+
+        let mut buf = [0u8; 128];
+        let bytes = postcard::to_slice(&config, &mut buf).unwrap();
+
+        let decoded: BarkBankConfig = postcard::from_bytes(bytes).unwrap();
+
+        assert_eq!(decoded, config);
+    }
+
+    /// a steady tone fades away with per-band floors, but stays bright with a global floor
+    #[test]
+    fn test_floor_mode() {
+        let per_band = BarkBankConfig {
+            peak_init: 0.0,
+            floor_rise_s: 0.2,
+            ..Default::default()
+        };
+
+        let global = BarkBankConfig {
+            floor_mode: FloorMode::Global { fraction: 1.0 },
+            ..per_band
+        };
+
+        let mut outputs = [per_band, global].map(|config| {
+            let mut bank = BarkBank::new(100.0, 48_000.).with_config(config);
+
+            assert_eq!(bank.config(), &config);
+
+            let mut output = AggregatedBins::new();
+
+            for n in 0..100 {
+                let pcm: Vec<f32> = (0..480)
+                    .map(|i| {
+                        let t = (n * 480 + i) as f32 / 48_000.0;
+                        0.5 * (core::f32::consts::TAU * 1_000.0 * t).sin()
+                    })
+                    .collect();
+
+                output = bank.push_samples(&pcm);
+            }
+
+            output.0
+        });
+
+        let [per_band, global] = &mut outputs;
+
+        let tone = global
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+
+        assert!(global[tone] > 0.9, "{global:?}");
+        assert!(per_band[tone] < 0.05, "{per_band:?}");
+    }
+
+    #[test]
+    fn test_set_config_keeps_levels() {
+        let mut bank = BarkBank::new(100.0, 48_000.);
+
+        bank.push_samples(&[0.1; 480]);
+
+        let peaks: Vec<f32> = bank.bands.iter().map(|x| x.peak_env.value).collect();
+
+        bank.set_config(BarkBankConfig {
+            q_boost: 1.5,
+            phon: 80.0,
+            ..Default::default()
+        });
+
+        let after: Vec<f32> = bank.bands.iter().map(|x| x.peak_env.value).collect();
+        assert_eq!(peaks, after);
+
+        // set_phon and the config agree
+        assert_eq!(bank.config().phon, 80.0);
+        bank.set_phon(70.0);
+        assert_eq!(bank.config().phon, 70.0);
+
+        bank.reset();
+        assert!(bank.bands.iter().all(|x| x.peak_env.value == 2.0));
+    }
 }
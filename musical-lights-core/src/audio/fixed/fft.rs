@@ -0,0 +1,281 @@
+use core::marker::PhantomData;
+
+use num::Complex;
+
+use super::{Q15, Q15Window, q15_from_f32, q15_mul, saturate_q15};
+use crate::windows::{Window, const_cos};
+
+/// A real FFT on Q15 samples.
+///
+/// The even samples go in the real part and the odd samples go in the imaginary part of an FFT that is half the size.
+/// Then the two halves get split back apart. This is the same trick that microfft and CMSIS use.
+///
+/// Every stage divides by 2 so that nothing can overflow. The outputs are scaled by `1 / FFT_IN`, so a full scale sine
+/// that is centered on a bin comes out at about `0.5 * coherent gain`.
+///
+/// TODO: a buffered version like [`BufferedFFT`](crate::audio::BufferedFFT)? the M0 probably doesn't have the RAM for overlap
+pub struct FixedFFT<const FFT_IN: usize, const FFT_OUT: usize, WI: Window<FFT_IN>> {
+    buf: [Complex<Q15>; FFT_OUT],
+    input_window: PhantomData<WI>,
+}
+
+impl<const FFT_IN: usize, const FFT_OUT: usize, WI: Window<FFT_IN>> FixedFFT<FFT_IN, FFT_OUT, WI> {
+    /// W_N^k = e^(-2πik/N) for the first half of the circle
+    const TWIDDLES: &'static [Complex<Q15>; FFT_OUT] = &twiddles::<FFT_IN, FFT_OUT>();
+
+    pub const fn new() -> Self {
+        assert!(FFT_IN.is_power_of_two());
+        assert!(FFT_IN >= 4);
+        assert!(FFT_IN / 2 == FFT_OUT);
+
+        Self {
+            buf: [Complex::new(0, 0); FFT_OUT],
+            input_window: PhantomData,
+        }
+    }
+
+    /// window the samples and then FFT them. bin 0 is the DC offset. the nyquist bin is dropped
+    pub fn fft(&mut self, samples: &[Q15; FFT_IN]) -> &[Complex<Q15>; FFT_OUT] {
+        let window = WI::WINDOW_Q15;
+
+        for (i, x) in self.buf.iter_mut().enumerate() {
+            *x = Complex::new(
+                q15_mul(samples[2 * i], window[2 * i]),
+                q15_mul(samples[2 * i + 1], window[2 * i + 1]),
+            );
+        }
+
+        self.complex_fft();
+
+        self.split();
+
+        &self.buf
+    }
+
+    /// radix-2 decimation in time. in place
+    fn complex_fft(&mut self) {
+        let n = FFT_OUT;
+
+        // bit reversed order
+        let shift = usize::BITS - n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> shift;
+
+            if j > i {
+                self.buf.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let half = len / 2;
+
+            // W_len^j is W_FFT_IN^(j * FFT_IN / len)
+            let step = FFT_IN / len;
+
+            for start in (0..n).step_by(len) {
+                for j in 0..half {
+                    let a = self.buf[start + j];
+                    let t = mul(widen(self.buf[start + j + half]), Self::TWIDDLES[j * step]);
+
+                    self.buf[start + j] = Complex::new(
+                        saturate_q15((a.re as i32 + t.re) >> 1),
+                        saturate_q15((a.im as i32 + t.im) >> 1),
+                    );
+                    self.buf[start + j + half] = Complex::new(
+                        saturate_q15((a.re as i32 - t.re) >> 1),
+                        saturate_q15((a.im as i32 - t.im) >> 1),
+                    );
+                }
+            }
+
+            len *= 2;
+        }
+    }
+
+    /// turn the half size complex FFT back into the first half of the real FFT
+    ///
+    /// X[k] = E[k] + W^k O[k] where E and O are the FFTs of the even and odd samples
+    fn split(&mut self) {
+        let n = FFT_OUT;
+
+        for k in 0..=n / 2 {
+            let a = widen(self.buf[k]);
+            // Z[N] wraps around to Z[0]. conj(Z[N - k]). this is in i32 so negating -32768 can't overflow
+            let b = widen(self.buf[(n - k) % n]).conj();
+
+            // these are shifted by 2 instead of 1 to get the total scaling to 1 / FFT_IN
+            let e = Complex::new((a.re + b.re) >> 2, (a.im + b.im) >> 2);
+            let d = Complex::new((a.re - b.re) >> 2, (a.im - b.im) >> 2);
+
+            // O = (A - B) / 2i
+            let o = Complex::new(d.im, -d.re);
+
+            let wo = mul(o, Self::TWIDDLES[k]);
+
+            if k == 0 {
+                // the imaginary part of Z[0] is the nyquist bin. drop it. TODO: pack it into bin 0 like microfft does?
+                self.buf[0] = Complex::new(saturate_q15(e.re + wo.re), 0);
+                continue;
+            }
+
+            if k != n - k {
+                // X[N - k] = conj(E) + W^(N - k) conj(O)
+                let wo = mul(o.conj(), Self::TWIDDLES[n - k]);
+
+                self.buf[n - k] =
+                    Complex::new(saturate_q15(e.re + wo.re), saturate_q15(-e.im + wo.im));
+            }
+
+            self.buf[k] = Complex::new(saturate_q15(e.re + wo.re), saturate_q15(e.im + wo.im));
+        }
+    }
+}
+
+impl<const FFT_IN: usize, const FFT_OUT: usize, WI: Window<FFT_IN>> Default
+    for FixedFFT<FFT_IN, FFT_OUT, WI>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline(always)]
+fn widen(x: Complex<Q15>) -> Complex<i32> {
+    Complex::new(x.re as i32, x.im as i32)
+}
+
+/// complex Q15 multiply. the result is left in i32 (still Q15) so the butterfly can add before saturating
+///
+/// `b` is always a twiddle (|b| <= 1), so this can't overflow as long as `a` is within ±32768
+#[inline(always)]
+fn mul(a: Complex<i32>, b: Complex<Q15>) -> Complex<i32> {
+    let (ar, ai, br, bi) = (a.re, a.im, b.re as i32, b.im as i32);
+
+    Complex::new(
+        (ar * br - ai * bi + (1 << 14)) >> 15,
+        (ar * bi + ai * br + (1 << 14)) >> 15,
+    )
+}
+
+/// re² + im². this is [`FftOutputs::iter_power`](crate::audio::FftOutputs::iter_power) for Q15 (the result is Q30)
+#[inline]
+pub fn power(x: Complex<Q15>) -> u32 {
+    (x.re as i32 * x.re as i32) as u32 + (x.im as i32 * x.im as i32) as u32
+}
+
+/// Sum the power of the bins into bands. `map` can come from any [`AggregatedBinsBuilder::bin_map`](crate::audio::AggregatedBinsBuilder::bin_map)
+///
+/// This is the only integer part of the aggregation. Convert the (few) outputs to decibels with floats once per frame
+pub fn sum_power_into<const IN: usize, const OUT: usize>(
+    map: &[Option<usize>; IN],
+    spectrum: &[Complex<Q15>; IN],
+    output: &mut [u64; OUT],
+) {
+    output.fill(0);
+
+    for (&x, b) in spectrum.iter().zip(map.iter()) {
+        if let Some(b) = b {
+            output[*b] += power(x) as u64;
+        }
+    }
+}
+
+const fn twiddles<const FFT_IN: usize, const FFT_OUT: usize>() -> [Complex<Q15>; FFT_OUT] {
+    let mut twiddles = [Complex::new(0, 0); FFT_OUT];
+
+    let mut k = 0;
+    while k < FFT_OUT {
+        let theta = core::f64::consts::TAU * k as f64 / FFT_IN as f64;
+
+        // sin(x) = cos(π/2 - x)
+        twiddles[k] = Complex::new(
+            q15_from_f32(const_cos(theta) as f32),
+            q15_from_f32(-const_cos(core::f64::consts::FRAC_PI_2 - theta) as f32),
+        );
+
+        k += 1;
+    }
+
+    twiddles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::{FlatWindow, HanningWindow};
+
+    const N: usize = 256;
+
+    fn naive_dft(x: &[f64; N]) -> Vec<(f64, f64)> {
+        (0..N / 2)
+            .map(|k| {
+                let mut re = 0.0;
+                let mut im = 0.0;
+
+                for (n, x) in x.iter().enumerate() {
+                    let theta = core::f64::consts::TAU * (k * n) as f64 / N as f64;
+                    re += x * theta.cos();
+                    im -= x * theta.sin();
+                }
+
+                (re / N as f64, im / N as f64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_dft() {
+        let signal: [f64; N] = core::array::from_fn(|i| {
+            let t = i as f64 / N as f64 * core::f64::consts::TAU;
+
+            0.1 + 0.4 * (5.0 * t).sin() + 0.2 * (17.3 * t).cos() + 0.1 * (100.0 * t + 1.0).sin()
+        });
+
+        let samples = signal.map(|x| q15_from_f32(x as f32));
+
+        let mut fft = FixedFFT::<N, { N / 2 }, FlatWindow<N>>::new();
+
+        let spectrum = fft.fft(&samples);
+
+        let expected = naive_dft(&signal);
+
+        for (k, (x, (re, im))) in spectrum.iter().zip(expected).enumerate() {
+            let re_err = (x.re as f64 / 32768.0 - re).abs();
+            let im_err = (x.im as f64 / 32768.0 - im).abs();
+
+            // a few lsb of rounding per stage
+            assert!(re_err < 5e-4 && im_err < 5e-4, "{k}: {x} != {re} + {im}i");
+        }
+    }
+
+    #[test]
+    fn test_sine_on_bin() {
+        let bin = 48;
+
+        let samples: [Q15; N] = core::array::from_fn(|i| {
+            q15_from_f32(0.5 * (core::f32::consts::TAU * (bin * i) as f32 / N as f32).sin())
+        });
+
+        let mut fft = FixedFFT::<N, { N / 2 }, HanningWindow<N>>::new();
+
+        let spectrum = fft.fft(&samples);
+
+        // 0.5 amplitude * 0.5 for the real fft * 0.5 for the hann coherent gain
+        let peak = power(spectrum[bin]) as f32 / (1u64 << 30) as f32;
+        assert!((peak.sqrt() - 0.125).abs() < 1e-3, "{peak}");
+
+        // the neighbors are half as tall
+        let neighbor = power(spectrum[bin + 1]) as f32 / (1u64 << 30) as f32;
+        assert!((neighbor.sqrt() - 0.0625).abs() < 1e-3, "{neighbor}");
+
+        let map: [Option<usize>; N / 2] = core::array::from_fn(|i| Some(i / 32));
+        let mut bands = [0; 4];
+
+        sum_power_into(&map, spectrum, &mut bands);
+
+        assert!(bands[1] > bands[0] * 1000);
+        assert!(bands[1] > bands[2] * 1000);
+        assert!(bands[1] > bands[3] * 1000);
+    }
+}
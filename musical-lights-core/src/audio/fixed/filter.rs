@@ -0,0 +1,133 @@
+use biquad::Coefficients;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// coefficients are Q2.29. band-pass filters have a1 close to -2, so we need 2 integer bits
+const COEFFICIENT_SHIFT: u32 = 29;
+
+/// A biquad on Q30 samples (Q31 with 1 bit of headroom). Convert Q15 with `(x as i32) << 15`.
+///
+/// Direct form 1 so that the state is the same format as the samples. The math is done with an i64 accumulator. The M0
+/// doesn't have a 64-bit multiply, but the software one is still way faster than soft floats.
+///
+/// TODO: implement [`biquad::Biquad`]? `Coefficients<i32>` would be confusing
+#[derive(Clone, Copy, Debug)]
+pub struct FixedBiquad {
+    b0: i32,
+    b1: i32,
+    b2: i32,
+    a1: i32,
+    a2: i32,
+    x1: i32,
+    x2: i32,
+    y1: i32,
+    y2: i32,
+}
+
+impl FixedBiquad {
+    /// the coefficients must all be between -4.0 and 4.0. they are for any band-pass or low/high-pass filter
+    pub fn new(coefficients: Coefficients<f32>) -> Self {
+        let Coefficients { a1, a2, b0, b1, b2 } = coefficients;
+
+        Self {
+            b0: to_coefficient(b0),
+            b1: to_coefficient(b1),
+            b2: to_coefficient(b2),
+            a1: to_coefficient(a1),
+            a2: to_coefficient(a2),
+            x1: 0,
+            x2: 0,
+            y1: 0,
+            y2: 0,
+        }
+    }
+
+    #[inline]
+    pub fn run(&mut self, x: i32) -> i32 {
+        let acc = self.b0 as i64 * x as i64
+            + self.b1 as i64 * self.x1 as i64
+            + self.b2 as i64 * self.x2 as i64
+            - self.a1 as i64 * self.y1 as i64
+            - self.a2 as i64 * self.y2 as i64;
+
+        // round and then back to Q30
+        let y = ((acc + (1 << (COEFFICIENT_SHIFT - 1))) >> COEFFICIENT_SHIFT)
+            .clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+
+        y
+    }
+
+    pub fn reset_state(&mut self) {
+        self.x1 = 0;
+        self.x2 = 0;
+        self.y1 = 0;
+        self.y2 = 0;
+    }
+}
+
+fn to_coefficient(x: f32) -> i32 {
+    assert!(x.abs() < 4.0, "coefficient out of range: {x}");
+
+    let x = x as f64 * (1 << COEFFICIENT_SHIFT) as f64;
+
+    // round half away from zero. f64::round needs std
+    (if x >= 0.0 { x + 0.5 } else { x - 0.5 }) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use biquad::{Biquad, DirectForm2Transposed, ToHertz, Type};
+
+    use super::*;
+
+    /// the fixed point filter should track the f32 filter closely. even for a narrow filter in the bass
+    #[test]
+    fn test_matches_f32() {
+        let sample_rate_hz = 48_000.0;
+
+        for (fc, q) in [(60.0, 3.0), (1_000.0, 3.0), (10_000.0, 1.0)] {
+            let c =
+                Coefficients::<f32>::from_params(Type::BandPass, sample_rate_hz.hz(), fc.hz(), q)
+                    .unwrap();
+
+            let mut float = DirectForm2Transposed::<f32>::new(c);
+            let mut fixed = FixedBiquad::new(c);
+
+            for i in 0..4800 {
+                let t = i as f32 / sample_rate_hz;
+
+                // the center frequency and something off to the side
+                let x = 0.4 * (core::f32::consts::TAU * fc * t).sin()
+                    + 0.4 * (core::f32::consts::TAU * fc * 3.7 * t).sin();
+
+                let expected = float.run(x);
+                let actual = fixed.run((x * (1 << 30) as f32) as i32) as f32 / (1 << 30) as f32;
+
+                assert!(
+                    (actual - expected).abs() < 1e-3,
+                    "{fc} Hz sample {i}: {actual} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let c = Coefficients::<f32>::from_params(Type::LowPass, 48_000.0.hz(), 100.0.hz(), 0.7)
+            .unwrap();
+
+        let mut fixed = FixedBiquad::new(c);
+
+        fixed.run(1 << 29);
+        assert_ne!(fixed.run(0), 0);
+
+        fixed.reset_state();
+        assert_eq!(fixed.run(0), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Fixed-point audio for chips without an FPU.
+//!
+//! The feather's Cortex-M0 does every f32 operation in software. That is way too slow for a biquad per band per sample
+//! or an FFT. These do the per-sample work with integers and only switch to floats once per frame (24 values instead
+//! of thousands).
+//!
+//! Enable the `fixed-point` feature to use these. It also switches the [`FilterBank`](super::FilterBank) biquads to
+//! [`FixedBiquad`].
+//!
+//! Samples -> Q15 -> Q15Window -> FixedFFT -> power (u32) -> any bin map (Bark, Shazam, etc.)
+//! Samples -> Q15 -> FilterBank (FixedBiquad) -> AggregatedBins
+//!
+//! TODO: the ESP32 has an FPU and doesn't need any of this. but it might still be faster there. benchmark it
+mod fft;
+mod filter;
+mod window;
+
+pub use fft::{FixedFFT, sum_power_into};
+pub use filter::FixedBiquad;
+pub use window::Q15Window;
+
+/// 1 sign bit and 15 fractional bits. -1.0..1.0 in an i16
+pub type Q15 = i16;
+
+/// 1 sign bit and 31 fractional bits. -1.0..1.0 in an i32
+pub type Q31 = i32;
+
+/// The closest Q15 to `x`. Anything outside -1.0..1.0 saturates
+///
+/// This is for building tables. Try not to call it per-sample on the M0!
+pub const fn q15_from_f32(x: f32) -> Q15 {
+    let x = x * 32768.0;
+
+    // round half away from zero. `as` truncates, saturates, and turns NaN into 0
+    let x = if x >= 0.0 { x + 0.5 } else { x - 0.5 };
+
+    saturate_q15(x as i32)
+}
+
+pub const fn q15_to_f32(x: Q15) -> f32 {
+    x as f32 / 32768.0
+}
+
+/// The closest Q31 to `x`. Anything outside -1.0..1.0 saturates
+pub const fn q31_from_f32(x: f32) -> Q31 {
+    (x as f64 * 2_147_483_648.0) as Q31
+}
+
+pub const fn q31_to_f32(x: Q31) -> f32 {
+    (x as f64 / 2_147_483_648.0) as f32
+}
+
+/// drop the bottom 16 bits. use this to feed 24-bit samples into the Q15 FFT
+#[inline(always)]
+pub const fn q31_to_q15(x: Q31) -> Q15 {
+    (x >> 16) as Q15
+}
+
+/// Q15 * Q15 with rounding. -1.0 * -1.0 saturates to just under 1.0
+#[inline(always)]
+pub const fn q15_mul(a: Q15, b: Q15) -> Q15 {
+    saturate_q15((a as i32 * b as i32 + (1 << 14)) >> 15)
+}
+
+#[inline(always)]
+pub const fn saturate_q15(x: i32) -> Q15 {
+    if x > Q15::MAX as i32 {
+        Q15::MAX
+    } else if x < Q15::MIN as i32 {
+        Q15::MIN
+    } else {
+        x as Q15
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(q15_from_f32(0.0), 0);
+        assert_eq!(q15_from_f32(0.5), 16384);
+        assert_eq!(q15_from_f32(-0.5), -16384);
+        assert_eq!(q15_from_f32(-1.0), Q15::MIN);
+        // 1.0 doesn't fit
+        assert_eq!(q15_from_f32(1.0), Q15::MAX);
+        assert_eq!(q15_from_f32(f32::NAN), 0);
+
+        assert_eq!(q15_to_f32(16384), 0.5);
+        assert_eq!(q15_to_f32(Q15::MIN), -1.0);
+
+        assert_eq!(q31_from_f32(0.25), 1 << 29);
+        assert_eq!(q31_from_f32(2.0), Q31::MAX);
+        assert_eq!(q31_to_f32(-(1 << 30)), -0.5);
+        assert_eq!(q31_to_q15(q31_from_f32(-0.5)), -16384);
+    }
+
+    #[test]
+    fn test_q15_mul() {
+        assert_eq!(q15_mul(16384, 16384), 8192);
+        assert_eq!(q15_mul(-16384, 16384), -8192);
+        assert_eq!(q15_mul(Q15::MAX, 1000), 1000);
+        assert_eq!(q15_mul(Q15::MIN, Q15::MIN), Q15::MAX);
+    }
+}
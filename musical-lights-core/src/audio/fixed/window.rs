@@ -0,0 +1,69 @@
+use super::{Q15, q15_from_f32, q15_mul};
+use crate::windows::Window;
+
+/// Every [`Window`] as a Q15 table. This is built at compile time from [`Window::WINDOW`]
+pub trait Q15Window<const N: usize>: Window<N> {
+    const WINDOW_Q15: &'static [Q15; N];
+
+    /// [`Window::OUTPUT_SCALING`] as a Q15 multiplier would overflow, so this is the coherent gain in Q15 instead.
+    /// divide by it (or just ignore it if you only care about relative levels)
+    const COHERENT_GAIN_Q15: Q15;
+
+    #[inline]
+    fn apply_windows_q15(x: &mut [Q15; N]) {
+        for (sample, multiplier) in x.iter_mut().zip(Self::WINDOW_Q15.iter()) {
+            *sample = q15_mul(*sample, *multiplier);
+        }
+    }
+}
+
+impl<const N: usize, W: Window<N>> Q15Window<N> for W {
+    const WINDOW_Q15: &'static [Q15; N] = &q15_table(W::WINDOW);
+
+    const COHERENT_GAIN_Q15: Q15 = {
+        let mut sum = 0.0;
+
+        let mut i = 0;
+        while i < N {
+            sum += W::WINDOW[i] as f64;
+            i += 1;
+        }
+
+        q15_from_f32((sum / N as f64) as f32)
+    };
+}
+
+const fn q15_table<const N: usize>(window: &[f32; N]) -> [Q15; N] {
+    let mut table = [0; N];
+
+    let mut i = 0;
+    while i < N {
+        table[i] = q15_from_f32(window[i]);
+        i += 1;
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::{FlatWindow, HanningWindow};
+
+    #[test]
+    fn test_q15_windows() {
+        for (&q, &f) in HanningWindow::<256>::WINDOW_Q15
+            .iter()
+            .zip(HanningWindow::<256>::WINDOW.iter())
+        {
+            assert!((q as f32 / 32768.0 - f).abs() <= 1.0 / 32768.0, "{q} {f}");
+        }
+
+        assert_eq!(HanningWindow::<256>::COHERENT_GAIN_Q15, 16384);
+        assert_eq!(FlatWindow::<4>::WINDOW_Q15, &[Q15::MAX; 4]);
+
+        let mut x = [16384; 4];
+        FlatWindow::<4>::apply_windows_q15(&mut x);
+        assert_eq!(x, [16384; 4]);
+    }
+}
@@ -1,61 +1,470 @@
-use i24::i24 as I24;
-
-use crate::remap;
-
-const I24_MAX: f32 = I24::MAX.to_i32() as f32;
-
-/// TODO: better name. this is for 24-bit audio!
-/// TODO: is there a more efficient way to do this?
-/// TODO: this needs some tests!
-pub fn parse_i2s_24_bit_mono_to_f32_array<const IN: usize, const OUT: usize>(
-    input: &[u8; IN],
-    output: &mut [f32; OUT],
-) {
-    // TODO: debug assert? compile time assert? should this be size_of i32 or f32?
-    assert_eq!(IN, OUT * size_of::<i32>());
-
-    // TODO: should chunk size be 4 or 8? i'm not sure how mono works
-    for (chunk, x) in input.chunks_exact(4).zip(output.iter_mut()) {
-        // chunk[0] is always empty. TODO: i thought chunk3 was the one that would always be empty, but i guess not?
-        debug_assert!(chunk[0] == 0);
-
-        // TODO: be or le?
-        *x = I24::from_le_bytes([chunk[1], chunk[2], chunk[3]]).to_i32() as f32 / I24_MAX;
-
-        debug_assert!(*x >= -1.0);
-        debug_assert!(*x <= 1.0);
-    }
-}
-
-/// TODO: this needs some tests!
-/// I don't really want to turn everything into f32s, but thats what microfft wants. maybe we should find a library that can work on i16 and i24
-pub fn parse_i2s_16_bit_mono_to_f32_array<const IN: usize, const OUT: usize>(
-    input: &[u8; IN],
-    output: &mut [f32; OUT],
-) {
-    // TODO: debug assert? compile time assert?
-    assert_eq!(IN, OUT * size_of::<i16>());
-
-    // TODO: should chunk size be 2 or 4? i'm not sure how mono works
-    for (chunk, x) in input.chunks_exact(2).zip(output.iter_mut()) {
-        // TODO: is there an off-by-one error here? does a or b need to be moved by 1?
-        *x = remap(
-            // TODO: be or le?
-            i16::from_le_bytes([chunk[0], chunk[1]]) as f32,
-            i16::MIN as f32,
-            i16::MAX as f32,
-            -1.0,
-            1.0,
-        );
+//! Turn the bytes from an I2S peripheral's DMA buffer into samples.
+//!
+//! Every mic and every chip lays these out a little differently, so describe the layout with an [`I2sFormat`] instead of
+//! picking one of a bunch of functions.
+use thiserror::Error;
+
+use crate::audio::StereoSamples;
+#[cfg(feature = "fixed-point")]
+use crate::audio::fixed::{Q15, Q31};
+
+/// how many bits. used for both the samples and the slots that they are sent in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BitDepth {
+    Bits16,
+    Bits24,
+    Bits32,
+}
 
-        debug_assert!(*x >= -1.0);
-        debug_assert!(*x <= 1.0);
+impl BitDepth {
+    pub const fn bits(&self) -> u32 {
+        match self {
+            Self::Bits16 => 16,
+            Self::Bits24 => 24,
+            Self::Bits32 => 32,
+        }
     }
+
+    pub const fn bytes(&self) -> usize {
+        self.bits() as usize / 8
+    }
+}
+
+/// Where the sample sits inside of its slot.
+///
+/// The peripheral handles the one bit delay of Philips framing, so by the time the bytes are in memory Philips and
+/// left-justified look the same. They are both here so that the config can say what the mic's datasheet says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Framing {
+    /// the sample is in the most significant bits of the slot. the padding is in the low bits (LSB padded)
+    Philips,
+    /// same layout as [`Framing::Philips`]
+    LeftJustified,
+    /// the sample is in the least significant bits of the slot. the padding is in the high bits (MSB padded)
+    RightJustified,
+}
+
+/// the byte order of each slot in the buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2sError {
+    #[error("expected {expected} bytes of input, got {actual}")]
+    InputLength { expected: usize, actual: usize },
+    #[error("{sample_bits}-bit samples don't fit in {slot_bits}-bit slots")]
+    SampleTooWide { sample_bits: u32, slot_bits: u32 },
+}
+
+/// How the samples are laid out in the bytes that come out of the I2S peripheral.
+///
+/// Stereo buffers are interleaved with the left slot first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct I2sFormat {
+    /// how many bits of audio are in each sample
+    pub sample_bits: BitDepth,
+    /// how many bits each sample takes up in the buffer. at least as many as `sample_bits`
+    pub slot_bits: BitDepth,
+    pub framing: Framing,
+    pub endian: Endian,
+}
+
+impl I2sFormat {
+    /// 16-bit samples in 16-bit slots. this is what the ESP32 gives with `DataBitWidth::Bits16`
+    pub const PHILIPS_16_BIT: Self = Self::new(BitDepth::Bits16, BitDepth::Bits16);
+
+    /// 24-bit samples in the top of 32-bit slots. This is what the SPH0645 and INMP441 send. The low byte is always empty
+    pub const PHILIPS_24_BIT: Self = Self::new(BitDepth::Bits24, BitDepth::Bits32);
+
+    /// Philips framing and little endian. Change them with [`Self::with_framing`] and [`Self::with_endian`]
+    pub const fn new(sample_bits: BitDepth, slot_bits: BitDepth) -> Self {
+        Self {
+            sample_bits,
+            slot_bits,
+            framing: Framing::Philips,
+            endian: Endian::Little,
+        }
+    }
+
+    pub const fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    pub const fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// bytes per sample per channel
+    pub const fn slot_bytes(&self) -> usize {
+        self.slot_bits.bytes()
+    }
+
+    pub const fn validate(&self) -> Result<(), I2sError> {
+        if self.sample_bits.bits() > self.slot_bits.bits() {
+            return Err(I2sError::SampleTooWide {
+                sample_bits: self.sample_bits.bits(),
+                slot_bits: self.slot_bits.bits(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// One slot as a full scale i32 (Q31). The sample ends up in the high bits and the padding bits are cleared.
+    ///
+    /// `slot` must be [`Self::slot_bytes`] long.
+    #[inline]
+    pub fn read_slot(&self, slot: &[u8]) -> i32 {
+        debug_assert_eq!(slot.len(), self.slot_bytes());
+
+        let mut word = 0u32;
+        match self.endian {
+            Endian::Little => {
+                for &b in slot.iter().rev() {
+                    word = (word << 8) | b as u32;
+                }
+            }
+            Endian::Big => {
+                for &b in slot.iter() {
+                    word = (word << 8) | b as u32;
+                }
+            }
+        }
+
+        let slot_bits = self.slot_bits.bits();
+        let sample_bits = self.sample_bits.bits();
+
+        // move the slot to the top of the word
+        let word = word << (32 - slot_bits);
+
+        let word = match self.framing {
+            Framing::Philips | Framing::LeftJustified => word,
+            // shift out the padding. it might be zeros or it might be sign extension. we don't care which
+            Framing::RightJustified => word << (slot_bits - sample_bits),
+        };
+
+        // some mics put junk after the sample
+        (word & (u32::MAX << (32 - sample_bits))) as i32
+    }
+
+    /// one sample per slot
+    pub fn parse_mono_to_f32(&self, input: &[u8], output: &mut [f32]) -> Result<(), I2sError> {
+        self.parse_mono_with(input, output, q31_to_f32)
+    }
+
+    /// interleaved left and right slots
+    pub fn parse_stereo_to_f32<const S: usize>(
+        &self,
+        input: &[u8],
+        output: &mut StereoSamples<S>,
+    ) -> Result<(), I2sError> {
+        self.validate()?;
+
+        let slot_bytes = self.slot_bytes();
+
+        check_len(input, S * 2 * slot_bytes)?;
+
+        for ((frame, l), r) in input
+            .chunks_exact(2 * slot_bytes)
+            .zip(output.left.0.iter_mut())
+            .zip(output.right.0.iter_mut())
+        {
+            let (left, right) = frame.split_at(slot_bytes);
+
+            *l = q31_to_f32(self.read_slot(left));
+            *r = q31_to_f32(self.read_slot(right));
+        }
+
+        Ok(())
+    }
+
+    /// [`Self::parse_mono_to_f32`] without any floats. The sample is in the top of the i32, so this is Q31 for any bit depth
+    #[cfg(feature = "fixed-point")]
+    pub fn parse_mono_to_q31(&self, input: &[u8], output: &mut [Q31]) -> Result<(), I2sError> {
+        self.parse_mono_with(input, output, |x| x)
+    }
+
+    /// [`Self::parse_mono_to_f32`] without any floats. Anything past 16 bits is dropped
+    #[cfg(feature = "fixed-point")]
+    pub fn parse_mono_to_q15(&self, input: &[u8], output: &mut [Q15]) -> Result<(), I2sError> {
+        self.parse_mono_with(input, output, crate::audio::fixed::q31_to_q15)
+    }
+
+    fn parse_mono_with<T>(
+        &self,
+        input: &[u8],
+        output: &mut [T],
+        f: impl Fn(i32) -> T,
+    ) -> Result<(), I2sError> {
+        self.validate()?;
+
+        let slot_bytes = self.slot_bytes();
+
+        check_len(input, output.len() * slot_bytes)?;
+
+        for (slot, x) in input.chunks_exact(slot_bytes).zip(output.iter_mut()) {
+            *x = f(self.read_slot(slot));
+        }
+
+        Ok(())
+    }
+}
+
+fn check_len(input: &[u8], expected: usize) -> Result<(), I2sError> {
+    if input.len() != expected {
+        return Err(I2sError::InputLength {
+            expected,
+            actual: input.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// -1.0..=1.0
+#[inline(always)]
+fn q31_to_f32(x: i32) -> f32 {
+    x as f32 / 2_147_483_648.0
 }
 
 #[cfg(test)]
 mod tests {
-    // TODO: test for 24-bit audio
-    // TODO: test for 16-bit audio
-    // TODO: test for 8-bit audio?
+    use super::*;
+    use crate::audio::{Samples, Stereo};
+
+    const ALL_BITS: [BitDepth; 3] = [BitDepth::Bits16, BitDepth::Bits24, BitDepth::Bits32];
+    const ALL_FRAMING: [Framing; 3] = [
+        Framing::Philips,
+        Framing::LeftJustified,
+        Framing::RightJustified,
+    ];
+    const ALL_ENDIAN: [Endian; 2] = [Endian::Little, Endian::Big];
+
+    /// the opposite of `read_slot`. `padding` fills the bits that aren't part of the sample
+    fn write_slot(format: &I2sFormat, sample: i32, padding: u32) -> Vec<u8> {
+        let slot_bits = format.slot_bits.bits();
+        let sample_bits = format.sample_bits.bits();
+        let pad_bits = slot_bits - sample_bits;
+
+        // the sample bits, right aligned. u64 so that shifting by 32 is fine
+        let sample = (sample as u32 >> (32 - sample_bits)) as u64;
+        let padding = padding as u64 & ((1u64 << pad_bits) - 1);
+
+        let word = match format.framing {
+            Framing::Philips | Framing::LeftJustified => (sample << pad_bits) | padding,
+            Framing::RightJustified => (padding << sample_bits) | sample,
+        } as u32;
+
+        let bytes = word.to_be_bytes();
+        let mut slot = bytes[(4 - format.slot_bytes())..].to_vec();
+
+        if format.endian == Endian::Little {
+            slot.reverse();
+        }
+
+        slot
+    }
+
+    fn all_formats() -> impl Iterator<Item = I2sFormat> {
+        ALL_BITS.into_iter().flat_map(|sample_bits| {
+            ALL_BITS.into_iter().flat_map(move |slot_bits| {
+                ALL_FRAMING.into_iter().flat_map(move |framing| {
+                    ALL_ENDIAN.into_iter().map(move |endian| {
+                        I2sFormat::new(sample_bits, slot_bits)
+                            .with_framing(framing)
+                            .with_endian(endian)
+                    })
+                })
+            })
+        })
+    }
+
+    /// full scale values that every bit depth can represent exactly
+    const SAMPLES: [i32; 6] = [
+        0,
+        i32::MIN,
+        0x4000_0000,
+        -0x4000_0000,
+        0x7FFF_0000,
+        -0x0001_0000,
+    ];
+
+    #[test]
+    fn test_every_format() {
+        let mut tested = 0;
+
+        for format in all_formats() {
+            if format.validate().is_err() {
+                assert!(format.sample_bits.bits() > format.slot_bits.bits());
+                continue;
+            }
+
+            tested += 1;
+
+            // junk in the padding must be ignored
+            for padding in [0, u32::MAX, 0xA5A5_A5A5] {
+                let input: Vec<u8> = SAMPLES
+                    .iter()
+                    .flat_map(|&x| write_slot(&format, x, padding))
+                    .collect();
+
+                let mut output = [0.0; SAMPLES.len()];
+                format.parse_mono_to_f32(&input, &mut output).unwrap();
+
+                for (&expected, actual) in SAMPLES.iter().zip(output) {
+                    assert_eq!(
+                        actual,
+                        expected as f32 / 2_147_483_648.0,
+                        "{format:?} padding={padding:#x}"
+                    );
+                }
+            }
+        }
+
+        // 6 valid (sample, slot) pairs * 3 framings * 2 endians
+        assert_eq!(tested, 36);
+    }
+
+    #[test]
+    fn test_known_bytes() {
+        // the old 24-bit parser: little endian with the empty byte first
+        let input = [0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0xC0];
+        let mut output = [0.0; 2];
+        I2sFormat::PHILIPS_24_BIT
+            .parse_mono_to_f32(&input, &mut output)
+            .unwrap();
+        assert_eq!(output, [0.5, -0.5]);
+
+        let input = [0x00, 0x40, 0x00, 0xC0, 0xFF, 0x7F, 0x00, 0x80];
+        let mut output = [0.0; 4];
+        I2sFormat::PHILIPS_16_BIT
+            .parse_mono_to_f32(&input, &mut output)
+            .unwrap();
+        assert_eq!(output, [0.5, -0.5, 32767.0 / 32768.0, -1.0]);
+
+        // the same 16-bit samples as big endian
+        let input = [0x40, 0x00, 0xC0, 0x00];
+        let mut output = [0.0; 2];
+        I2sFormat::PHILIPS_16_BIT
+            .with_endian(Endian::Big)
+            .parse_mono_to_f32(&input, &mut output)
+            .unwrap();
+        assert_eq!(output, [0.5, -0.5]);
+
+        // 24-bit right justified in 32-bit slots with sign extension in the top byte
+        let input = [0x00, 0x00, 0xC0, 0xFF, 0x00, 0x00, 0x40, 0x00];
+        let mut output = [0.0; 2];
+        I2sFormat::PHILIPS_24_BIT
+            .with_framing(Framing::RightJustified)
+            .parse_mono_to_f32(&input, &mut output)
+            .unwrap();
+        assert_eq!(output, [-0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_stereo() {
+        // left is half scale. right is negative half scale
+        let input = [0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0xC0].repeat(2);
+
+        let mut stereo = Stereo::new(Samples([0.0; 2]), Samples([0.0; 2]));
+        I2sFormat::PHILIPS_24_BIT
+            .parse_stereo_to_f32(&input, &mut stereo)
+            .unwrap();
+
+        assert_eq!(stereo.left.0, [0.5; 2]);
+        assert_eq!(stereo.right.0, [-0.5; 2]);
+
+        let input = [0xFF, 0x7F, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00];
+
+        let mut stereo = Stereo::new(Samples([0.0; 2]), Samples([0.0; 2]));
+        I2sFormat::PHILIPS_16_BIT
+            .parse_stereo_to_f32(&input, &mut stereo)
+            .unwrap();
+
+        assert_eq!(stereo.left.0, [32767.0 / 32768.0, 0.0]);
+        assert_eq!(stereo.right.0, [-1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut output = [0.0; 4];
+
+        assert_eq!(
+            I2sFormat::PHILIPS_24_BIT.parse_mono_to_f32(&[0; 15], &mut output),
+            Err(I2sError::InputLength {
+                expected: 16,
+                actual: 15
+            })
+        );
+
+        assert_eq!(
+            I2sFormat::PHILIPS_16_BIT.parse_mono_to_f32(&[0; 16], &mut output),
+            Err(I2sError::InputLength {
+                expected: 8,
+                actual: 16
+            })
+        );
+
+        assert_eq!(
+            I2sFormat::new(BitDepth::Bits24, BitDepth::Bits16)
+                .parse_mono_to_f32(&[0; 8], &mut output),
+            Err(I2sError::SampleTooWide {
+                sample_bits: 24,
+                slot_bits: 16
+            })
+        );
+
+        let mut stereo = Stereo::new(Samples([0.0; 2]), Samples([0.0; 2]));
+        assert_eq!(
+            I2sFormat::PHILIPS_16_BIT.parse_stereo_to_f32(&[0; 4], &mut stereo),
+            Err(I2sError::InputLength {
+                expected: 8,
+                actual: 4
+            })
+        );
+
+        // nothing was written
+        assert_eq!(output, [0.0; 4]);
+    }
+
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_fixed_point() {
+        use crate::audio::fixed::{q15_to_f32, q31_to_f32};
+
+        let input = [0x00, 0x40, 0x00, 0xC0, 0xFF, 0x7F, 0x00, 0x80];
+
+        let mut q15 = [0; 4];
+        I2sFormat::PHILIPS_16_BIT
+            .parse_mono_to_q15(&input, &mut q15)
+            .unwrap();
+        assert_eq!(q15, [0x4000, -0x4000, i16::MAX, i16::MIN]);
+        assert_eq!(q15_to_f32(q15[0]), 0.5);
+
+        let input = [0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0xC0];
+
+        let mut q31 = [0; 2];
+        I2sFormat::PHILIPS_24_BIT
+            .parse_mono_to_q31(&input, &mut q31)
+            .unwrap();
+        assert_eq!(q31_to_f32(q31[0]), 0.5);
+        assert_eq!(q31_to_f32(q31[1]), -0.5);
+
+        // 24-bit to q15 drops the low byte
+        let mut q15 = [0; 2];
+        I2sFormat::PHILIPS_24_BIT
+            .parse_mono_to_q15(&input, &mut q15)
+            .unwrap();
+        assert_eq!(q15, [0x4000, -0x4000]);
+    }
 }
@@ -0,0 +1,276 @@
+//! Program loudness (LUFS) per EBU R128 / ITU-R BS.1770.
+//!
+//! Samples are K-weighted and then chopped into 100 ms blocks of mean square power.
+//! - momentary = the last 400 ms
+//! - short-term = the last 3 s
+//! - integrated = everything since the last reset, with quiet parts gated out so silence between songs doesn't count
+//!
+//! There's no allocator on the microcontrollers, so integrated loudness keeps a histogram of the 400 ms gating blocks
+//! (0.1 LU per bin, like libebur128) instead of every block.
+use circular_buffer::CircularBuffer;
+
+use crate::{audio::KWeightingFilter, remap};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// 4 blocks of 100 ms
+const MOMENTARY_BLOCKS: usize = 4;
+
+/// 30 blocks of 100 ms
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// gating blocks quieter than this are always ignored
+const ABSOLUTE_GATE_LUFS: f32 = -70.0;
+
+/// gating blocks more than this far below the (absolute gated) loudness are ignored
+const RELATIVE_GATE_LU: f32 = -10.0;
+
+/// anything louder than this goes in the top bin of the histogram. a full scale square wave is +3
+const HISTOGRAM_MAX_LUFS: f32 = 5.0;
+
+/// LU per histogram bin
+const HISTOGRAM_STEP: f32 = 0.1;
+
+const HISTOGRAM_BINS: usize = ((HISTOGRAM_MAX_LUFS - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP) as usize;
+
+/// Measures how loud the music sounds to a person. For one channel.
+///
+/// TODO: channel weights once we have stereo input
+pub struct LoudnessMeter {
+    filter: KWeightingFilter,
+    /// number of samples in 100 ms
+    block_len: usize,
+    /// sum of the squares of the samples in the current block
+    block_sum: f32,
+    /// number of samples in the current block
+    block_count: usize,
+    /// mean square power of the most recent 100 ms blocks
+    blocks: CircularBuffer<SHORT_TERM_BLOCKS, f32>,
+    /// how many 400 ms gating blocks have landed in each 0.1 LU range
+    histogram: [u32; HISTOGRAM_BINS],
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let block_len = (sample_rate_hz / 10.0) as usize;
+
+        assert!(block_len > 0);
+
+        Self {
+            filter: KWeightingFilter::new(sample_rate_hz),
+            block_len,
+            block_sum: 0.0,
+            block_count: 0,
+            blocks: CircularBuffer::new(),
+            histogram: [0; HISTOGRAM_BINS],
+        }
+    }
+
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        for &x in samples {
+            let y = self.filter.run(x);
+
+            self.block_sum += y * y;
+            self.block_count += 1;
+
+            if self.block_count == self.block_len {
+                self.push_block(self.block_sum / self.block_len as f32);
+
+                self.block_sum = 0.0;
+                self.block_count = 0;
+            }
+        }
+    }
+
+    fn push_block(&mut self, power: f32) {
+        self.blocks.push_back(power);
+
+        // gating blocks overlap by 75%, so every 100 ms block finishes a new one
+        if let Some(power) = self.mean_power(MOMENTARY_BLOCKS) {
+            let lufs = power_to_lufs(power);
+
+            if lufs > ABSOLUTE_GATE_LUFS {
+                self.histogram[lufs_to_bin(lufs)] += 1;
+            }
+        }
+    }
+
+    /// mean power of the most recent `n` blocks. None if we haven't seen that many yet
+    fn mean_power(&self, n: usize) -> Option<f32> {
+        if self.blocks.len() < n {
+            return None;
+        }
+
+        Some(self.blocks.iter().rev().take(n).sum::<f32>() / n as f32)
+    }
+
+    /// loudness of the last 400 ms (LUFS)
+    pub fn momentary(&self) -> Option<f32> {
+        self.mean_power(MOMENTARY_BLOCKS).map(power_to_lufs)
+    }
+
+    /// loudness of the last 3 s (LUFS)
+    pub fn short_term(&self) -> Option<f32> {
+        self.mean_power(SHORT_TERM_BLOCKS).map(power_to_lufs)
+    }
+
+    /// gated loudness since the start or the last [`Self::reset_integrated`] (LUFS)
+    pub fn integrated(&self) -> Option<f32> {
+        let absolute = self.gated_power(0)?;
+
+        let relative_gate = power_to_lufs(absolute) + RELATIVE_GATE_LU;
+
+        // the relative gate is always above the absolute gate
+        let first_bin = lufs_to_bin(relative_gate.max(ABSOLUTE_GATE_LUFS));
+
+        self.gated_power(first_bin).map(power_to_lufs)
+    }
+
+    /// mean power of all the gating blocks in the histogram at or above `first_bin`
+    fn gated_power(&self, first_bin: usize) -> Option<f32> {
+        let mut total = 0.0;
+        let mut count = 0;
+
+        for (bin, &n) in self.histogram.iter().enumerate().skip(first_bin) {
+            if n > 0 {
+                total += n as f32 * lufs_to_power(bin_to_lufs(bin));
+                count += n;
+            }
+        }
+
+        if count == 0 {
+            None
+        } else {
+            Some(total / count as f32)
+        }
+    }
+
+    /// start a new integrated measurement. call this between songs or sets
+    pub fn reset_integrated(&mut self) {
+        self.histogram.fill(0);
+    }
+
+    /// momentary loudness compared to the program loudness. 0.0 is `range_lu` quieter. 1.0 is as loud or louder.
+    ///
+    /// This is a single number for global brightness. Unlike per-band envelopes, one loud band doesn't make it pump.
+    pub fn brightness(&self, range_lu: f32) -> f32 {
+        match (self.momentary(), self.integrated()) {
+            (Some(momentary), Some(integrated)) => {
+                remap(momentary, integrated - range_lu, integrated, 0.0, 1.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[inline]
+fn power_to_lufs(power: f32) -> f32 {
+    -0.691 + 10.0 * power.log10()
+}
+
+#[inline]
+fn lufs_to_power(lufs: f32) -> f32 {
+    10.0f32.powf((lufs + 0.691) / 10.0)
+}
+
+#[inline]
+fn lufs_to_bin(lufs: f32) -> usize {
+    (((lufs - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP) as usize).min(HISTOGRAM_BINS - 1)
+}
+
+/// the center of a bin
+#[inline]
+fn bin_to_lufs(bin: usize) -> f32 {
+    ABSOLUTE_GATE_LUFS + (bin as f32 + 0.5) * HISTOGRAM_STEP
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use super::*;
+
+    const SAMPLE_RATE_HZ: f32 = 48_000.0;
+
+    /// a 1 kHz sine. K-weighting at 1 kHz and the -0.691 cancel out, so a mono sine at `dbfs` peak is `dbfs - 3.01` LUFS
+    fn push_sine(meter: &mut LoudnessMeter, dbfs: f32, seconds: f32) {
+        let amplitude = 10.0f32.powf(dbfs / 20.0);
+
+        let samples: Vec<f32> = (0..(seconds * SAMPLE_RATE_HZ) as usize)
+            .map(|i| amplitude * (2.0 * PI * 997.0 * i as f32 / SAMPLE_RATE_HZ).sin())
+            .collect();
+
+        // push in small chunks like the mic would
+        for chunk in samples.chunks(512) {
+            meter.push_samples(chunk);
+        }
+    }
+
+    #[test]
+    fn test_sine() {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE_HZ);
+
+        assert_eq!(meter.momentary(), None);
+        assert_eq!(meter.integrated(), None);
+
+        push_sine(&mut meter, -20.0, 5.0);
+
+        for lufs in [
+            meter.momentary().unwrap(),
+            meter.short_term().unwrap(),
+            meter.integrated().unwrap(),
+        ] {
+            assert!((lufs - -23.01).abs() < 0.1, "{lufs}");
+        }
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE_HZ);
+
+        push_sine(&mut meter, -20.0, 10.0);
+        meter.push_samples(&[0.0; 48_000 * 10]);
+
+        assert!(meter.momentary().unwrap() < ABSOLUTE_GATE_LUFS);
+
+        // the blocks that overlap the end of the tone still count a little
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated - -23.01).abs() < 0.3, "{integrated}");
+    }
+
+    #[test]
+    fn test_relative_gate() {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE_HZ);
+
+        // -36 is more than 10 LU below everything else. it gets gated
+        push_sine(&mut meter, -20.0, 10.0);
+        push_sine(&mut meter, -36.0, 10.0);
+
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated - -23.01).abs() < 0.3, "{integrated}");
+
+        meter.reset_integrated();
+
+        // -26 is close enough to count. the mean power of -23 and -29 LUFS is -25.04
+        push_sine(&mut meter, -20.0, 10.0);
+        push_sine(&mut meter, -26.0, 10.0);
+
+        let integrated = meter.integrated().unwrap();
+        assert!((integrated - -25.04).abs() < 0.3, "{integrated}");
+    }
+
+    #[test]
+    fn test_brightness() {
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE_HZ);
+
+        assert_eq!(meter.brightness(20.0), 0.0);
+
+        push_sine(&mut meter, -20.0, 10.0);
+        assert!(meter.brightness(20.0) > 0.99);
+
+        push_sine(&mut meter, -30.0, 1.0);
+        let brightness = meter.brightness(20.0);
+        assert!(brightness > 0.4 && brightness < 0.6, "{brightness}");
+    }
+}
@@ -1,34 +1,72 @@
 //! Audio processing
 //!
-//! Samples -> Buffer -> Window -> FFT -> Amplitudes -> WeightedAmplitudes -> AggregatedAmplitudes -> Decibels -> MicLoudness
-//!                                                                           (Bark, Shazam, etc.)
-//!
-//! TODO: bucket by note
+//! Samples -> Resampler -> Agc -> Buffer -> Window -> FFT -> Amplitudes -> WeightedAmplitudes -> AggregatedAmplitudes -> Decibels -> MicLoudness
+//!                                                                                               (Bark, Shazam, Chroma, etc.)
+//! Samples -> ConstantQ -> ConstantQAmplitudes
+mod adc;
+mod agc;
 mod amplitudes;
 mod bark_scale;
+mod beat;
 mod buffered_fft;
+mod chroma;
+mod constant_q;
+mod dc_blocker;
 mod decibels;
 mod down_resistance_builder;
 mod exponential_scale;
-mod fft;
 mod filter_bank;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
 mod i2s;
+mod loudness;
+mod novelty;
 mod peak_scaled;
+mod pitch;
+mod resample;
 mod samples;
 mod shazam;
+mod spectral_features;
+mod weighted_bins;
 mod weighting;
 
-pub use amplitudes::{AggregatedBins, AggregatedBinsBuilder, Amplitudes, WeightedAmplitudes};
+pub use adc::AdcConverter;
+pub use agc::{Agc, AgcConfig};
+pub use amplitudes::{
+    AggregatedBins, AggregatedBinsBuilder, Aggregation, Amplitudes, WeightedAmplitudes,
+};
 pub use bark_scale::{BarkScaleAmplitudes, BarkScaleBuilder};
-pub use buffered_fft::{BufferedFFT, FftOutputs, bin_to_frequency, frequency_to_bin};
+pub use beat::{BeatDetector, BeatTick};
+pub use buffered_fft::{BufferedFFT, FftOutputs, Overlap, bin_to_frequency, frequency_to_bin};
+pub use chroma::{CHROMA_OUT, ChromaAmplitudes, ChromaBuilder, chroma_class};
+pub use constant_q::{ConstantQ, ConstantQAmplitudes};
+pub use dc_blocker::DcBlocker;
 pub use decibels::Decibels;
+#[allow(deprecated)]
 pub use down_resistance_builder::DownResistanceBuilder;
 pub use exponential_scale::{ExponentialScaleAmplitudes, ExponentialScaleBuilder};
-pub use filter_bank::BarkBank;
-pub use i2s::{parse_i2s_16_bit_mono_to_f32_array, parse_i2s_24_bit_mono_to_f32_array};
+pub use filter_bank::{
+    BandScale, BarkBank, BarkBankConfig, FilterBank, FilterBankError, FloorMode,
+};
+pub use i2s::{BitDepth, Endian, Framing, I2sError, I2sFormat};
+pub use loudness::LoudnessMeter;
+pub use novelty::{NOVELTY_PEAK_DECAY, NOVELTY_SPARKLE, SpectralFlux, novelty_sparkle};
+#[allow(deprecated)]
 pub use peak_scaled::PeakScaledBuilder;
-pub use samples::{Samples, WindowedSamples};
+pub use pitch::{Pitch, PitchDetector, frequency_to_midi, midi_to_frequency};
+pub use resample::{Decimator, Resampler};
+pub use samples::{
+    MAX_STEREO_WIDTH, Samples, Stereo, StereoChannel, StereoSamples, WindowedSamples,
+};
 pub use shazam::{SHAZAM_SCALE_OUT, ShazamScaleBuilder};
-pub use weighting::{AWeighting, FlatWeighting, Weighting};
+pub use spectral_features::SpectralFeatures;
+pub use weighted_bins::{
+    BinWeight, TriangularScaleAmplitudes, TriangularScaleBuilder, TriangularSpacing, WeightedBinMap,
+};
+pub use weighting::{
+    AWeighting, CWeighting, FlatWeighting, Iso226Weighting, KWeighting, KWeightingFilter,
+    Weighting, ZWeighting, c_weighting, c_weighting_decibels, iso226_spl, iso226_weighting,
+    iso226_weighting_decibels, k_weighting, k_weighting_decibels,
+};
 
 // TODO: test comparing bark scale and exponential scale
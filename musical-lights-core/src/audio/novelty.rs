@@ -0,0 +1,240 @@
+//! Spectral flux novelty.
+//!
+//! How much louder things got since the last frame. Only increases count (half-wave rectified). Decreases are just
+//! sounds fading out and aren't interesting to look at.
+//!
+//! FFT magnitudes are log compressed first so that a loud bass note doesn't hide a hi-hat. Bands that are already on a
+//! perceptual scale (like the outputs of [`BarkBank`](crate::audio::BarkBank)) are used as-is.
+use crate::audio::{AggregatedBins, AggregatedBinsBuilder, FftOutputs};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// a band sparkles when its novelty is at least this fraction of its recent peak novelty
+pub const NOVELTY_SPARKLE: f32 = 0.6;
+
+/// how fast the recent peak novelty decays each frame
+pub const NOVELTY_PEAK_DECAY: f32 = 0.95;
+
+/// Keeps the previous frame around so that each new frame can be compared to it.
+///
+/// IN = number of FFT bins (or bands) in each frame
+pub struct SpectralFlux<const IN: usize> {
+    previous: [f32; IN],
+}
+
+impl<const IN: usize> Default for SpectralFlux<IN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const IN: usize> SpectralFlux<IN> {
+    pub const fn new() -> Self {
+        Self {
+            previous: [0.0; IN],
+        }
+    }
+
+    /// total novelty of this frame across all the bins
+    pub fn novelty(&mut self, spectrum: &FftOutputs<'_, IN>) -> f32 {
+        let mut total = 0.0;
+
+        for (x, previous) in spectrum.iter_amplitude().zip(self.previous.iter_mut()) {
+            total += rectified_flux(compress(x), previous);
+        }
+
+        total
+    }
+
+    /// novelty of this frame grouped into bands with the builder's map. Bins outside the map are skipped.
+    ///
+    /// The bins in a band are summed, so wide bands will have more novelty than narrow ones.
+    ///
+    /// Returns the total novelty of all the bands.
+    pub fn novelty_into<const OUT: usize, B: AggregatedBinsBuilder<IN, OUT>>(
+        &mut self,
+        spectrum: &FftOutputs<'_, IN>,
+        builder: &B,
+        output: &mut [f32; OUT],
+    ) -> f32 {
+        output.fill(0.0);
+
+        let mut total = 0.0;
+
+        for ((x, previous), &band) in spectrum
+            .iter_amplitude()
+            .zip(self.previous.iter_mut())
+            .zip(builder.bin_map().iter())
+        {
+            let flux = rectified_flux(compress(x), previous);
+
+            if let Some(band) = band {
+                output[band] += flux;
+                total += flux;
+            }
+        }
+
+        total
+    }
+
+    /// total novelty of this frame of bands
+    pub fn bands_novelty(&mut self, bands: &AggregatedBins<IN>) -> f32 {
+        let mut total = 0.0;
+
+        for (&x, previous) in bands.0.iter().zip(self.previous.iter_mut()) {
+            total += rectified_flux(x, previous);
+        }
+
+        total
+    }
+
+    /// novelty of each band in this frame. Returns the total novelty of all the bands.
+    pub fn bands_novelty_into(
+        &mut self,
+        bands: &AggregatedBins<IN>,
+        output: &mut [f32; IN],
+    ) -> f32 {
+        let mut total = 0.0;
+
+        for ((&x, previous), out) in bands
+            .0
+            .iter()
+            .zip(self.previous.iter_mut())
+            .zip(output.iter_mut())
+        {
+            *out = rectified_flux(x, previous);
+
+            total += *out;
+        }
+
+        total
+    }
+}
+
+/// if a band's novelty is a real transient and not just a wiggle. the lights use this to decide what sparkles
+///
+/// `novelty_peak` is the band's recent peak novelty. it is updated here, so keep one for each band
+#[inline]
+pub fn novelty_sparkle(novelty: f32, novelty_peak: &mut f32) -> bool {
+    *novelty_peak = novelty.max(*novelty_peak * NOVELTY_PEAK_DECAY);
+
+    novelty > f32::EPSILON && novelty >= *novelty_peak * NOVELTY_SPARKLE
+}
+
+/// log compression for FFT magnitudes
+#[inline]
+fn compress(amplitude: f32) -> f32 {
+    (1.0 + amplitude).ln()
+}
+
+/// how much `x` went up since `previous`. `previous` is replaced with `x`
+#[inline]
+fn rectified_flux(x: f32, previous: &mut f32) -> f32 {
+    let flux = (x - *previous).max(0.0);
+
+    *previous = x;
+
+    flux
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+
+    use super::*;
+    use crate::audio::ShazamScaleBuilder;
+
+    #[test]
+    fn test_steady_spectrum() {
+        let mut flux = SpectralFlux::<8>::new();
+
+        let spectrum = [Complex::new(1.0, 0.0); 8];
+
+        // everything is new on the first frame
+        assert!(flux.novelty(&FftOutputs::new(&spectrum)) > 0.0);
+
+        for _ in 0..10 {
+            assert_eq!(flux.novelty(&FftOutputs::new(&spectrum)), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_only_increases_count() {
+        let mut flux = SpectralFlux::<4>::new();
+
+        let quiet = [Complex::new(1.0, 0.0); 4];
+        let mut loud = quiet;
+        loud[2] = Complex::new(0.0, 10.0);
+
+        flux.novelty(&FftOutputs::new(&quiet));
+
+        let up = flux.novelty(&FftOutputs::new(&loud));
+        assert!((up - (11.0f32.ln() - 2.0f32.ln())).abs() < 1e-6, "{up}");
+
+        let down = flux.novelty(&FftOutputs::new(&quiet));
+        assert_eq!(down, 0.0);
+    }
+
+    #[test]
+    fn test_novelty_into_bands() {
+        const BINS: usize = 512;
+
+        let sample_rate_hz = 44_100.0;
+        let builder = ShazamScaleBuilder::<BINS>::new(sample_rate_hz);
+
+        let mut flux = SpectralFlux::<BINS>::new();
+        let mut output = [0.0; 4];
+
+        let silence = [Complex::new(0.0, 0.0); BINS];
+        flux.novelty_into(&FftOutputs::new(&silence), &builder, &mut output);
+        assert_eq!(output, [0.0; 4]);
+
+        // light up bin 4 (~172 Hz). that is in shazam band 2
+        let mut spectrum = silence;
+        spectrum[4] = Complex::new(1.0, 0.0);
+
+        let total = flux.novelty_into(&FftOutputs::new(&spectrum), &builder, &mut output);
+
+        assert_eq!(output[0], 0.0);
+        assert_eq!(output[1], 0.0);
+        assert!(output[2] > 0.0);
+        assert_eq!(output[3], 0.0);
+        assert_eq!(total, output[2]);
+    }
+
+    #[test]
+    fn test_bands_novelty_into() {
+        let mut flux = SpectralFlux::<3>::new();
+        let mut output = [0.0; 3];
+
+        flux.bands_novelty_into(&AggregatedBins([0.5, 0.5, 0.5]), &mut output);
+
+        let total = flux.bands_novelty_into(&AggregatedBins([0.25, 0.75, 1.0]), &mut output);
+
+        assert_eq!(output, [0.0, 0.25, 0.5]);
+        assert_eq!(total, 0.75);
+    }
+
+    #[test]
+    fn test_novelty_sparkle() {
+        let mut peak = 0.0;
+
+        // nothing new never sparkles
+        assert!(!novelty_sparkle(0.0, &mut peak));
+
+        // a hit sets the peak
+        assert!(novelty_sparkle(1.0, &mut peak));
+        assert_eq!(peak, 1.0);
+
+        // a small wiggle right after doesn't count
+        assert!(!novelty_sparkle(0.1, &mut peak));
+        assert_eq!(peak, NOVELTY_PEAK_DECAY);
+
+        // but once the peak has decayed, a smaller hit does
+        for _ in 0..20 {
+            novelty_sparkle(0.0, &mut peak);
+        }
+        assert!(novelty_sparkle(0.5, &mut peak));
+    }
+}
@@ -1,11 +1,13 @@
-//! TODO: i don't like this very much.
+//! TODO: i don't like this very much. use [`Agc`](super::Agc) instead.
 
+#[deprecated(note = "use Agc on the samples instead")]
 pub struct PeakScaledBuilder {
     pub max: f32,
     pub decay_per_tick: f32,
     pub min: f32,
 }
 
+#[allow(deprecated)]
 impl PeakScaledBuilder {
     pub fn new(decay_per_tick: f32) -> Self {
         Self {
@@ -0,0 +1,339 @@
+//! Pitch (dominant note) detection.
+//!
+//! Uses a harmonic product spectrum on the FFT outputs. A sung or played note has energy at its fundamental and at
+//! every multiple of it. Multiplying (adding the logs of) the spectrum with copies of itself squished by 2, 3, 4, ...
+//! makes the fundamental stand out even if it is quiet (or missing!) in the original spectrum.
+//!
+//! FFT bins are pretty wide for low notes (~10 Hz for a 4096 FFT at 44.1 kHz), so the harmonic peaks are refined with
+//! parabolic interpolation before they are averaged into the final frequency.
+use crate::audio::{FftOutputs, bin_to_frequency, frequency_to_bin};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// how many harmonics (including the fundamental) to multiply together
+const HARMONICS: usize = 5;
+
+/// keeps the log of an empty bin from going to -inf
+const LOG_FLOOR: f32 = 1e-9;
+
+/// bins quieter than this fraction of the loudest bin (-40 dB) count as empty.
+/// without this, window leakage makes the sub-harmonics of a note look good enough to win and we guess an octave low
+const DYNAMIC_RANGE: f32 = 0.01;
+
+/// anything quieter than this (mean power per bin) is silence and has no pitch
+const SILENCE_POWER: f32 = 1e-12;
+
+/// A4
+const A4_HZ: f32 = 440.0;
+const A4_MIDI: f32 = 69.0;
+
+/// fractional midi note number for a frequency. 69.0 is A4 (440 Hz). 60.0 is middle C.
+#[inline]
+pub fn frequency_to_midi(frequency: f32) -> f32 {
+    A4_MIDI + 12.0 * (frequency / A4_HZ).log2()
+}
+
+/// frequency of a (possibly fractional) midi note number
+#[inline]
+pub fn midi_to_frequency(midi: f32) -> f32 {
+    A4_HZ * 2.0f32.powf((midi - A4_MIDI) / 12.0)
+}
+
+/// the dominant note in a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pitch {
+    /// estimated fundamental frequency
+    pub frequency: f32,
+    /// the nearest note
+    pub midi_note: u8,
+    /// how far the frequency is from `midi_note`. -50.0 to 50.0
+    pub cents: f32,
+    /// fraction of the power that is in the harmonics of this note. 0.0 is noise. 1.0 is a pure tone
+    pub confidence: f32,
+}
+
+impl Pitch {
+    /// None if the nearest note is below C-1 (midi 0) or above G9 (midi 127)
+    pub fn from_frequency(frequency: f32, confidence: f32) -> Option<Self> {
+        if frequency <= 0.0 {
+            return None;
+        }
+
+        let midi = frequency_to_midi(frequency);
+
+        let midi_note = midi.round();
+
+        if !(0.0..=127.0).contains(&midi_note) {
+            return None;
+        }
+
+        Some(Self {
+            frequency,
+            midi_note: midi_note as u8,
+            cents: (midi - midi_note) * 100.0,
+            confidence,
+        })
+    }
+
+    /// 0 = C, 1 = C#, ... 9 = A, 11 = B
+    #[inline]
+    pub fn pitch_class(&self) -> u8 {
+        self.midi_note % 12
+    }
+}
+
+/// Finds the strongest fundamental between a min and max frequency.
+///
+/// IN = number of FFT bins
+pub struct PitchDetector<const IN: usize> {
+    sample_rate_hz: f32,
+    /// lowest fundamental bin that we check
+    min_bin: usize,
+    /// highest fundamental bin that we check
+    max_bin: usize,
+    /// log of the magnitudes. kept here so a big array isn't put on the stack every frame
+    log_magnitudes: [f32; IN],
+}
+
+impl<const IN: usize> PitchDetector<IN> {
+    /// `min_freq` and `max_freq` are limits on the fundamental. 80 to 1000 Hz covers most voices.
+    pub fn new(min_freq: f32, max_freq: f32, sample_rate_hz: f32) -> Self {
+        assert!(min_freq > 0.0 && min_freq < max_freq);
+
+        // we need a bin on either side for the interpolation
+        let min_bin = frequency_to_bin(min_freq, sample_rate_hz, IN).max(2);
+        let max_bin = frequency_to_bin(max_freq, sample_rate_hz, IN);
+
+        assert!(
+            HARMONICS * (max_bin + 1) < IN,
+            "max_freq is too high to see its harmonics. use a higher sample rate or a lower max_freq"
+        );
+
+        Self {
+            sample_rate_hz,
+            min_bin,
+            max_bin,
+            log_magnitudes: [0.0; IN],
+        }
+    }
+
+    /// Returns None if the frame is silent.
+    ///
+    /// Check the confidence before trusting this! A noisy frame will still have a "pitch".
+    pub fn pitch(&mut self, spectrum: &FftOutputs<'_, IN>) -> Option<Pitch> {
+        let highest_bin = HARMONICS * (self.max_bin + 1);
+
+        let mut total_power = 0.0;
+        let mut loudest = 0.0f32;
+
+        for (i, x) in spectrum.iter_amplitude().enumerate() {
+            if i >= self.min_bin / 2 && i <= highest_bin {
+                total_power += x * x;
+                loudest = loudest.max(x);
+            }
+        }
+
+        if total_power / ((highest_bin - self.min_bin / 2) as f32) < SILENCE_POWER {
+            return None;
+        }
+
+        let floor = loudest * DYNAMIC_RANGE + LOG_FLOOR;
+
+        for (x, log_magnitude) in spectrum
+            .iter_amplitude()
+            .zip(self.log_magnitudes.iter_mut())
+        {
+            *log_magnitude = x.max(floor).ln();
+        }
+
+        // the harmonic product spectrum. logs so that it is a sum instead of a product
+        let mut best_bin = self.min_bin;
+        let mut best_score = f32::MIN;
+
+        for k in self.min_bin..=self.max_bin {
+            let score: f32 = (1..=HARMONICS).map(|h| self.harmonic_peak(k, h).1).sum();
+
+            if score > best_score {
+                best_bin = k;
+                best_score = score;
+            }
+        }
+
+        // refine every harmonic and average them. weighted by how loud each one is
+        let mut weighted_bin = 0.0;
+        let mut total_weight = 0.0;
+        let mut harmonic_power = 0.0;
+
+        for h in 1..=HARMONICS {
+            let (peak, _) = self.harmonic_peak(best_bin, h);
+
+            let offset = self.interpolate(peak);
+
+            let amplitude = spectrum.spectrum()[peak].norm();
+
+            weighted_bin += amplitude * (peak as f32 + offset) / h as f32;
+            total_weight += amplitude;
+
+            // a windowed peak spreads into its neighbors
+            harmonic_power += spectrum.spectrum()[peak - 1..=peak + 1]
+                .iter()
+                .map(|x| x.norm_sqr())
+                .sum::<f32>();
+        }
+
+        let fundamental_bin = weighted_bin / total_weight;
+
+        // bin_to_frequency wants a usize, so scale the fraction ourselves
+        let frequency = fundamental_bin * bin_to_frequency(1, self.sample_rate_hz, IN);
+
+        let confidence = (harmonic_power / total_power).clamp(0.0, 1.0);
+
+        Pitch::from_frequency(frequency, confidence)
+    }
+
+    /// the loudest bin near the `h`th harmonic of bin `k`. returns the bin and its log magnitude
+    ///
+    /// harmonics of a fundamental that is between two bins drift further from `h * k` the higher they go
+    #[inline]
+    fn harmonic_peak(&self, k: usize, h: usize) -> (usize, f32) {
+        let center = h * k;
+        let spread = h / 2;
+
+        let mut best = (center, self.log_magnitudes[center]);
+
+        for i in (center - spread)..=(center + spread) {
+            if self.log_magnitudes[i] > best.1 {
+                best = (i, self.log_magnitudes[i]);
+            }
+        }
+
+        best
+    }
+
+    /// fit a parabola through a peak and its neighbors. returns how far (in bins) the real peak is from `i`
+    #[inline]
+    fn interpolate(&self, i: usize) -> f32 {
+        let before = self.log_magnitudes[i - 1];
+        let peak = self.log_magnitudes[i];
+        let after = self.log_magnitudes[i + 1];
+
+        let curvature = before - 2.0 * peak + after;
+
+        if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use super::*;
+    use crate::{
+        audio::{BufferedFFT, FlatWeighting, Samples},
+        windows::HanningWindow,
+    };
+
+    const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+    type MyBufferedFFT = BufferedFFT<4096, 4096, 2048, HanningWindow<4096>, FlatWeighting<2048>>;
+
+    /// a note with a few harmonics that get quieter
+    fn note(frequency: f32, fundamental: f32) -> Samples<4096> {
+        Samples(core::array::from_fn(|i| {
+            let t = i as f32 / SAMPLE_RATE_HZ;
+
+            fundamental * (2.0 * PI * frequency * t).sin()
+                + 0.5 * (2.0 * PI * 2.0 * frequency * t).sin()
+                + 0.3 * (2.0 * PI * 3.0 * frequency * t).sin()
+                + 0.2 * (2.0 * PI * 4.0 * frequency * t).sin()
+        }))
+    }
+
+    fn detect(samples: &Samples<4096>) -> Option<Pitch> {
+        let mut fft = MyBufferedFFT::new(FlatWeighting);
+        let mut detector = PitchDetector::<2048>::new(60.0, 1_000.0, SAMPLE_RATE_HZ);
+
+        fft.push_samples(samples);
+
+        detector.pitch(&fft.fft())
+    }
+
+    #[test]
+    fn test_midi_round_trip() {
+        assert_eq!(frequency_to_midi(440.0), 69.0);
+        assert!((frequency_to_midi(261.6256) - 60.0).abs() < 1e-4);
+        assert!((midi_to_frequency(60.0) - 261.6256).abs() < 1e-2);
+
+        for midi in 21..=108 {
+            let midi = midi as f32;
+
+            assert!((frequency_to_midi(midi_to_frequency(midi)) - midi).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_pitch_from_frequency() {
+        let pitch = Pitch::from_frequency(446.0, 1.0).unwrap();
+
+        assert_eq!(pitch.midi_note, 69);
+        assert_eq!(pitch.pitch_class(), 9);
+        assert!((pitch.cents - 23.45).abs() < 0.1, "{}", pitch.cents);
+
+        let pitch = Pitch::from_frequency(255.0, 1.0).unwrap();
+
+        assert_eq!(pitch.midi_note, 60);
+        assert!(pitch.cents < -40.0, "{}", pitch.cents);
+    }
+
+    #[test]
+    fn test_pitch_out_of_range() {
+        // the very ends of the midi range are still notes
+        let lowest = Pitch::from_frequency(midi_to_frequency(0.0), 1.0).unwrap();
+        assert_eq!(lowest.midi_note, 0);
+        assert!(lowest.cents.abs() < 1.0, "{lowest:?}");
+
+        let highest = Pitch::from_frequency(midi_to_frequency(127.0), 1.0).unwrap();
+        assert_eq!(highest.midi_note, 127);
+        assert!(highest.cents.abs() < 1.0, "{highest:?}");
+
+        // an octave past either end isn't a midi note at all
+        assert_eq!(Pitch::from_frequency(midi_to_frequency(-12.0), 1.0), None);
+        assert_eq!(Pitch::from_frequency(midi_to_frequency(139.0), 1.0), None);
+        assert_eq!(Pitch::from_frequency(0.0, 1.0), None);
+    }
+
+    #[test]
+    fn test_notes() {
+        // A2, C4, A4, and a detuned E5
+        for frequency in [110.0, 261.6256, 440.0, 672.0] {
+            let pitch = detect(&note(frequency, 1.0)).unwrap();
+
+            let expected = frequency_to_midi(frequency);
+
+            assert_eq!(pitch.midi_note, expected.round() as u8, "{pitch:?}");
+            assert!(
+                (pitch.cents - (expected - expected.round()) * 100.0).abs() < 10.0,
+                "{frequency}: {pitch:?}"
+            );
+            assert!(pitch.confidence > 0.8, "{pitch:?}");
+        }
+    }
+
+    #[test]
+    fn test_missing_fundamental() {
+        let pitch = detect(&note(220.0, 0.0)).unwrap();
+
+        assert_eq!(pitch.midi_note, 57, "{pitch:?}");
+    }
+
+    #[test]
+    fn test_silence() {
+        assert_eq!(detect(&Samples([0.0; 4096])), None);
+    }
+}
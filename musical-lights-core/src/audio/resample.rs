@@ -0,0 +1,318 @@
+//! Sample rate conversion.
+//!
+//! The mics and ADCs all run at different rates (44.1 kHz, 48 kHz, whatever the STM32 ADC manages), but the band
+//! layouts are all in Hz. Put this in front of the analysers so they can all run at one internal rate.
+//!
+//! Going down to 22.05 kHz halves the work for everything after it. Nothing we light up cares about anything above 11 kHz
+//! anyways.
+//!
+//! Samples -> Resampler -> Agc -> Buffer -> Window -> FFT -> ...
+//!
+//! This is a polyphase windowed-sinc filter. The anti-aliasing low-pass filter is split into `PHASES` sub-filters of
+//! `TAPS` taps each. Each one is the filter delayed by a different fraction of an input sample. Every output picks the
+//! two sub-filters closest to where it lands between the inputs and linearly interpolates between them. Only the outputs
+//! that are actually needed get calculated, so decimating by 2 costs `2 * TAPS` multiplies per output sample.
+use circular_buffer::CircularBuffer;
+
+use crate::windows::const_cos;
+
+/// how much of the new nyquist frequency to keep. the rest is the filter's transition band
+const PASSBAND: f64 = 0.9;
+
+/// Convert a stream of samples from one sample rate to another. The ratio doesn't need to be an integer.
+///
+/// - TAPS = taps per sub-filter. more taps are a sharper filter (less aliasing), but cost more per output sample.
+///   16-32 is plenty for lights
+/// - PHASES = how many fractional delays to choose from. more phases are more accurate for odd ratios like 48k -> 44.1k.
+///   this costs RAM but not CPU. Use 1 for integer decimation (see [`Decimator`])
+///
+/// The filter delays everything by `TAPS / 2` input samples.
+///
+/// Everything is calculated in [`Self::new`], so this can go in a `static`.
+pub struct Resampler<const TAPS: usize, const PHASES: usize> {
+    input_hz: f32,
+    output_hz: f32,
+    /// `coefficients[p][j]` is the tap for the `j`th newest sample when the output is `p / PHASES` samples after it
+    coefficients: [[f32; TAPS]; PHASES],
+    history: CircularBuffer<TAPS, f32>,
+    /// input samples per output sample
+    step: f32,
+    /// when the next output is due. in input samples after the newest input sample
+    next: f32,
+}
+
+/// Integer decimation only ever needs the first phase. `Decimator::<32>::new(44_100.0, 22_050.0)`
+pub type Decimator<const TAPS: usize> = Resampler<TAPS, 1>;
+
+impl<const TAPS: usize, const PHASES: usize> Resampler<TAPS, PHASES> {
+    pub const fn new(input_hz: f32, output_hz: f32) -> Self {
+        assert!(TAPS >= 2);
+        assert!(PHASES >= 1);
+        assert!(input_hz > 0.0);
+        assert!(output_hz > 0.0);
+
+        Self {
+            input_hz,
+            output_hz,
+            coefficients: coefficients::<TAPS, PHASES>(input_hz, output_hz),
+            history: CircularBuffer::new(),
+            step: input_hz / output_hz,
+            next: 1.0,
+        }
+    }
+
+    pub const fn input_hz(&self) -> f32 {
+        self.input_hz
+    }
+
+    pub const fn output_hz(&self) -> f32 {
+        self.output_hz
+    }
+
+    /// the most samples that [`Self::process`] can output for `input_len` samples in
+    pub fn max_output_len(&self, input_len: usize) -> usize {
+        (input_len as f32 / self.step) as usize + 1
+    }
+
+    /// forget all the old samples
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.next = 1.0;
+    }
+
+    /// Resample a block of samples. Returns how many samples were written to `output`.
+    ///
+    /// `output` must be at least [`Self::max_output_len`] long. The number of outputs changes from call to call when the
+    /// ratio isn't an integer.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> usize {
+        assert!(output.len() >= self.max_output_len(input.len()));
+
+        let mut n = 0;
+
+        for &x in input {
+            self.push(x, |y| {
+                output[n] = y;
+                n += 1;
+            });
+        }
+
+        n
+    }
+
+    /// Add one sample and call `f` with any outputs that are now ready. This is 0 or 1 outputs when decimating.
+    #[inline]
+    pub fn push(&mut self, x: f32, mut f: impl FnMut(f32)) {
+        self.history.push_back(x);
+
+        self.next -= 1.0;
+
+        while self.next < 1.0 {
+            f(self.output_at(self.next));
+
+            self.next += self.step;
+        }
+    }
+
+    /// the output `fraction` of a sample after the newest input
+    fn output_at(&self, fraction: f32) -> f32 {
+        let position = fraction * PHASES as f32;
+
+        let phase = (position as usize).min(PHASES - 1);
+        let blend = position - phase as f32;
+
+        let a = self.dot(&self.coefficients[phase]);
+
+        if blend <= 0.0 {
+            return a;
+        }
+
+        // a whole sample of delay is the first phase shifted over by one tap
+        let b = if phase + 1 < PHASES {
+            self.dot(&self.coefficients[phase + 1])
+        } else {
+            self.dot(&self.coefficients[0][1..])
+        };
+
+        a + (b - a) * blend
+    }
+
+    /// newest sample first
+    #[inline]
+    fn dot(&self, taps: &[f32]) -> f32 {
+        let (older, newer) = self.history.as_slices();
+
+        newer
+            .iter()
+            .rev()
+            .chain(older.iter().rev())
+            .zip(taps)
+            .map(|(x, c)| x * c)
+            .sum()
+    }
+}
+
+/// Blackman windowed sinc. Each phase is normalized so that DC passes through at exactly 1.0
+const fn coefficients<const TAPS: usize, const PHASES: usize>(
+    input_hz: f32,
+    output_hz: f32,
+) -> [[f32; TAPS]; PHASES] {
+    use core::f64::consts::{FRAC_PI_2, PI, TAU};
+
+    let ratio = output_hz as f64 / input_hz as f64;
+
+    // cycles per input sample. only filter below the new nyquist when going down
+    let cutoff = if ratio < 1.0 { ratio } else { 1.0 } * 0.5 * PASSBAND;
+
+    let center = TAPS as f64 / 2.0;
+
+    let mut coefficients = [[0.0; TAPS]; PHASES];
+
+    let mut p = 0;
+    while p < PHASES {
+        let mut row = [0.0f64; TAPS];
+        let mut sum = 0.0;
+
+        let mut j = 0;
+        while j < TAPS {
+            // how far this tap is from the output. in input samples
+            let t = j as f64 + p as f64 / PHASES as f64;
+
+            let x = PI * 2.0 * cutoff * (t - center);
+
+            // sin(x) = cos(x - π/2)
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                const_cos(x - FRAC_PI_2) / x
+            };
+
+            let window = 0.42 - 0.5 * const_cos(TAU * t / TAPS as f64)
+                + 0.08 * const_cos(2.0 * TAU * t / TAPS as f64);
+
+            row[j] = sinc * window;
+            sum += row[j];
+
+            j += 1;
+        }
+
+        let mut j = 0;
+        while j < TAPS {
+            coefficients[p][j] = (row[j] / sum) as f32;
+            j += 1;
+        }
+
+        p += 1;
+    }
+
+    coefficients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(unused_imports)]
+    use micromath::F32Ext;
+
+    fn sine(hz: f32, sample_rate_hz: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (core::f32::consts::TAU * hz * i as f32 / sample_rate_hz).sin())
+            .collect()
+    }
+
+    fn rms(x: &[f32]) -> f32 {
+        (x.iter().map(|x| x * x).sum::<f32>() / x.len() as f32).sqrt()
+    }
+
+    fn resample<const TAPS: usize, const PHASES: usize>(
+        r: &mut Resampler<TAPS, PHASES>,
+        input: &[f32],
+    ) -> Vec<f32> {
+        let mut output = vec![0.0; r.max_output_len(input.len())];
+
+        let n = r.process(input, &mut output);
+        output.truncate(n);
+
+        output
+    }
+
+    /// this runs at compile time
+    const DECIMATOR: Decimator<32> = Decimator::new(44_100.0, 22_050.0);
+
+    #[test]
+    fn test_decimate() {
+        let mut r = DECIMATOR;
+
+        assert_eq!(r.output_hz(), 22_050.0);
+
+        // DC passes through
+        let output = resample(&mut r, &[0.5; 4410]);
+        assert_eq!(output.len(), 2205);
+        for x in &output[32..] {
+            assert!((x - 0.5).abs() < 1e-5, "{x}");
+        }
+
+        // 1 kHz is in the passband. 0.707 is the rms of a full scale sine
+        r.reset();
+        let output = resample(&mut r, &sine(1_000.0, 44_100.0, 4410));
+        let level = rms(&output[32..]);
+        assert!((level - 0.707).abs() < 0.01, "{level}");
+
+        // 15 kHz is above the new nyquist and would alias down to 7 kHz
+        r.reset();
+        let output = resample(&mut r, &sine(15_000.0, 44_100.0, 4410));
+        let level = rms(&output[32..]);
+        assert!(level < 0.005, "{level}");
+    }
+
+    #[test]
+    fn test_fractional() {
+        let mut r = Resampler::<32, 64>::new(48_000.0, 44_100.0);
+
+        // exactly 44100 outputs for 48000 inputs. the blocks don't need to line up
+        let input = sine(1_000.0, 48_000.0, 48_000);
+        let mut output = Vec::new();
+        for block in input.chunks(480) {
+            output.extend(resample(&mut r, block));
+        }
+        assert_eq!(output.len(), 44_100);
+
+        // compare to a sine generated at the new rate. the filter delays by TAPS / 2 input samples
+        let delay_s = 16.0 / 48_000.0;
+        let mut worst: f32 = 0.0;
+        for (i, y) in output.iter().enumerate().skip(100) {
+            let t = i as f32 / 44_100.0 - delay_s;
+            let expected = (core::f32::consts::TAU * 1_000.0 * t).sin();
+
+            worst = worst.max((y - expected).abs());
+        }
+        assert!(worst < 0.01, "{worst}");
+    }
+
+    #[test]
+    fn test_upsample() {
+        let mut r = Resampler::<16, 16>::new(22_050.0, 44_100.0);
+
+        let output = resample(&mut r, &sine(2_000.0, 22_050.0, 2205));
+        assert_eq!(output.len(), 4410);
+
+        let level = rms(&output[64..]);
+        assert!((level - 0.707).abs() < 0.01, "{level}");
+    }
+
+    #[test]
+    fn test_blocks_match() {
+        let input = sine(3_000.0, 44_100.0, 1000);
+
+        let mut a = Resampler::<16, 8>::new(44_100.0, 16_000.0);
+        let all_at_once = resample(&mut a, &input);
+
+        let mut b = Resampler::<16, 8>::new(44_100.0, 16_000.0);
+        let mut in_blocks = Vec::new();
+        for block in input.chunks(7) {
+            in_blocks.extend(resample(&mut b, block));
+        }
+
+        assert_eq!(all_at_once, in_blocks);
+    }
+}
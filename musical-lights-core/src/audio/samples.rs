@@ -1,3 +1,6 @@
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
 /// S = number of microphone samples
 /// TODO: we need to have an option that uses a Box
 #[derive(Debug)]
@@ -5,6 +8,185 @@
 #[repr(transparent)]
 pub struct Samples<const S: usize>(pub [f32; S]);
 
+/// One of the two channels from a stereo mic, or a mix of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StereoChannel {
+    Left,
+    Right,
+    /// (left + right) / 2. what both mics hear. this is what a mono mic would hear
+    Mid,
+    /// (left - right) / 2. what is different between the mics. silent for mono sources
+    Side,
+}
+
+/// A pair of anything. Samples, analysers, or their outputs.
+///
+/// A jacket with two mics can put a [`BarkBank`](crate::audio::BarkBank) on each side and light the left and right
+/// panels independently with [`Stereo::run`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stereo<T> {
+    pub left: T,
+    pub right: T,
+}
+
+/// [`StereoSamples::width`] of two channels that are exactly out of phase. 100x louder side than mid is 40 dB
+pub const MAX_STEREO_WIDTH: f32 = 100.0;
+
+/// S = number of microphone samples per channel
+pub type StereoSamples<const S: usize> = Stereo<Samples<S>>;
+
+impl<T> Stereo<T> {
+    pub const fn new(left: T, right: T) -> Self {
+        Self { left, right }
+    }
+
+    /// make two of the same thing. useful for analysers: `Stereo::from_fn(|| BarkBank::new(fps, sample_rate_hz))`
+    pub fn from_fn(mut f: impl FnMut() -> T) -> Self {
+        Self {
+            left: f(),
+            right: f(),
+        }
+    }
+
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Stereo<U> {
+        Stereo {
+            left: f(self.left),
+            right: f(self.right),
+        }
+    }
+
+    pub fn as_ref(&self) -> Stereo<&T> {
+        Stereo {
+            left: &self.left,
+            right: &self.right,
+        }
+    }
+
+    pub fn as_mut(&mut self) -> Stereo<&mut T> {
+        Stereo {
+            left: &mut self.left,
+            right: &mut self.right,
+        }
+    }
+
+    /// run one analyser per channel. the left analyser gets the left samples and the right analyser gets the right samples
+    pub fn run<I, U>(
+        &mut self,
+        input: &Stereo<I>,
+        mut f: impl FnMut(&mut T, &I) -> U,
+    ) -> Stereo<U> {
+        Stereo {
+            left: f(&mut self.left, &input.left),
+            right: f(&mut self.right, &input.right),
+        }
+    }
+}
+
+impl<const S: usize> StereoSamples<S> {
+    /// deinterleave `[l, r, l, r, ...]`. there must be exactly `2 * S` samples
+    pub fn from_interleaved(interleaved: &[f32]) -> Self {
+        assert_eq!(
+            interleaved.len(),
+            2 * S,
+            "need one left and one right per sample"
+        );
+
+        let mut x = Self::new(Samples([0.0; S]), Samples([0.0; S]));
+
+        for ((frame, l), r) in interleaved
+            .chunks_exact(2)
+            .zip(x.left.0.iter_mut())
+            .zip(x.right.0.iter_mut())
+        {
+            *l = frame[0];
+            *r = frame[1];
+        }
+
+        x
+    }
+
+    /// the same samples on both sides
+    pub fn from_mono(samples: &Samples<S>) -> Self {
+        Self::new(Samples(samples.0), Samples(samples.0))
+    }
+
+    pub fn channel(&self, channel: StereoChannel) -> Samples<S> {
+        let mut output = Samples([0.0; S]);
+
+        self.channel_into(channel, &mut output);
+
+        output
+    }
+
+    /// like [`Self::channel`], but without a big array on the stack
+    pub fn channel_into(&self, channel: StereoChannel, output: &mut Samples<S>) {
+        let (l, r) = (&self.left.0, &self.right.0);
+
+        match channel {
+            StereoChannel::Left => output.0.copy_from_slice(l),
+            StereoChannel::Right => output.0.copy_from_slice(r),
+            StereoChannel::Mid => {
+                for ((x, l), r) in output.0.iter_mut().zip(l).zip(r) {
+                    *x = (l + r) * 0.5;
+                }
+            }
+            StereoChannel::Side => {
+                for ((x, l), r) in output.0.iter_mut().zip(l).zip(r) {
+                    *x = (l - r) * 0.5;
+                }
+            }
+        }
+    }
+
+    /// How wide the stereo image is. side rms / mid rms.
+    ///
+    /// 0.0 is mono. 1.0 is two totally different signals. Above 1.0 means the mics are out of phase (or wired backwards).
+    /// Silence is 0.0. Perfectly out of phase (no mid at all) is [`MAX_STEREO_WIDTH`], and nothing goes above that.
+    pub fn width(&self) -> f32 {
+        let (mid, side) =
+            self.left
+                .0
+                .iter()
+                .zip(self.right.0.iter())
+                .fold((0.0, 0.0), |(mid, side), (l, r)| {
+                    let m = l + r;
+                    let s = l - r;
+
+                    (mid + m * m, side + s * s)
+                });
+
+        if side == 0.0 {
+            0.0
+        } else if mid == 0.0 {
+            MAX_STEREO_WIDTH
+        } else {
+            (side / mid).sqrt().min(MAX_STEREO_WIDTH)
+        }
+    }
+
+    /// Pearson correlation between the channels. 1.0 is mono. 0.0 is unrelated. -1.0 is out of phase.
+    ///
+    /// Silence on either side is 1.0 so that it looks like mono instead of wide.
+    pub fn correlation(&self) -> f32 {
+        let (lr, ll, rr) = self
+            .left
+            .0
+            .iter()
+            .zip(self.right.0.iter())
+            .fold((0.0, 0.0, 0.0), |(lr, ll, rr), (l, r)| {
+                (lr + l * r, ll + l * l, rr + r * r)
+            });
+
+        if ll == 0.0 || rr == 0.0 {
+            1.0
+        } else {
+            lr / (ll * rr).sqrt()
+        }
+    }
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(transparent)]
@@ -32,3 +214,75 @@ impl<const S: usize> WindowedSamples<S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine<const S: usize>(cycles: f32, amplitude: f32) -> Samples<S> {
+        Samples(core::array::from_fn(|i| {
+            amplitude * (core::f32::consts::TAU * cycles * i as f32 / S as f32).sin()
+        }))
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let x = StereoSamples::<3>::from_interleaved(&[1.0, -1.0, 0.5, 0.25, 0.0, 1.0]);
+
+        assert_eq!(x.left.0, [1.0, 0.5, 0.0]);
+        assert_eq!(x.right.0, [-1.0, 0.25, 1.0]);
+
+        assert_eq!(x.channel(StereoChannel::Left).0, x.left.0);
+        assert_eq!(x.channel(StereoChannel::Right).0, x.right.0);
+        assert_eq!(x.channel(StereoChannel::Mid).0, [0.0, 0.375, 0.5]);
+        assert_eq!(x.channel(StereoChannel::Side).0, [1.0, 0.125, -0.5]);
+    }
+
+    #[test]
+    fn test_width() {
+        let mono = StereoSamples::from_mono(&sine::<256>(4.0, 0.5));
+        assert_eq!(mono.width(), 0.0);
+        assert!((mono.correlation() - 1.0).abs() < 1e-6);
+        assert!(
+            mono.channel(StereoChannel::Side)
+                .0
+                .iter()
+                .all(|x| *x == 0.0)
+        );
+
+        // unrelated signals on each side
+        let wide = Stereo::new(sine::<256>(4.0, 0.5), sine::<256>(9.0, 0.5));
+        assert!((wide.width() - 1.0).abs() < 1e-3, "{}", wide.width());
+        assert!(wide.correlation().abs() < 1e-3, "{}", wide.correlation());
+
+        // one mic wired backwards
+        let inverted = Stereo::new(sine::<256>(4.0, 0.5), sine::<256>(4.0, -0.5));
+        assert_eq!(inverted.width(), MAX_STEREO_WIDTH);
+
+        // a tiny bit of mid doesn't blow up either
+        let almost_inverted = Stereo::new(sine::<256>(4.0, 0.5), sine::<256>(4.0, -0.4999));
+        assert_eq!(almost_inverted.width(), MAX_STEREO_WIDTH);
+        assert!((inverted.correlation() + 1.0).abs() < 1e-6);
+
+        let silent = StereoSamples::<256>::from_mono(&Samples([0.0; 256]));
+        assert_eq!(silent.width(), 0.0);
+        assert_eq!(silent.correlation(), 1.0);
+    }
+
+    #[test]
+    fn test_run_per_channel() {
+        // a very simple "analyser" that remembers the loudest sample it has ever seen
+        let mut peaks = Stereo::from_fn(|| 0.0f32);
+
+        let x = Stereo::new(sine::<256>(4.0, 0.5), sine::<256>(4.0, 0.25));
+
+        let seen = peaks.run(&x, |peak, samples| {
+            *peak = samples.0.iter().fold(*peak, |acc, x| acc.max(x.abs()));
+            *peak
+        });
+
+        assert!((seen.left - 0.5).abs() < 1e-6);
+        assert!((seen.right - 0.25).abs() < 1e-6);
+        assert_eq!(seen, peaks);
+    }
+}
@@ -1,5 +1,7 @@
 //! TODO: i don't think this is actually how shazam works
-use super::amplitudes::{AggregatedBins, AggregatedBinsBuilder};
+//!
+//! Unlike the other scales, the outputs are linear power, not dBFS. That goes for every [`Aggregation`] too.
+use super::amplitudes::{AggregatedBins, AggregatedBinsBuilder, Aggregation};
 use crate::audio::{FftOutputs, bin_to_frequency};
 
 pub const SHAZAM_SCALE_OUT: usize = 4;
@@ -15,16 +17,21 @@ pub struct ShazamScaleBuilder<const FFT_OUT: usize> {
 pub struct ShazamAmplitudes(pub AggregatedBins<SHAZAM_SCALE_OUT>);
 
 impl<const FFT_OUT: usize> ShazamScaleBuilder<FFT_OUT> {
-    pub const fn uninit() -> Self {
-        let map = [None; FFT_OUT];
+    /// The whole map is built here. This is const so that it can go in a `static`
+    /// TODO: dynamically create weights (its always just 1.0 for now so no need)
+    pub const fn new(sample_rate_hz: f32) -> Self {
+        let mut map = [None; FFT_OUT];
 
-        Self { map }
-    }
+        let mut i = 0;
+        while i < FFT_OUT {
+            let f = bin_to_frequency(i, sample_rate_hz, FFT_OUT);
+
+            map[i] = shazam_band(f);
 
-    pub fn new(sample_rate_hz: f32) -> Self {
-        let mut x = Self::uninit();
-        x.init(sample_rate_hz);
-        x
+            i += 1;
+        }
+
+        Self { map }
     }
 }
 
@@ -43,21 +50,6 @@ impl<const FFT_OUT: usize> AggregatedBinsBuilder<FFT_OUT, SHAZAM_SCALE_OUT>
         &self.map
     }
 
-    /// TODO: how can we use types to be sure this init gets called
-    fn init(&mut self, sample_rate_hz: f32) {
-        // TODO: dynamically create weights (its always just 1.0 for now so no need)
-
-        for (i, x) in self.map.iter_mut().enumerate() {
-            let f = bin_to_frequency(i, sample_rate_hz, FFT_OUT);
-
-            let b = shazam_band(f);
-
-            // info!("{} {} = {:?}", i, f, b);
-
-            *x = b;
-        }
-    }
-
     /// TODO: rename this function? should sum_power_into just be here and not as a builder in Aggregated Bins at all?
     #[inline]
     fn loudness_into(&self, spectrum: &FftOutputs<FFT_OUT>, output: &mut Self::Output) {
@@ -67,6 +59,21 @@ impl<const FFT_OUT: usize> AggregatedBinsBuilder<FFT_OUT, SHAZAM_SCALE_OUT>
             &mut output.0.0,
         )
     }
+
+    /// linear like [`Self::loudness_into`]. this skips the conversion to decibels that the other builders do
+    #[inline]
+    fn loudness_into_with(
+        &self,
+        spectrum: &FftOutputs<FFT_OUT>,
+        aggregation: Aggregation,
+        output: &mut Self::Output,
+    ) {
+        self.aggregate_power_into(
+            aggregation,
+            spectrum.iter_mean_square_power_density(),
+            &mut output.0.0,
+        )
+    }
 }
 
 /// turn a frequency into a bin for shazam
@@ -83,7 +90,9 @@ pub const fn shazam_band(f: f32) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
-    use super::shazam_band;
+    use num::Complex;
+
+    use super::*;
 
     #[test]
     fn test_shazam_scale() {
@@ -101,4 +110,29 @@ mod tests {
         // TODO: i might actually want to go higher than this to get to 18 or 20kHz
         assert_eq!(shazam_band(f32::MAX), None);
     }
+
+    #[test]
+    fn test_loudness_with_is_linear() {
+        const BINS: usize = 512;
+
+        let builder = ShazamScaleBuilder::<BINS>::new(44_100.0);
+
+        // bin 4 is ~172 Hz. that is in band 2
+        let mut spectrum = [Complex::new(0.0, 0.0); BINS];
+        spectrum[4] = Complex::new(1.0, 0.0);
+        let spectrum = FftOutputs::new(&spectrum);
+
+        let loudness = builder.loudness(&spectrum).0.0;
+        let summed = builder.loudness_with(&spectrum, Aggregation::Sum).0.0;
+
+        assert_eq!(loudness, summed);
+        assert!(loudness[2] > 0.0);
+
+        // no decibels here. silent bands stay at 0.0 instead of going to -inf
+        assert_eq!(loudness[0], 0.0);
+        assert_eq!(
+            builder.loudness_with(&spectrum, Aggregation::Max).0.0[0],
+            0.0
+        );
+    }
 }
@@ -0,0 +1,264 @@
+//! Spectral descriptors. One number per frame for how bright, how noisy, and how loud the sound is.
+//!
+//! These are for picking palettes and modes, not for drawing bars. A high centroid is a bright sound (cymbals, synth
+//! leads). A low centroid is dark (bass, kick). Flatness near 1.0 is noise (rain, applause, a fan). Flatness near 0.0 is
+//! tonal (singing, chords).
+//!
+//! Samples -> SpectralFeatures <- FftOutputs
+use super::{FftOutputs, Samples, bin_to_frequency};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// how much of the power is below the rolloff frequency
+const ROLLOFF_FRACTION: f32 = 0.85;
+
+/// keeps the log of an empty bin from going to -inf. this is what librosa uses
+const LOG_FLOOR: f32 = 1e-10;
+
+/// Descriptors of one frame. Silence is all 0.0.
+///
+/// The spectral ones skip bin 0. The DC offset isn't a sound and it would drag the centroid down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpectralFeatures {
+    /// the "center of mass" of the amplitudes. brightness
+    pub centroid_hz: f32,
+    /// standard deviation of the amplitudes around the centroid. a pure tone is 0.0. noise is wide
+    pub spread_hz: f32,
+    /// 85% of the power is below this frequency
+    pub rolloff_hz: f32,
+    /// geometric mean / arithmetic mean of the power. 0.0 is a pure tone. 1.0 is white noise
+    pub flatness: f32,
+    /// loudest bin / mean bin. big for a pure tone. 1.0 for white noise
+    pub crest: f32,
+    /// rms of the samples (before the window!). a full scale sine is 0.707
+    pub rms: f32,
+    /// fraction of samples where the sign changes. noisy and bright sounds cross more. 2 * hz / sample rate for a sine
+    pub zero_crossing_rate: f32,
+}
+
+impl SpectralFeatures {
+    /// Everything. Use the same samples that went into the FFT, but without the window applied.
+    pub fn new<const S: usize, const N: usize>(
+        samples: &Samples<S>,
+        spectrum: &FftOutputs<'_, N>,
+        sample_rate_hz: f32,
+    ) -> Self {
+        let mut x = Self::from_spectrum(spectrum, sample_rate_hz);
+
+        x.rms = Self::rms(&samples.0);
+        x.zero_crossing_rate = Self::zero_crossing_rate(&samples.0);
+
+        x
+    }
+
+    /// Only the spectral features. `rms` and `zero_crossing_rate` are left at 0.0
+    pub fn from_spectrum<const N: usize>(
+        spectrum: &FftOutputs<'_, N>,
+        sample_rate_hz: f32,
+    ) -> Self {
+        let frequency = |i: usize| bin_to_frequency(i, sample_rate_hz, N);
+
+        let mut amplitude_sum = 0.0;
+        let mut weighted_sum = 0.0;
+        let mut max_amplitude: f32 = 0.0;
+
+        let mut power_sum = 0.0;
+        let mut log_power_sum = 0.0;
+
+        for (i, (amplitude, power)) in spectrum
+            .iter_amplitude()
+            .zip(spectrum.iter_power())
+            .enumerate()
+            .skip(1)
+        {
+            amplitude_sum += amplitude;
+            weighted_sum += amplitude * frequency(i);
+            max_amplitude = max_amplitude.max(amplitude);
+
+            power_sum += power;
+            log_power_sum += power.max(LOG_FLOOR).ln();
+        }
+
+        if amplitude_sum <= 0.0 {
+            return Self::default();
+        }
+
+        let bins = (N - 1) as f32;
+
+        let centroid_hz = weighted_sum / amplitude_sum;
+
+        // second pass for the things that need the totals first
+        let mut variance = 0.0;
+        let mut rolloff_hz = 0.0;
+        let mut power_so_far = 0.0;
+
+        for (i, (amplitude, power)) in spectrum
+            .iter_amplitude()
+            .zip(spectrum.iter_power())
+            .enumerate()
+            .skip(1)
+        {
+            let f = frequency(i);
+
+            variance += amplitude * (f - centroid_hz) * (f - centroid_hz);
+
+            if power_so_far < ROLLOFF_FRACTION * power_sum {
+                power_so_far += power;
+                rolloff_hz = f;
+            }
+        }
+
+        let arithmetic_mean = power_sum / bins;
+        let geometric_mean = (log_power_sum / bins).exp();
+
+        Self {
+            centroid_hz,
+            spread_hz: (variance / amplitude_sum).sqrt(),
+            rolloff_hz,
+            // the approximate exp can go a tiny bit over
+            flatness: (geometric_mean / arithmetic_mean).min(1.0),
+            crest: max_amplitude / (amplitude_sum / bins),
+            rms: 0.0,
+            zero_crossing_rate: 0.0,
+        }
+    }
+
+    pub fn rms(samples: &[f32]) -> f32 {
+        if samples.is_empty() {
+            return 0.0;
+        }
+
+        let sum_squares: f32 = samples.iter().map(|x| x * x).sum();
+
+        (sum_squares / samples.len() as f32).sqrt()
+    }
+
+    /// 0.0 is exactly zero and doesn't count as a crossing. that keeps digital silence from looking like noise
+    pub fn zero_crossing_rate(samples: &[f32]) -> f32 {
+        if samples.len() < 2 {
+            return 0.0;
+        }
+
+        let crossings = samples
+            .windows(2)
+            .filter(|x| (x[0] < 0.0 && x[1] > 0.0) || (x[0] > 0.0 && x[1] < 0.0))
+            .count();
+
+        crossings as f32 / (samples.len() - 1) as f32
+    }
+
+    /// centroid scaled to 0.0 (dark) to 1.0 (bright). `max_hz` is where it hits 1.0. 5 kHz is a good place to start
+    pub fn brightness(&self, max_hz: f32) -> f32 {
+        (self.centroid_hz / max_hz).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::Complex;
+
+    use super::*;
+    use crate::audio::frequency_to_bin;
+
+    const BINS: usize = 512;
+    const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+    #[test]
+    fn test_pure_tone() {
+        let bin = frequency_to_bin(1_000.0, SAMPLE_RATE_HZ, BINS);
+        let hz = bin_to_frequency(bin, SAMPLE_RATE_HZ, BINS);
+
+        let mut spectrum = [Complex::new(0.0, 0.0); BINS];
+        spectrum[bin] = Complex::new(0.0, 3.0);
+
+        let x = SpectralFeatures::from_spectrum(&FftOutputs::new(&spectrum), SAMPLE_RATE_HZ);
+
+        assert!((x.centroid_hz - hz).abs() < 1e-2, "{x:?}");
+        assert!(x.spread_hz < 1e-2, "{x:?}");
+        assert_eq!(x.rolloff_hz, hz);
+        assert!(x.flatness < 1e-3, "{x:?}");
+        assert!((x.crest - (BINS - 1) as f32).abs() < 1e-2, "{x:?}");
+    }
+
+    #[test]
+    fn test_flat_spectrum() {
+        let spectrum = [Complex::new(1.0, 0.0); BINS];
+
+        let x = SpectralFeatures::from_spectrum(&FftOutputs::new(&spectrum), SAMPLE_RATE_HZ);
+
+        let nyquist = SAMPLE_RATE_HZ / 2.0;
+
+        // bins 1..BINS average out to the middle bin
+        let middle = bin_to_frequency(BINS / 2, SAMPLE_RATE_HZ, BINS);
+        assert!((x.centroid_hz - middle).abs() < 1.0, "{x:?}");
+
+        // a uniform distribution's standard deviation is width / sqrt(12)
+        let spread = nyquist / 12.0f32.sqrt();
+        assert!((x.spread_hz - spread).abs() / spread < 0.01, "{x:?}");
+
+        assert!(
+            (x.rolloff_hz / nyquist - ROLLOFF_FRACTION).abs() < 0.01,
+            "{x:?}"
+        );
+        assert!(x.flatness > 0.99, "{x:?}");
+        assert!((x.crest - 1.0).abs() < 1e-3, "{x:?}");
+    }
+
+    /// white noise and a tone are easy to tell apart
+    #[test]
+    fn test_noise_vs_tone() {
+        // a quick and repeatable pseudo random spectrum
+        let mut seed: u32 = 1;
+        let noise: [Complex<f32>; BINS] = core::array::from_fn(|_| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            Complex::new((seed >> 8) as f32 / (1 << 24) as f32 + 0.1, 0.0)
+        });
+
+        let mut tone = [Complex::new(1e-4, 0.0); BINS];
+        tone[frequency_to_bin(440.0, SAMPLE_RATE_HZ, BINS)] = Complex::new(1.0, 0.0);
+        tone[frequency_to_bin(880.0, SAMPLE_RATE_HZ, BINS)] = Complex::new(0.5, 0.0);
+
+        let noise = SpectralFeatures::from_spectrum(&FftOutputs::new(&noise), SAMPLE_RATE_HZ);
+        let tone = SpectralFeatures::from_spectrum(&FftOutputs::new(&tone), SAMPLE_RATE_HZ);
+
+        assert!(noise.flatness > 0.5, "{noise:?}");
+        assert!(tone.flatness < 0.1, "{tone:?}");
+
+        assert!(noise.centroid_hz > tone.centroid_hz);
+        assert!(noise.brightness(5_000.0) > tone.brightness(5_000.0));
+        assert!(tone.crest > noise.crest);
+    }
+
+    #[test]
+    fn test_samples() {
+        let hz = 1_000.0;
+
+        let samples = Samples::<4410>(core::array::from_fn(|i| {
+            (core::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE_HZ).sin()
+        }));
+
+        let spectrum = [Complex::new(1.0, 0.0); BINS];
+
+        let x = SpectralFeatures::new(&samples, &FftOutputs::new(&spectrum), SAMPLE_RATE_HZ);
+
+        assert!(
+            (x.rms - core::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3,
+            "{x:?}"
+        );
+
+        let expected = 2.0 * hz / SAMPLE_RATE_HZ;
+        assert!((x.zero_crossing_rate - expected).abs() < 1e-3, "{x:?}");
+    }
+
+    #[test]
+    fn test_silence() {
+        let spectrum = [Complex::new(0.0, 0.0); BINS];
+        let samples = Samples([0.0; 64]);
+
+        let x = SpectralFeatures::new(&samples, &FftOutputs::new(&spectrum), SAMPLE_RATE_HZ);
+
+        assert_eq!(x, SpectralFeatures::default());
+    }
+}
@@ -0,0 +1,399 @@
+//! Bins that count towards more than one band.
+//!
+//! The `bin_map` on [`AggregatedBinsBuilder`] puts each FFT bin into exactly one band. That is fine up high where a band
+//! is dozens of bins wide, but down low an exponential scale wants bands that are narrower than a bin. Those bands end up
+//! with one bin or no bins at all, and the bass flickers between them.
+//!
+//! Here each band is a triangle (like the mel filters in speech recognition). A bin between two band centers is split
+//! between both bands depending on how close it is to each. Triangles are never narrower than a bin, so every band
+//! always gets something.
+use core::borrow::Borrow;
+
+use super::amplitudes::{AggregatedBins, AggregatedBinsBuilder, Aggregation};
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+/// How much of one bin goes into one band
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BinWeight {
+    pub bin: usize,
+    pub band: usize,
+    pub weight: f32,
+}
+
+/// A sparse map from IN FFT bins to OUT bands where a bin can go into multiple bands with different weights.
+///
+/// N is the most (bin, band) pairs this can hold. The weights have to be pushed in bin order so that aggregating only
+/// needs one pass over the spectrum.
+///
+/// TODO: u16 for bin and band would save some RAM
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WeightedBinMap<const IN: usize, const OUT: usize, const N: usize> {
+    weights: [BinWeight; N],
+    len: usize,
+    /// the total weight that went into each band. this is the fractional version of a bin count
+    band_weights: [f32; OUT],
+}
+
+impl<const IN: usize, const OUT: usize, const N: usize> Default for WeightedBinMap<IN, OUT, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const IN: usize, const OUT: usize, const N: usize> WeightedBinMap<IN, OUT, N> {
+    pub const fn new() -> Self {
+        Self {
+            weights: [BinWeight {
+                bin: 0,
+                band: 0,
+                weight: 0.0,
+            }; N],
+            len: 0,
+            band_weights: [0.0; OUT],
+        }
+    }
+
+    /// Add part of a bin to a band. Weights <= 0 are skipped.
+    pub const fn push(&mut self, bin: usize, band: usize, weight: f32) {
+        assert!(bin < IN);
+        assert!(band < OUT);
+
+        if weight <= 0.0 {
+            return;
+        }
+
+        assert!(self.len < N, "WeightedBinMap is full. increase N");
+        assert!(
+            self.len == 0 || self.weights[self.len - 1].bin <= bin,
+            "weights must be pushed in bin order"
+        );
+
+        self.weights[self.len] = BinWeight { bin, band, weight };
+        self.len += 1;
+
+        self.band_weights[band] += weight;
+    }
+
+    pub fn weights(&self) -> &[BinWeight] {
+        &self.weights[..self.len]
+    }
+
+    pub const fn band_weights(&self) -> &[f32; OUT] {
+        &self.band_weights
+    }
+
+    /// The band that gets the most of each bin. This is for things that only understand one band per bin.
+    pub fn nearest_map(&self) -> [Option<usize>; IN] {
+        let mut map = [None; IN];
+        let mut best = [0.0; IN];
+
+        for w in self.weights() {
+            if w.weight > best[w.bin] {
+                best[w.bin] = w.weight;
+                map[w.bin] = Some(w.band);
+            }
+        }
+
+        map
+    }
+
+    /// Like [`AggregatedBins::aggregate_power_into`], but each bin's power is multiplied by its weight first. The modes
+    /// that divide by the bin count divide by the total weight instead.
+    pub fn aggregate_power_into<I>(
+        &self,
+        aggregation: Aggregation,
+        input_power: I,
+        output: &mut [f32; OUT],
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<f32>,
+    {
+        output.fill(0.0);
+
+        let mut weights = self.weights().iter().peekable();
+
+        for (bin, x) in input_power.into_iter().enumerate() {
+            let x = *x.borrow();
+
+            while let Some(w) = weights.next_if(|w| w.bin == bin) {
+                let x = x * w.weight;
+                let y = &mut output[w.band];
+
+                if let Aggregation::Max = aggregation {
+                    *y = y.max(x);
+                } else {
+                    *y += x;
+                }
+            }
+        }
+
+        for (y, &n) in output.iter_mut().zip(self.band_weights.iter()) {
+            *y = aggregation.finish_weighted(*y, n);
+        }
+    }
+}
+
+/// How to space the band centers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriangularSpacing {
+    /// The same number of bands in every octave. This is what
+    /// [`ExponentialScaleBuilder`](crate::audio::ExponentialScaleBuilder) is going for
+    #[default]
+    Exponential,
+    /// Linear below ~700 Hz and logarithmic above. This is closer to how we hear pitch, so the bass gets fewer bands
+    Mel,
+}
+
+impl TriangularSpacing {
+    fn hz_to_scale(self, hz: f32) -> f32 {
+        match self {
+            Self::Exponential => hz.ln(),
+            Self::Mel => 2595.0 * (1.0 + hz / 700.0).log10(),
+        }
+    }
+
+    fn scale_to_hz(self, x: f32) -> f32 {
+        match self {
+            Self::Exponential => x.exp(),
+            Self::Mel => 700.0 * (10.0f32.powf(x / 2595.0) - 1.0),
+        }
+    }
+}
+
+/// Overlapping triangular bands between `min_freq` and `max_freq`.
+///
+/// Each band's triangle starts at the center of the band below it and ends at the center of the band above it, so in the
+/// middle of the range every bin's weights add up to 1.0. Down low the triangles get widened to at least a bin on each
+/// side, so a bin there can count towards a few bands.
+///
+/// `2 * IN + 3 * OUT` is always a big enough N. `TriangularScaleBuilder::<512, 24, { 2 * 512 + 3 * 24 }>`
+///
+/// TODO: this needs ln so it can't be const yet
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TriangularScaleBuilder<const IN: usize, const OUT: usize, const N: usize> {
+    weights: WeightedBinMap<IN, OUT, N>,
+    /// the band that gets the most of each bin. only used by code that doesn't know about the weights
+    nearest: [Option<usize>; IN],
+}
+
+/// TODO: should this be a trait instead?
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(transparent)]
+pub struct TriangularScaleAmplitudes<const OUT: usize>(pub AggregatedBins<OUT>);
+
+impl<const IN: usize, const OUT: usize, const N: usize> TriangularScaleBuilder<IN, OUT, N> {
+    pub fn new(
+        min_freq: f32,
+        max_freq: f32,
+        sample_rate_hz: f32,
+        spacing: TriangularSpacing,
+    ) -> Self {
+        assert!(OUT > 0);
+        assert!(min_freq > 0.0);
+        assert!(min_freq < max_freq);
+        assert!(
+            sample_rate_hz / 2.0 >= max_freq,
+            "sample rate too low. must be at least double the maximum frequency"
+        );
+
+        let min_scale = spacing.hz_to_scale(min_freq);
+        let max_scale = spacing.hz_to_scale(max_freq);
+
+        // OUT + 2 edges. edge b is the bottom of band b, edge b + 1 is its center, and edge b + 2 is its top
+        // this is a fractional bin. it is `frequency_to_bin` without the rounding
+        let edge = |i: usize| {
+            let x = min_scale + (max_scale - min_scale) * i as f32 / (OUT + 1) as f32;
+
+            spacing.scale_to_hz(x) * (IN * 2) as f32 / sample_rate_hz
+        };
+
+        let mut weights = WeightedBinMap::new();
+
+        // bins on the outside so that the weights get pushed in bin order
+        for bin in 0..IN {
+            for band in 0..OUT {
+                let weight = triangle(bin as f32, edge(band), edge(band + 1), edge(band + 2));
+
+                weights.push(bin, band, weight);
+            }
+        }
+
+        let nearest = weights.nearest_map();
+
+        Self { weights, nearest }
+    }
+
+    pub fn weights(&self) -> &WeightedBinMap<IN, OUT, N> {
+        &self.weights
+    }
+}
+
+/// how much of bin `k` goes into the band. never narrower than a bin on either side so every band catches a bin
+fn triangle(k: f32, lower: f32, center: f32, upper: f32) -> f32 {
+    let rise = (center - lower).max(1.0);
+    let fall = (upper - center).max(1.0);
+
+    let weight = if k <= center {
+        1.0 - (center - k) / rise
+    } else {
+        1.0 - (k - center) / fall
+    };
+
+    weight.max(0.0)
+}
+
+impl<const IN: usize, const OUT: usize, const N: usize> AggregatedBinsBuilder<IN, OUT>
+    for TriangularScaleBuilder<IN, OUT, N>
+{
+    type Output = TriangularScaleAmplitudes<OUT>;
+
+    #[inline]
+    fn as_inner_mut<'a>(&self, output: &'a mut Self::Output) -> &'a mut [f32; OUT] {
+        &mut output.0.0
+    }
+
+    #[inline]
+    fn bin_map(&self) -> &[Option<usize>; IN] {
+        &self.nearest
+    }
+
+    #[inline]
+    fn sum_power_into<I>(&self, input_power: I, output: &mut [f32; OUT])
+    where
+        I: IntoIterator,
+        I::Item: Borrow<f32>,
+    {
+        self.weights
+            .aggregate_power_into(Aggregation::Sum, input_power, output);
+    }
+
+    #[inline]
+    fn aggregate_power_into<I>(
+        &self,
+        aggregation: Aggregation,
+        input_power: I,
+        output: &mut [f32; OUT],
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<f32>,
+    {
+        self.weights
+            .aggregate_power_into(aggregation, input_power, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{ExponentialScaleBuilder, frequency_to_bin};
+
+    const IN: usize = 512;
+    const OUT: usize = 24;
+    const N: usize = 2 * IN + 3 * OUT;
+
+    const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+    #[test]
+    fn test_weighted_map() {
+        let mut map = WeightedBinMap::<4, 2, 8>::new();
+
+        map.push(0, 0, 1.0);
+        map.push(1, 0, 0.75);
+        map.push(1, 1, 0.25);
+        map.push(2, 1, 1.0);
+        // skipped
+        map.push(3, 1, 0.0);
+
+        assert_eq!(map.weights().len(), 4);
+        assert_eq!(map.band_weights(), &[1.75, 1.25]);
+        assert_eq!(map.nearest_map(), [Some(0), Some(0), Some(1), None]);
+
+        let power = [4.0, 8.0, 16.0, 1000.0];
+
+        let mut output = [0.0; 2];
+
+        map.aggregate_power_into(Aggregation::Sum, power, &mut output);
+        assert_eq!(output, [10.0, 18.0]);
+
+        map.aggregate_power_into(Aggregation::Mean, power, &mut output);
+        assert_eq!(output, [10.0 / 1.75, 18.0 / 1.25]);
+
+        map.aggregate_power_into(Aggregation::Max, power, &mut output);
+        assert_eq!(output, [6.0, 16.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "bin order")]
+    fn test_weighted_map_order() {
+        let mut map = WeightedBinMap::<4, 2, 8>::new();
+
+        map.push(1, 0, 1.0);
+        map.push(0, 1, 1.0);
+    }
+
+    /// the exponential scale can't give the lowest bands their own bins. the triangles always can
+    #[test]
+    fn test_low_bands_never_empty() {
+        for spacing in [TriangularSpacing::Exponential, TriangularSpacing::Mel] {
+            let builder =
+                TriangularScaleBuilder::<IN, OUT, N>::new(20.0, 20_000.0, SAMPLE_RATE_HZ, spacing);
+
+            for (band, &n) in builder.weights().band_weights().iter().enumerate() {
+                assert!(n > 0.0, "{spacing:?} band {band} is empty");
+            }
+
+            // a flat spectrum comes out flat
+            let mut output = [0.0; OUT];
+            builder.aggregate_power_into(Aggregation::Mean, [1.0; IN], &mut output);
+            for x in output {
+                assert!((x - 1.0).abs() < 1e-5, "{spacing:?} {output:?}");
+            }
+        }
+
+        // the old way shares bins between the bottom bands
+        let exponential = ExponentialScaleBuilder::<IN, OUT>::new(20.0, 20_000.0, SAMPLE_RATE_HZ);
+        let counts = exponential.bin_counts();
+        assert!(counts[0] <= 1, "{counts:?}");
+    }
+
+    /// a tone between two band centers is split between them
+    #[test]
+    fn test_split_between_bands() {
+        let builder = TriangularScaleBuilder::<IN, OUT, N>::new(
+            20.0,
+            20_000.0,
+            SAMPLE_RATE_HZ,
+            TriangularSpacing::Exponential,
+        );
+
+        let bin = frequency_to_bin(3_000.0, SAMPLE_RATE_HZ, IN);
+
+        let mut power = [0.0; IN];
+        power[bin] = 1.0;
+
+        let mut output = [0.0; OUT];
+        builder.sum_power_into(power, &mut output);
+
+        let lit: Vec<_> = output.iter().filter(|x| **x > 0.0).collect();
+        assert_eq!(lit.len(), 2, "{output:?}");
+
+        // up here the triangles are wide, so the weights add up to the whole bin
+        let total: f32 = output.iter().sum();
+        assert!((total - 1.0).abs() < 1e-5, "{total}");
+
+        // the loudest band is the one the nearest map picks
+        let loudest = output
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i);
+        assert_eq!(builder.bin_map()[bin], loudest);
+    }
+}
@@ -1,18 +1,37 @@
 use crate::audio::bin_to_frequency;
+use crate::windows::const_sqrt;
 
 #[allow(unused_imports)]
 use micromath::F32Ext;
 
-use super::Weighting;
+use super::{Weighting, skip_ends};
+
+/// +2.0 dB. this makes 1 kHz come out at 0 dB
+const A_1000: f64 = 1.258_925_411_794_167_3;
 
 pub struct AWeighting<const N: usize> {
     sample_rate_hz: f32,
+    weights: [f32; N],
 }
 
 impl<const N: usize> AWeighting<N> {
-    /// TODO: create a lookup table, or calculate as needed? a lookup table is easy to add after this
+    /// one A-weighting gain per FFT bin. This is const so that it can go in a `static`
     pub const fn new(sample_rate_hz: f32) -> Self {
-        Self { sample_rate_hz }
+        let mut weights = [0.0; N];
+
+        let mut i = 0;
+        while i < N {
+            let f = bin_to_frequency(i, sample_rate_hz, N);
+
+            weights[i] = skip_ends::<N>(i, a_weighting(f));
+
+            i += 1;
+        }
+
+        Self {
+            sample_rate_hz,
+            weights,
+        }
     }
 }
 
@@ -22,24 +41,29 @@ impl<const N: usize> Weighting<N> for AWeighting<N> {
 
         a_weighting(f)
     }
+
+    #[inline]
+    fn weights(&self) -> &[f32; N] {
+        &self.weights
+    }
 }
 
 pub fn a_weighting_decibels(f: f32) -> f32 {
-    let numerator = (12194f32.powi(2)) * f.powi(4);
-    let denominator = (f.powi(2) + 20.6f32.powi(2))
-        * ((f.powi(2) + 107.7f32.powi(2)) * (f.powi(2) + 737.9f32.powi(2))).sqrt()
-        * (f.powi(2) + 12194f32.powi(2));
-
-    let ra = numerator / denominator;
-
-    20.0 * ra.log10() + 2.0
+    20.0 * a_weighting(f).log10()
 }
 
-/// reminder, multiplying with this is the same as adding decibels
-pub fn a_weighting(f: f32) -> f32 {
-    let a_weighted_decibels = a_weighting_decibels(f);
+/// linear gain for `f`. 1.0 at 1 kHz
+///
+/// This is done in f64 without any logs so that it works in a `const fn`
+pub const fn a_weighting(f: f32) -> f32 {
+    let f2 = f as f64 * f as f64;
+
+    let numerator = 12194.0 * 12194.0 * f2 * f2;
+    let denominator = (f2 + 20.6 * 20.6)
+        * const_sqrt((f2 + 107.7 * 107.7) * (f2 + 737.9 * 737.9))
+        * (f2 + 12194.0 * 12194.0);
 
-    10.0f32.powf(a_weighted_decibels / 20.0)
+    (numerator / denominator * A_1000) as f32
 }
 
 #[cfg(test)]
@@ -50,4 +74,27 @@ mod tests {
     fn test_a_weighting() {
         assert_eq!(a_weighting(0.0), 0.0);
     }
+
+    #[test]
+    fn test_a_weighting_table() {
+        // IEC 61672-1 table 3. the nominal frequencies are rounded. the exact ones are 1000 * 10^(n/10)
+        for (n, expected) in [
+            (-15, -39.4),
+            (-12, -26.2),
+            (-9, -16.1),
+            (-6, -8.6),
+            (-3, -3.2),
+            (0, 0.0),
+            (3, 1.2),
+            (6, 1.0),
+            (9, -1.1),
+            (12, -6.6),
+        ] {
+            let f = 1000.0 * 10.0f32.powf(n as f32 / 10.0);
+
+            let db = a_weighting_decibels(f);
+
+            assert!((db - expected).abs() < 0.1, "{f}: {db}");
+        }
+    }
 }
@@ -0,0 +1,95 @@
+use crate::audio::bin_to_frequency;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use super::{Weighting, skip_ends};
+
+/// +0.06 dB. this makes 1 kHz come out at 0 dB
+const C_1000: f64 = 1.006_931_668_851_804_2;
+
+/// Mostly flat with a gentle roll-off at the very top and bottom. Sound level meters use this for loud (100 dB+) sounds.
+pub struct CWeighting<const N: usize> {
+    sample_rate_hz: f32,
+    weights: [f32; N],
+}
+
+impl<const N: usize> CWeighting<N> {
+    /// one C-weighting gain per FFT bin. This is const so that it can go in a `static`
+    pub const fn new(sample_rate_hz: f32) -> Self {
+        let mut weights = [0.0; N];
+
+        let mut i = 0;
+        while i < N {
+            let f = bin_to_frequency(i, sample_rate_hz, N);
+
+            weights[i] = skip_ends::<N>(i, c_weighting(f));
+
+            i += 1;
+        }
+
+        Self {
+            sample_rate_hz,
+            weights,
+        }
+    }
+}
+
+impl<const N: usize> Weighting<N> for CWeighting<N> {
+    fn weight(&self, i: usize) -> f32 {
+        let f = bin_to_frequency(i, self.sample_rate_hz, N);
+
+        c_weighting(f)
+    }
+
+    #[inline]
+    fn weights(&self) -> &[f32; N] {
+        &self.weights
+    }
+}
+
+pub fn c_weighting_decibels(f: f32) -> f32 {
+    20.0 * c_weighting(f).log10()
+}
+
+/// linear gain for `f`. almost flat, with the ends rolled off below 31.5 Hz and above 8 kHz
+///
+/// This is done in f64 without any logs so that it works in a `const fn`
+pub const fn c_weighting(f: f32) -> f32 {
+    let f2 = f as f64 * f as f64;
+
+    let numerator = 12194.0 * 12194.0 * f2;
+    let denominator = (f2 + 20.6 * 20.6) * (f2 + 12194.0 * 12194.0);
+
+    (numerator / denominator * C_1000) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_weighting() {
+        assert_eq!(c_weighting(0.0), 0.0);
+
+        // IEC 61672-1 table 3. the nominal frequencies are rounded. the exact ones are 1000 * 10^(n/10)
+        for (n, expected) in [
+            (-15, -3.0),
+            (-12, -0.8),
+            (-9, -0.2),
+            (-6, 0.0),
+            (-3, 0.0),
+            (0, 0.0),
+            (3, -0.2),
+            (6, -0.8),
+            (9, -3.0),
+            (12, -8.5),
+        ] {
+            let f = 1000.0 * 10.0f32.powf(n as f32 / 10.0);
+
+            let db = c_weighting_decibels(f);
+
+            assert!((db - expected).abs() < 0.1, "{f}: {db}");
+        }
+    }
+}
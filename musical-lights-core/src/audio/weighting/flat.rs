@@ -2,6 +2,16 @@ use super::Weighting;
 
 pub struct FlatWeighting<const N: usize>;
 
+impl<const N: usize> FlatWeighting<N> {
+    /// the ends are special. the rest are doubled because the fft output is only half the wave
+    const WEIGHTS: &'static [f32; N] = &{
+        let mut weights = [2.0; N];
+        weights[0] = 1.0;
+        weights[N - 1] = 1.0;
+        weights
+    };
+}
+
 impl<const N: usize> Weighting<N> for FlatWeighting<N> {
     fn weight(&self, _i: usize) -> f32 {
         // TODO: skip the first bin or no?
@@ -12,4 +22,27 @@ impl<const N: usize> Weighting<N> for FlatWeighting<N> {
         1.0
         // }
     }
+
+    #[inline]
+    fn weights(&self) -> &[f32; N] {
+        Self::WEIGHTS
+    }
+}
+
+/// Z-weighting ("zero") is the IEC 61672 name for no weighting at all
+pub type ZWeighting<const N: usize> = FlatWeighting<N>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_z_weighting() {
+        let z: ZWeighting<8> = FlatWeighting;
+
+        let curve = z.curve();
+
+        // the ends are special. the rest are doubled because the fft output is only half the wave
+        assert_eq!(curve, [1.0, 2.0, 2.0, 2.0, 2.0, 2.0, 2.0, 1.0]);
+    }
 }
@@ -0,0 +1,173 @@
+//! ISO 226:2023 equal-loudness contours.
+//!
+//! A-weighting is (roughly) the 40-phon contour. That's fine for quiet rooms, but a loud venue is closer to 80 or 90
+//! phon where the bass doesn't need nearly as much help. This lets us pick the contour that matches where we are.
+//!
+//! This is just the constants and the formula, so no licensing is required. Check out the paper though! It's really cool!
+use crate::audio::bin_to_frequency;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use super::{Weighting, weights_from_fn};
+
+/// frequencies for the table below (Hz)
+const ISO226_F: [f32; 29] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0,
+    500.0, 630.0, 800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0,
+    8000.0, 10_000.0, 12_500.0,
+];
+
+/// exponent for loudness perception
+const ISO226_ALPHA: [f32; 29] = [
+    0.635, 0.602, 0.569, 0.537, 0.509, 0.482, 0.456, 0.433, 0.412, 0.391, 0.373, 0.357, 0.343,
+    0.330, 0.320, 0.311, 0.303, 0.300, 0.295, 0.292, 0.290, 0.290, 0.289, 0.289, 0.289, 0.293,
+    0.303, 0.323, 0.354,
+];
+
+/// magnitude of the linear transfer function normalized at 1000 Hz (dB)
+const ISO226_LU: [f32; 29] = [
+    -31.5, -27.2, -23.1, -19.3, -16.1, -13.1, -10.4, -8.2, -6.3, -4.6, -3.2, -2.1, -1.2, -0.5, 0.0,
+    0.4, 0.5, 0.0, -2.7, -4.2, -1.2, 1.4, 2.3, 1.0, -2.3, -7.2, -11.2, -10.9, -3.5,
+];
+
+/// threshold of hearing (dB SPL)
+const ISO226_TF: [f32; 29] = [
+    78.1, 68.7, 59.5, 51.1, 44.0, 37.5, 31.5, 26.5, 22.1, 17.9, 14.4, 11.4, 8.6, 6.2, 4.4, 3.0,
+    2.2, 2.4, 3.5, 1.7, -1.3, -4.2, -6.0, -5.4, -1.5, 6.0, 12.6, 13.9, 12.3,
+];
+
+/// Weight bins with the equal-loudness contour for a given phon level.
+///
+/// The standard only covers 20 to 90 phon. Outside of that still gives numbers, but they are guesses.
+pub struct Iso226Weighting<const N: usize> {
+    sample_rate_hz: f32,
+    phon: f32,
+    weights: [f32; N],
+}
+
+impl<const N: usize> Iso226Weighting<N> {
+    /// TODO: this needs logs and powf so it can't be const. precompute the contours for a few phon levels?
+    pub fn new(sample_rate_hz: f32, phon: f32) -> Self {
+        let weights =
+            weights_from_fn(|i| iso226_weighting(bin_to_frequency(i, sample_rate_hz, N), phon));
+
+        Self {
+            sample_rate_hz,
+            phon,
+            weights,
+        }
+    }
+}
+
+impl<const N: usize> Weighting<N> for Iso226Weighting<N> {
+    fn weight(&self, i: usize) -> f32 {
+        let f = bin_to_frequency(i, self.sample_rate_hz, N);
+
+        iso226_weighting(f, self.phon)
+    }
+
+    #[inline]
+    fn weights(&self) -> &[f32; N] {
+        &self.weights
+    }
+}
+
+/// the sound pressure level (dB SPL) of a tone at `f` that sounds as loud as a 1 kHz tone at `phon` dB SPL
+///
+/// The table only goes from 20 Hz to 12.5 kHz. Frequencies outside of that use the closest end.
+pub fn iso226_spl(f: f32, phon: f32) -> f32 {
+    let (alpha, lu, tf) = iso226_params(f);
+
+    let a = 4e-10f32.powf(0.3 - alpha) * (10f32.powf(0.03 * phon) - 10f32.powf(0.072))
+        + 10f32.powf(alpha * (tf + lu) / 10.0);
+
+    10.0 / alpha * a.log10() - lu
+}
+
+/// how much to adjust (dB) a tone at `f` so that it sounds as loud as the same level at 1 kHz
+pub fn iso226_weighting_decibels(f: f32, phon: f32) -> f32 {
+    phon - iso226_spl(f, phon)
+}
+
+/// linear gain for `f` at `phon`
+pub fn iso226_weighting(f: f32, phon: f32) -> f32 {
+    10.0f32.powf(iso226_weighting_decibels(f, phon) / 20.0)
+}
+
+/// interpolate the table on a log frequency scale
+fn iso226_params(f: f32) -> (f32, f32, f32) {
+    let last = ISO226_F.len() - 1;
+
+    if f <= ISO226_F[0] {
+        return (ISO226_ALPHA[0], ISO226_LU[0], ISO226_TF[0]);
+    }
+
+    if f >= ISO226_F[last] {
+        return (ISO226_ALPHA[last], ISO226_LU[last], ISO226_TF[last]);
+    }
+
+    // the table is small. a linear search is fine
+    let i = ISO226_F.iter().position(|&x| x > f).unwrap() - 1;
+
+    let t = (f / ISO226_F[i]).ln() / (ISO226_F[i + 1] / ISO226_F[i]).ln();
+
+    let lerp = |x: &[f32; 29]| x[i] + t * (x[i + 1] - x[i]);
+
+    (lerp(&ISO226_ALPHA), lerp(&ISO226_LU), lerp(&ISO226_TF))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1khz_is_reference() {
+        for phon in [20.0, 40.0, 60.0, 80.0, 90.0] {
+            assert!((iso226_spl(1000.0, phon) - phon).abs() < 0.01);
+            assert!((iso226_weighting(1000.0, phon) - 1.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_iso226_table_points() {
+        // (f, phon, dB SPL) read off the 2023 contours
+        for (f, phon, expected) in [
+            (20.0, 40.0, 99.7),
+            (100.0, 40.0, 64.2),
+            (100.0, 60.0, 78.5),
+            (3150.0, 60.0, 56.4),
+            (12_500.0, 80.0, 85.6),
+        ] {
+            let spl = iso226_spl(f, phon);
+
+            assert!((spl - expected).abs() < 1.0, "{f} Hz at {phon} phon: {spl}");
+        }
+    }
+
+    #[test]
+    fn test_loud_needs_less_bass() {
+        let quiet = iso226_weighting(50.0, 30.0);
+        let loud = iso226_weighting(50.0, 90.0);
+
+        assert!(quiet < loud);
+        assert!(loud < 1.0);
+    }
+
+    #[test]
+    fn test_interpolation_is_smooth() {
+        let mut f = 20.0;
+        let mut prev = iso226_spl(f, 60.0);
+
+        // no big jumps between the points in the table
+        while f < 12_500.0 {
+            f *= 1.02;
+
+            let spl = iso226_spl(f, 60.0);
+
+            assert!((spl - prev).abs() < 1.0, "{f}: {prev} -> {spl}");
+
+            prev = spl;
+        }
+    }
+}
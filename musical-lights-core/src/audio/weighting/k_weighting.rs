@@ -0,0 +1,231 @@
+//! ITU-R BS.1770 K-weighting. This is what LUFS meters use.
+//!
+//! It's a high shelf (about +4 dB above 2 kHz, for the acoustic effect of a head) followed by a high pass (the "RLB"
+//! filter that removes the very low bass). The standard gives coefficients for 48 kHz. These are the analog prototypes
+//! so that any sample rate works.
+use biquad::{Biquad, Coefficients, DirectForm2Transposed};
+use core::f32::consts::PI;
+use num::Complex;
+
+use crate::audio::bin_to_frequency;
+
+#[allow(unused_imports)]
+use micromath::F32Ext;
+
+use super::{Weighting, weights_from_fn};
+
+const SHELF_F0: f32 = 1_681.974_5;
+const SHELF_GAIN_DB: f32 = 3.999_843_8;
+const SHELF_Q: f32 = 0.707_175_24;
+/// how much of the shelf gain is at the band edge
+const SHELF_VB_EXP: f32 = 0.499_666_78;
+
+const HIGH_PASS_F0: f32 = 38.135_47;
+const HIGH_PASS_Q: f32 = 0.500_327_04;
+
+/// The two filter stages for a sample rate
+fn k_weighting_coefficients(sample_rate_hz: f32) -> [Coefficients<f32>; 2] {
+    // stage 1: high shelf
+    let k = (PI * SHELF_F0 / sample_rate_hz).tan();
+    let vh = 10.0f32.powf(SHELF_GAIN_DB / 20.0);
+    let vb = vh.powf(SHELF_VB_EXP);
+    let a0 = 1.0 + k / SHELF_Q + k * k;
+
+    let shelf = Coefficients {
+        b0: (vh + vb * k / SHELF_Q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / SHELF_Q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / SHELF_Q + k * k) / a0,
+    };
+
+    // stage 2: high pass. the standard doesn't normalize the numerator, so neither do we
+    let k = (PI * HIGH_PASS_F0 / sample_rate_hz).tan();
+    let a0 = 1.0 + k / HIGH_PASS_Q + k * k;
+
+    let high_pass = Coefficients {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / HIGH_PASS_Q + k * k) / a0,
+    };
+
+    [shelf, high_pass]
+}
+
+/// gain of a biquad at a frequency
+fn biquad_response(c: &Coefficients<f32>, f: f32, sample_rate_hz: f32) -> f32 {
+    let w = 2.0 * PI * f / sample_rate_hz;
+
+    // z^-1 and z^-2
+    let z1 = Complex::new(w.cos(), -w.sin());
+    let z2 = z1 * z1;
+
+    let numerator = z2 * c.b2 + z1 * c.b1 + c.b0;
+    let denominator = z2 * c.a2 + z1 * c.a1 + 1.0;
+
+    numerator.norm() / denominator.norm()
+}
+
+/// K-weighting as a curve on FFT bins
+pub struct KWeighting<const N: usize> {
+    sample_rate_hz: f32,
+    coefficients: [Coefficients<f32>; 2],
+    weights: [f32; N],
+}
+
+impl<const N: usize> KWeighting<N> {
+    /// TODO: the biquad coefficients need tan, so this can't be const
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let coefficients = k_weighting_coefficients(sample_rate_hz);
+
+        let weights = weights_from_fn(|i| {
+            let f = bin_to_frequency(i, sample_rate_hz, N);
+
+            coefficients
+                .iter()
+                .map(|c| biquad_response(c, f, sample_rate_hz))
+                .product()
+        });
+
+        Self {
+            sample_rate_hz,
+            coefficients,
+            weights,
+        }
+    }
+}
+
+impl<const N: usize> Weighting<N> for KWeighting<N> {
+    fn weight(&self, i: usize) -> f32 {
+        let f = bin_to_frequency(i, self.sample_rate_hz, N);
+
+        self.coefficients
+            .iter()
+            .map(|c| biquad_response(c, f, self.sample_rate_hz))
+            .product()
+    }
+
+    #[inline]
+    fn weights(&self) -> &[f32; N] {
+        &self.weights
+    }
+}
+
+/// K-weighting as a pre-filter for time-domain samples.
+///
+/// Run samples through this before giving them to [`FilterBank::push_samples`](crate::audio::FilterBank::push_samples).
+pub struct KWeightingFilter {
+    shelf: DirectForm2Transposed<f32>,
+    high_pass: DirectForm2Transposed<f32>,
+}
+
+impl KWeightingFilter {
+    pub fn new(sample_rate_hz: f32) -> Self {
+        let [shelf, high_pass] = k_weighting_coefficients(sample_rate_hz);
+
+        Self {
+            shelf: DirectForm2Transposed::<f32>::new(shelf),
+            high_pass: DirectForm2Transposed::<f32>::new(high_pass),
+        }
+    }
+
+    #[inline]
+    pub fn run(&mut self, x: f32) -> f32 {
+        self.high_pass.run(self.shelf.run(x))
+    }
+
+    pub fn run_in_place(&mut self, samples: &mut [f32]) {
+        for x in samples.iter_mut() {
+            *x = self.run(*x);
+        }
+    }
+}
+
+/// linear gain of the two K-weighting biquads at `f`. the shelf makes this go above 1.0 in the treble
+pub fn k_weighting(f: f32, sample_rate_hz: f32) -> f32 {
+    k_weighting_coefficients(sample_rate_hz)
+        .iter()
+        .map(|c| biquad_response(c, f, sample_rate_hz))
+        .product()
+}
+
+pub fn k_weighting_decibels(f: f32, sample_rate_hz: f32) -> f32 {
+    20.0 * k_weighting(f, sample_rate_hz).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_48khz_coefficients() {
+        // ITU-R BS.1770-4 tables 1 and 2
+        let [shelf, high_pass] = k_weighting_coefficients(48_000.0);
+
+        for (actual, expected) in [
+            (shelf.b0, 1.535_124_8),
+            (shelf.b1, -2.691_696_2),
+            (shelf.b2, 1.198_392_8),
+            (shelf.a1, -1.690_659_3),
+            (shelf.a2, 0.732_480_8),
+            (high_pass.b0, 1.0),
+            (high_pass.b1, -2.0),
+            (high_pass.b2, 1.0),
+            (high_pass.a1, -1.990_047_5),
+            (high_pass.a2, 0.990_072_3),
+        ] {
+            assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_k_weighting_curve() {
+        // it is not normalized at 1 kHz! that is where the -0.691 in the LUFS formula comes from
+        assert!((k_weighting_decibels(1000.0, 48_000.0) - 0.691).abs() < 0.01);
+
+        // the shape from ITU-R BS.1770 figures 1 and 2
+        for (f, expected) in [(100.0, -1.1), (10_000.0, 4.0)] {
+            let db = k_weighting_decibels(f, 48_000.0);
+
+            assert!((db - expected).abs() < 0.1, "{f}: {db}");
+        }
+
+        assert!(k_weighting_decibels(20.0, 48_000.0) < -10.0);
+    }
+
+    #[test]
+    fn test_filter_matches_curve() {
+        let sample_rate_hz = 48_000.0;
+
+        for f in [50.0, 1000.0, 8000.0] {
+            let mut filter = KWeightingFilter::new(sample_rate_hz);
+
+            let mut samples: [f32; 9600] =
+                core::array::from_fn(|i| (2.0 * PI * f * i as f32 / sample_rate_hz).sin());
+
+            filter.run_in_place(&mut samples);
+
+            // skip the start while the filter settles. the sine has an rms of 1/sqrt(2)
+            let settled = &samples[4800..];
+            let rms = (settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt();
+            let gain = rms * core::f32::consts::SQRT_2;
+
+            let expected = k_weighting(f, sample_rate_hz);
+
+            assert!((gain - expected).abs() < 0.01, "{f}: {gain} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_bin_curve() {
+        let weighting = KWeighting::<1024>::new(48_000.0);
+
+        let bin = 1000.0 / (24_000.0 / 1024.0);
+
+        let db = 20.0 * weighting.weight(bin as usize).log10();
+
+        assert!(db > 0.5 && db < 0.9, "{db}");
+    }
+}
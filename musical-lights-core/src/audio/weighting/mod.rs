@@ -1,9 +1,14 @@
 mod a_weighting;
+mod c_weighting;
 mod flat;
+mod iso226;
+mod k_weighting;
 
 pub use a_weighting::AWeighting;
-pub use flat::FlatWeighting;
-use itertools::Itertools;
+pub use c_weighting::{CWeighting, c_weighting, c_weighting_decibels};
+pub use flat::{FlatWeighting, ZWeighting};
+pub use iso226::{Iso226Weighting, iso226_spl, iso226_weighting, iso226_weighting_decibels};
+pub use k_weighting::{KWeighting, KWeightingFilter, k_weighting, k_weighting_decibels};
 
 /// similar to Windows, but different enough that I think we want a dedicated type
 /// TODO: still really unsure about this. needing to move things around to the heap/boxes has confused me.
@@ -11,38 +16,55 @@ pub trait Weighting<const N: usize> {
     /// the linear weight (NOT in db!).
     fn weight(&self, n: usize) -> f32;
 
+    /// The whole curve (with the ends skipped like [`Self::weight_skip_ends`]), calculated when the weighting is created.
+    ///
+    /// [`BufferedFFT`](crate::audio::BufferedFFT) multiplies by this after every FFT, so it needs to be a lookup table.
+    fn weights(&self) -> &[f32; N];
+
     /// bin 0 is special. we want to leave it alone
     /// the last bin is also apparently special.
     /// we also double it because the fft outputs are only half the wave. i think thats correct.
     /// TODO: think more about this. read more
-    /// TODO: this can't be const because we are in a trait. noooo
     fn weight_skip_ends(&self, n: usize) -> f32 {
-        if n == 0 || n == N - 1 {
-            1.0
-        } else {
-            self.weight(n) * 2.0
-        }
+        skip_ends::<N>(n, self.weight(n))
     }
 
     #[inline]
     fn curve(&self) -> [f32; N] {
-        let mut window = [0.0; N];
-
-        self.curve_buf(&mut window);
-
-        window
+        *self.weights()
     }
 
     /// TODO: whats the rusty name for this?
     /// TODO: what if we want the decibels adjustment instead of the
     #[inline]
     fn curve_buf(&self, output: &mut [f32; N]) {
-        output.iter_mut().set_from(self.curve_iter());
+        output.copy_from_slice(self.weights());
     }
 
     /// Iterators are cool. They aren't const, but traits aren't either so I guess its fine.
     #[inline]
     fn curve_iter(&self) -> impl Iterator<Item = f32> {
-        (0..N).map(|x| self.weight_skip_ends(x))
+        self.weights().iter().copied()
+    }
+}
+
+/// [`Weighting::weight_skip_ends`] for use in a `const fn`
+#[inline]
+pub(crate) const fn skip_ends<const N: usize>(n: usize, weight: f32) -> f32 {
+    if n == 0 || n == N - 1 {
+        1.0
+    } else {
+        weight * 2.0
     }
 }
+
+/// build the lookup table for [`Weighting::weights`] at runtime. for weightings that are too complicated for a `const fn`
+pub(crate) fn weights_from_fn<const N: usize>(weight: impl Fn(usize) -> f32) -> [f32; N] {
+    let mut weights = [0.0; N];
+
+    for (n, x) in weights.iter_mut().enumerate() {
+        *x = skip_ends::<N>(n, weight(n));
+    }
+
+    weights
+}
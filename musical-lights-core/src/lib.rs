@@ -21,7 +21,6 @@ pub mod message;
 pub mod orientation;
 pub mod radio;
 pub mod sd;
-pub mod speaker;
 pub mod windows;
 
 /// Map t in range [a, b] to range [c, d]
@@ -2,7 +2,7 @@
 use core::fmt::Display;
 
 use super::Gradient;
-use crate::audio::AggregatedBins;
+use crate::audio::{AggregatedBins, novelty_sparkle};
 use crate::lights::{Layout, SnakeXY};
 use crate::logging::{debug, info, trace};
 use crate::remap;
@@ -62,6 +62,8 @@ pub struct DancingLights<const X: usize, const Y: usize, const N: usize> {
     pub peak_max: f32,
     /// how fast to decay peak_max
     pub decay_alpha: f32,
+    /// recent maximum novelty of each band. decays over time
+    novelty_peak: [f32; Y],
 }
 
 /// TODO: macro for all the different inverts
@@ -106,11 +108,27 @@ impl<const X: usize, const Y: usize, const N: usize> DancingLights<X, Y, N> {
             fbuf,
             peak_max,
             decay_alpha,
+            novelty_peak: [0.0; Y],
         }
     }
 
     /// TODO: this X/Y is the opposite of how i usually think of things
     pub fn update(&mut self, loudness: AggregatedBins<Y>) {
+        self.update_inner(loudness, None);
+    }
+
+    /// Like `update`, but bands only sparkle on real transients instead of any time they went up.
+    ///
+    /// `novelty` is probably from [`SpectralFlux`](crate::audio::SpectralFlux).
+    pub fn update_with_novelty(
+        &mut self,
+        loudness: AggregatedBins<Y>,
+        novelty: &AggregatedBins<Y>,
+    ) {
+        self.update_inner(loudness, Some(novelty));
+    }
+
+    fn update_inner(&mut self, loudness: AggregatedBins<Y>, novelty: Option<&AggregatedBins<Y>>) {
         trace!("{:?}", loudness);
 
         // TODO: we want a recent min/max (with decay), not just the min/max from the current frame
@@ -148,6 +166,12 @@ impl<const X: usize, const Y: usize, const N: usize> DancingLights<X, Y, N> {
             // *channel = scaled.max((*channel).saturating_sub(1));
             *channel = scaled;
 
+            let sparkle = match novelty {
+                // TODO: this sparkles on noise a lot. prefer update_with_novelty
+                None => *channel > last,
+                Some(novelty) => novelty_sparkle(novelty.0[y], &mut self.novelty_peak[y]),
+            };
+
             // get the index of the first pixel of the row. this always has the color we want
             let bottom_n = SnakeXY::xy_to_n(0, y, X);
 
@@ -158,7 +182,7 @@ impl<const X: usize, const Y: usize, const N: usize> DancingLights<X, Y, N> {
             for x in BOTTOM_BORDER..(X - TOP_BORDER) {
                 let n = SnakeXY::xy_to_n(x, y, X);
 
-                if x == *channel as usize && x > last as usize {
+                if x == *channel as usize && sparkle {
                     // if it went up, do something special. maybe just bump the brightness instead of going full silver
                     self.fbuf[n] = SILVER;
                 } else if x <= *channel as usize {
@@ -202,4 +226,33 @@ impl<const X: usize, const Y: usize, const N: usize> DancingLights<X, Y, N> {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use smart_leds::colors::RED;
+
+    #[test]
+    fn test_sparkle_on_novelty() {
+        let gradient = Gradient::<2>::new(core::iter::repeat(RED));
+
+        let mut lights = DancingLights::<8, 2, 16>::new(gradient, 0.5);
+
+        let loudness = || AggregatedBins([0.0, 0.0]);
+        let row_sparkles = |lights: &DancingLights<8, 2, 16>, y: usize| {
+            (0..8).any(|x| lights.fbuf[SnakeXY::xy_to_n(x, y, 8)] == SILVER)
+        };
+
+        // no novelty means no sparkles, even though the bands went up from nothing
+        lights.update_with_novelty(loudness(), &AggregatedBins([0.0, 0.0]));
+        assert!(!row_sparkles(&lights, 0));
+        assert!(!row_sparkles(&lights, 1));
+
+        // a transient in the first band
+        lights.update_with_novelty(loudness(), &AggregatedBins([1.0, 0.0]));
+        assert!(row_sparkles(&lights, 0));
+        assert!(!row_sparkles(&lights, 1));
+
+        // a much smaller transient right after a big one isn't interesting
+        lights.update_with_novelty(loudness(), &AggregatedBins([0.1, 0.0]));
+        assert!(!row_sparkles(&lights, 0));
+    }
+}
@@ -1,2 +0,0 @@
-// fn frequency_to_midi(f: f32) -> int
-// int n = (int) ( ( 12 * log(f / 220.0) / log(2.0) ) + 57.01 );
@@ -0,0 +1,15 @@
+use super::{Window, cosine_sum_window};
+
+/// Wider main lobe than Hann, but much less leakage into far away bins
+pub struct BlackmanWindow<const N: usize>;
+
+impl<const N: usize> Window<N> for BlackmanWindow<N> {
+    const WINDOW: &'static [f32; N] = &cosine_sum_window([0.42, 0.5, 0.08]);
+}
+
+/// 4-term Blackman-Harris. Sidelobes are 92 dB down, so quiet notes next to loud ones still show up.
+pub struct BlackmanHarrisWindow<const N: usize>;
+
+impl<const N: usize> Window<N> for BlackmanHarrisWindow<N> {
+    const WINDOW: &'static [f32; N] = &cosine_sum_window([0.35875, 0.48829, 0.14128, 0.01168]);
+}
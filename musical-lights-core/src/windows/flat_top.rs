@@ -0,0 +1,52 @@
+use super::{Window, cosine_sum_window};
+
+/// A very wide main lobe with a flat top. A sine anywhere in a bin comes out at (almost) the same amplitude.
+///
+/// Use this for measuring levels. It's bad at telling close frequencies apart.
+pub struct FlatTopWindow<const N: usize>;
+
+impl<const N: usize> Window<N> for FlatTopWindow<N> {
+    /// the coefficients from matlab's `flattopwin`
+    const WINDOW: &'static [f32; N] = &cosine_sum_window([
+        0.215_578_95,
+        0.416_631_58,
+        0.277_263_158,
+        0.083_578_947,
+        0.006_947_368,
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audio::{BufferedFFT, FlatWeighting, Samples, bin_to_frequency},
+        windows::HanningWindow,
+    };
+
+    /// the peak amplitude of a sine that is `offset` bins away from the center of bin 64
+    fn peak<W: Window<1024>>(offset: f32) -> f32 {
+        let sample_rate_hz = 44_100.0;
+        let frequency = bin_to_frequency(64, sample_rate_hz, 512) * (64.0 + offset) / 64.0;
+
+        let mut fft = BufferedFFT::<1024, 1024, 512, W, FlatWeighting<512>>::new(FlatWeighting);
+
+        fft.push_samples(&Samples(core::array::from_fn(|i| {
+            (core::f32::consts::TAU * frequency * i as f32 / sample_rate_hz).sin()
+        })));
+
+        fft.fft().iter_amplitude().fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn test_scalloping_loss() {
+        let db = |x: f32| 20.0 * x.log10();
+
+        // halfway between two bins is the worst case
+        let hann = db(peak::<HanningWindow<1024>>(0.5) / peak::<HanningWindow<1024>>(0.0));
+        let flat_top = db(peak::<FlatTopWindow<1024>>(0.5) / peak::<FlatTopWindow<1024>>(0.0));
+
+        assert!((hann - -1.42).abs() < 0.05, "{hann}");
+        assert!(flat_top.abs() < 0.02, "{flat_top}");
+    }
+}
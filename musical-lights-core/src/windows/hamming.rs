@@ -0,0 +1,8 @@
+use super::{Window, cosine_sum_window};
+
+/// Like Hann, but it doesn't go all the way to zero at the edges. The closest sidelobe is lower, but the far ones don't fall off.
+pub struct HammingWindow<const N: usize>;
+
+impl<const N: usize> Window<N> for HammingWindow<N> {
+    const WINDOW: &'static [f32; N] = &cosine_sum_window([0.54, 0.46]);
+}
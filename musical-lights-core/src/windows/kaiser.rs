@@ -0,0 +1,61 @@
+use super::{Window, const_bessel_i0, const_sqrt};
+
+/// A window with a knob. Bigger beta means lower sidelobes but a wider main lobe.
+///
+/// Const generics can't be floats, so the beta is in tenths. `KaiserWindow<N, 86>` is beta = 8.6, which is close to Blackman.
+/// - 0 is the same as [`FlatWindow`](super::FlatWindow)
+/// - 50 is close to Hamming
+/// - 60 is close to Hann
+pub struct KaiserWindow<const N: usize, const BETA_TENTHS: u32>;
+
+impl<const N: usize, const BETA_TENTHS: u32> Window<N> for KaiserWindow<N, BETA_TENTHS> {
+    const WINDOW: &'static [f32; N] = &kaiser_window(BETA_TENTHS as f64 / 10.0);
+}
+
+/// w(i) = I0(beta * sqrt(1 - (2i/N - 1)^2)) / I0(beta)
+const fn kaiser_window<const N: usize>(beta: f64) -> [f32; N] {
+    let mut window = [0.0; N];
+
+    let denominator = const_bessel_i0(beta);
+
+    let mut i = 0;
+    while i < N {
+        let r = 2.0 * i as f64 / N as f64 - 1.0;
+
+        window[i] = (const_bessel_i0(beta * const_sqrt(1.0 - r * r)) / denominator) as f32;
+
+        i += 1;
+    }
+
+    window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::FlatWindow;
+
+    #[test]
+    fn test_beta_zero_is_flat() {
+        assert_eq!(KaiserWindow::<64, 0>::WINDOW, FlatWindow::<64>::WINDOW);
+    }
+
+    #[test]
+    fn test_kaiser() {
+        type K = KaiserWindow<1024, 86>;
+
+        // the edge is 1 / I0(8.6)
+        assert!(
+            (K::WINDOW[0] - 1.0 / 750.461_2).abs() < 1e-7,
+            "{}",
+            K::WINDOW[0]
+        );
+
+        // bigger beta, narrower window
+        assert!(KaiserWindow::<1024, 140>::coherent_gain() < K::coherent_gain());
+        assert!(K::coherent_gain() < KaiserWindow::<1024, 50>::coherent_gain());
+
+        // close to blackman
+        assert!((K::enbw() - 1.73).abs() < 0.05, "{}", K::enbw());
+    }
+}
@@ -1,46 +1,60 @@
+//! Window functions for the FFT.
+//!
+//! Every window is a lookup table that is built at compile time. The Cortex-M0 doesn't have an FPU, so calculating
+//! trig for every sample of every FFT is way too slow.
+//!
+//! TODO: which window is best for music? Hann is the usual choice. Blackman-Harris has less leakage. Flat-top measures amplitudes best.
+mod blackman;
 mod flat;
+mod flat_top;
+mod hamming;
 mod hanning;
+mod kaiser;
 
-use crate::logging::info;
-
+pub use blackman::{BlackmanHarrisWindow, BlackmanWindow};
 pub use flat::FlatWindow;
+pub use flat_top::FlatTopWindow;
+pub use hamming::HammingWindow;
 pub use hanning::HanningWindow;
+pub use kaiser::KaiserWindow;
 
 /// TODO: really not sure about this anymore
 pub trait Window<const N: usize> {
-    /// since the windows have some part of them reduced from their original value, we need to get them back to 1.0 after doing an FFT.
-    /// TODO: think more about this
-    fn output_scaling() -> f32 {
-        let sum_windows: f32 = Self::input_windows_iter().sum::<f32>();
-
-        // let coherent_gain = sum_windows / N as f32;
-        // 1.0 / coherent_gain
-
-        let scaling = N as f32 / sum_windows;
-
-        info!("scaling: {}", scaling);
+    /// The whole window, calculated at compile time.
+    ///
+    /// This is a reference so that the table is stored once in flash instead of copied onto the stack.
+    const WINDOW: &'static [f32; N];
 
-        scaling
+    /// The average of the window. A sine wave that is centered on a bin comes out of the FFT this much quieter.
+    fn coherent_gain() -> f32 {
+        Self::input_windows_iter().sum::<f32>() / N as f32
     }
 
-    fn input_window(i: usize) -> f32;
+    /// Equivalent noise bandwidth (in bins). Noise spreads across this many bins, so wider windows measure more noise.
+    fn enbw() -> f32 {
+        let sum: f32 = Self::input_windows_iter().sum();
+        let sum_squares: f32 = Self::input_windows_iter().map(|x| x * x).sum();
 
-    /// TODO: cache this? or rewrite it so that it can be const. can't use for loops in const though
-    fn input_windows() -> [f32; N] {
-        let mut window = [0.0; N];
+        N as f32 * sum_squares / (sum * sum)
+    }
 
-        for (i, sample) in window.iter_mut().enumerate() {
-            *sample = Self::input_window(i);
+    /// since the windows have some part of them reduced from their original value, we need to get them back to 1.0 after doing an FFT.
+    ///
+    /// This is 1.0 / [`Self::coherent_gain`], calculated once at compile time.
+    const OUTPUT_SCALING: f32 = output_scaling(Self::WINDOW);
 
-            info!("{} = {}", i, sample);
-        }
+    #[inline]
+    fn input_window(i: usize) -> f32 {
+        Self::WINDOW[i]
+    }
 
-        window
+    fn input_windows() -> [f32; N] {
+        *Self::WINDOW
     }
 
     #[inline]
     fn input_windows_iter() -> impl Iterator<Item = f32> {
-        (0..N).map(|i| Self::input_window(i))
+        Self::WINDOW.iter().copied()
     }
 
     /// TODO: is this a good name? is this the best way to arrange this?
@@ -51,3 +65,204 @@ pub trait Window<const N: usize> {
         }
     }
 }
+
+/// A sum of cosines. Hann, Hamming, Blackman, and flat-top are all this with different coefficients.
+///
+/// w(i) = a0 - a1 * cos(2πi/N) + a2 * cos(4πi/N) - ...
+///
+/// These are "periodic" windows (divide by N, not N - 1). That's the right kind for an FFT.
+pub const fn cosine_sum_window<const N: usize, const K: usize>(coefficients: [f64; K]) -> [f32; N] {
+    let mut window = [0.0; N];
+
+    let mut i = 0;
+    while i < N {
+        let mut x = 0.0;
+
+        let mut k = 0;
+        while k < K {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+
+            x += sign
+                * coefficients[k]
+                * const_cos(core::f64::consts::TAU * (k * i) as f64 / N as f64);
+
+            k += 1;
+        }
+
+        window[i] = x as f32;
+
+        i += 1;
+    }
+
+    window
+}
+
+/// N / sum of the window. Summed in f64 so that big windows don't lose precision
+const fn output_scaling<const N: usize>(window: &[f32; N]) -> f32 {
+    let mut sum = 0.0;
+
+    let mut i = 0;
+    while i < N {
+        sum += window[i] as f64;
+        i += 1;
+    }
+
+    (N as f64 / sum) as f32
+}
+
+/// cos that works in a const fn. Taylor series after moving x into -π..=π
+pub(crate) const fn const_cos(x: f64) -> f64 {
+    use core::f64::consts::{PI, TAU};
+
+    let mut x = x % TAU;
+    if x > PI {
+        x -= TAU;
+    } else if x < -PI {
+        x += TAU;
+    }
+
+    let x2 = x * x;
+
+    let mut sum = 1.0;
+    let mut term = 1.0;
+
+    // 20 terms is way past f64 precision for |x| <= π
+    let mut n = 1;
+    while n <= 20 {
+        term *= -x2 / ((2 * n - 1) * (2 * n)) as f64;
+        sum += term;
+        n += 1;
+    }
+
+    sum
+}
+
+/// sqrt that works in a const fn. Newton's method
+pub(crate) const fn const_sqrt(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    let mut guess = if x > 1.0 { x } else { 1.0 };
+
+    let mut n = 0;
+    while n < 64 {
+        let next = 0.5 * (guess + x / guess);
+
+        if next == guess {
+            break;
+        }
+
+        guess = next;
+        n += 1;
+    }
+
+    guess
+}
+
+/// modified Bessel function of the first kind, order 0. Kaiser windows need this
+const fn const_bessel_i0(x: f64) -> f64 {
+    let half_x2 = (x / 2.0) * (x / 2.0);
+
+    let mut sum = 1.0;
+    let mut term = 1.0;
+
+    let mut k = 1;
+    while k < 100 {
+        term *= half_x2 / (k * k) as f64;
+        sum += term;
+
+        if term < sum * 1e-17 {
+            break;
+        }
+
+        k += 1;
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const N: usize = 1024;
+
+    #[test]
+    fn test_const_math() {
+        for i in -100..=100 {
+            let x = i as f64 * 0.37;
+
+            assert!((const_cos(x) - x.cos()).abs() < 1e-12, "cos({x})");
+            assert!(
+                (const_sqrt(x.abs()) - x.abs().sqrt()).abs() < 1e-12,
+                "sqrt({x})"
+            );
+        }
+
+        // from a table
+        assert!((const_bessel_i0(0.0) - 1.0).abs() < 1e-15);
+        assert!((const_bessel_i0(1.0) - 1.266_065_877_752_008).abs() < 1e-12);
+        assert!((const_bessel_i0(5.0) - 27.239_871_823_604_45).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_hann_table() {
+        // the table should match the formula we used before there were tables
+        for (i, &x) in HanningWindow::<N>::WINDOW.iter().enumerate() {
+            let expected = 0.5 - 0.5 * (core::f32::consts::TAU * i as f32 / N as f32).cos();
+
+            assert!((x - expected).abs() < 1e-6, "{i}: {x} != {expected}");
+        }
+    }
+
+    /// reference values for large N from Harris, "On the Use of Windows for Harmonic Analysis with the DFT" (1978)
+    #[test]
+    fn test_coherent_gain_and_enbw() {
+        fn check<W: Window<N>>(name: &str, coherent_gain: f32, enbw: f32) {
+            assert!(
+                (W::coherent_gain() - coherent_gain).abs() < 1e-3,
+                "{name}: {}",
+                W::coherent_gain()
+            );
+            assert!((W::enbw() - enbw).abs() < 1e-3, "{name}: {}", W::enbw());
+            assert!(
+                (W::OUTPUT_SCALING * coherent_gain - 1.0).abs() < 1e-2,
+                "{name}"
+            );
+        }
+
+        check::<FlatWindow<N>>("flat", 1.0, 1.0);
+        check::<HanningWindow<N>>("hann", 0.5, 1.5);
+        check::<HammingWindow<N>>("hamming", 0.54, 1.3628);
+        check::<BlackmanWindow<N>>("blackman", 0.42, 1.7268);
+        check::<BlackmanHarrisWindow<N>>("blackman-harris", 0.35875, 2.0044);
+        check::<FlatTopWindow<N>>("flat-top", 0.21557895, 3.7702);
+    }
+
+    #[test]
+    fn test_symmetric() {
+        fn check<W: Window<N>>(name: &str) {
+            // periodic windows are symmetric around N/2
+            for i in 1..N / 2 {
+                let a = W::WINDOW[i];
+                let b = W::WINDOW[N - i];
+
+                assert!((a - b).abs() < 1e-6, "{name} {i}: {a} != {b}");
+            }
+
+            // the peak is in the middle
+            assert!(
+                (W::WINDOW[N / 2] - 1.0).abs() < 1e-6,
+                "{name}: {}",
+                W::WINDOW[N / 2]
+            );
+        }
+
+        check::<HanningWindow<N>>("hann");
+        check::<HammingWindow<N>>("hamming");
+        check::<BlackmanWindow<N>>("blackman");
+        check::<BlackmanHarrisWindow<N>>("blackman-harris");
+        check::<KaiserWindow<N, 86>>("kaiser");
+    }
+}
@@ -0,0 +1,299 @@
+//! Golden-file regression tests for the audio pipeline.
+//!
+//! Generated signals go through every analyser and the outputs are compared to the files in `tests/golden/`.
+//! If a change to the scaling is on purpose, look at the diff of the golden files and then regenerate them:
+//!
+//!     UPDATE_GOLDEN=1 cargo test --test golden
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+
+use musical_lights_core::audio::{
+    AggregatedBinsBuilder, BarkBank, BarkScaleBuilder, BufferedFFT, ExponentialScaleBuilder,
+    FlatWeighting, Samples, ShazamScaleBuilder,
+};
+use musical_lights_core::windows::HanningWindow;
+
+const SAMPLE_RATE_HZ: f32 = 44_100.0;
+
+const SAMPLES: usize = 512;
+
+const FFT_IN: usize = 2048;
+
+const FFT_OUT: usize = FFT_IN / 2;
+
+/// ~0.19 seconds. enough for the fft buffer to fill up a few times over
+const FRAMES: usize = 16;
+
+const EXPONENTIAL_BANDS: usize = 16;
+
+type TestFFT = BufferedFFT<SAMPLES, FFT_IN, FFT_OUT, HanningWindow<FFT_IN>, FlatWeighting<FFT_OUT>>;
+
+/// how close a value needs to be to the golden value
+#[derive(Clone, Copy)]
+struct Tolerance {
+    /// values below this are all treated the same. the bottom of a dB scale is just rounding noise
+    floor: f32,
+    absolute: f32,
+}
+
+/// for anything in dB
+const DB: Tolerance = Tolerance {
+    floor: -100.0,
+    absolute: 0.05,
+};
+
+/// for [`BarkBank`]. its outputs are 0.0 to 1.0
+const UNIT: Tolerance = Tolerance {
+    floor: f32::NEG_INFINITY,
+    absolute: 1e-3,
+};
+
+/// the fixed-point biquads round differently than the f32 ones, so they get their own golden files.
+///
+///     UPDATE_GOLDEN=1 cargo test --test golden --features fixed-point
+const BARK_BANK: &str = if cfg!(feature = "fixed-point") {
+    "bark_bank_fixed"
+} else {
+    "bark_bank"
+};
+
+/// the test signals. every one is `FRAMES * SAMPLES` long
+fn signals() -> Vec<(&'static str, Vec<f32>)> {
+    let len = FRAMES * SAMPLES;
+
+    let sine = |f: f64, amplitude: f64| -> Vec<f32> {
+        (0..len)
+            .map(|i| (amplitude * (2.0 * PI * f * i as f64 / SAMPLE_RATE_HZ as f64).sin()) as f32)
+            .collect()
+    };
+
+    vec![
+        ("silence", vec![0.0; len]),
+        ("sine_100", sine(100.0, 0.5)),
+        ("sine_440", sine(440.0, 0.5)),
+        ("sine_1000_quiet", sine(1000.0, 0.05)),
+        ("sine_5000", sine(5000.0, 0.5)),
+        ("pink_noise", pink_noise(len)),
+        ("sweep", sweep(len, 20.0, 20_000.0)),
+    ]
+}
+
+/// Paul Kellett's "refined" pink noise filter on top of white noise from a seeded xorshift. the same every run
+fn pink_noise(len: usize) -> Vec<f32> {
+    let mut state: u32 = 0x1234_5678;
+
+    let mut white = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        state as f64 / u32::MAX as f64 * 2.0 - 1.0
+    };
+
+    let mut b = [0.0f64; 7];
+
+    (0..len)
+        .map(|_| {
+            let w = white();
+
+            b[0] = 0.99886 * b[0] + w * 0.0555179;
+            b[1] = 0.99332 * b[1] + w * 0.0750759;
+            b[2] = 0.96900 * b[2] + w * 0.1538520;
+            b[3] = 0.86650 * b[3] + w * 0.3104856;
+            b[4] = 0.55000 * b[4] + w * 0.5329522;
+            b[5] = -0.7616 * b[5] - w * 0.0168980;
+
+            let pink = b[..6].iter().sum::<f64>() + b[6] + w * 0.5362;
+
+            b[6] = w * 0.115926;
+
+            // the filter has a gain of about 10
+            (pink * 0.02) as f32
+        })
+        .collect()
+}
+
+/// a sine that sweeps exponentially from `f0` to `f1`
+fn sweep(len: usize, f0: f64, f1: f64) -> Vec<f32> {
+    let duration = len as f64 / SAMPLE_RATE_HZ as f64;
+    let k = (f1 / f0).ln();
+
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE_HZ as f64;
+
+            let phase = 2.0 * PI * f0 * duration / k * ((t / duration * k).exp() - 1.0);
+
+            (0.5 * phase.sin()) as f32
+        })
+        .collect()
+}
+
+fn blocks(signal: &[f32]) -> impl Iterator<Item = Samples<SAMPLES>> + '_ {
+    signal
+        .chunks_exact(SAMPLES)
+        .map(|x| Samples(x.try_into().unwrap()))
+}
+
+/// run the signal through the fft. one row per frame from every builder, plus the full spectrum of the last frame
+fn fft_outputs(signal: &[f32]) -> [(&'static str, Vec<Vec<f32>>, Tolerance); 4] {
+    let mut fft = TestFFT::new(FlatWeighting);
+
+    let bark = BarkScaleBuilder::<FFT_OUT>::new(SAMPLE_RATE_HZ);
+    let exponential =
+        ExponentialScaleBuilder::<FFT_OUT, EXPONENTIAL_BANDS>::new(20.0, 20_000.0, SAMPLE_RATE_HZ);
+    let shazam = ShazamScaleBuilder::<FFT_OUT>::new(SAMPLE_RATE_HZ);
+
+    let mut bark_rows = vec![];
+    let mut exponential_rows = vec![];
+    let mut shazam_rows = vec![];
+    let mut spectrum = vec![];
+
+    for samples in blocks(signal) {
+        fft.push_samples(&samples);
+
+        let outputs = fft.fft();
+
+        bark_rows.push(bark.loudness(&outputs).0.0.to_vec());
+        exponential_rows.push(exponential.loudness(&outputs).0.0.to_vec());
+        shazam_rows.push(shazam.loudness(&outputs).0.0.to_vec());
+
+        spectrum = outputs.iter_amplitude().map(|x| 20.0 * x.log10()).collect();
+    }
+
+    [
+        ("fft", vec![spectrum], DB),
+        ("bark_scale", bark_rows, DB),
+        ("exponential_scale", exponential_rows, DB),
+        ("shazam_scale", shazam_rows, DB),
+    ]
+}
+
+fn bark_bank_outputs(signal: &[f32]) -> Vec<Vec<f32>> {
+    let mut bank = BarkBank::new(SAMPLE_RATE_HZ / SAMPLES as f32, SAMPLE_RATE_HZ);
+
+    blocks(signal)
+        .map(|samples| bank.push_samples(&samples.0).0.to_vec())
+        .collect()
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.csv"))
+}
+
+fn write_golden(name: &str, rows: &[Vec<f32>]) {
+    let mut out = String::from(
+        "# generated by tests/golden.rs. regenerate with UPDATE_GOLDEN=1 cargo test --test golden\n",
+    );
+
+    for row in rows {
+        let row: Vec<String> = row.iter().map(|x| x.to_string()).collect();
+
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    let path = golden_path(name);
+
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, out).unwrap();
+}
+
+fn read_golden(name: &str) -> Vec<Vec<f32>> {
+    let path = golden_path(name);
+
+    let contents = fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "{}: {err}. create it with UPDATE_GOLDEN=1 cargo test --test golden",
+            path.display()
+        )
+    });
+
+    contents
+        .lines()
+        .filter(|x| !x.starts_with('#'))
+        .map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+        .collect()
+}
+
+/// returns a description of every value that is out of tolerance
+fn compare(
+    name: &str,
+    expected: &[Vec<f32>],
+    actual: &[Vec<f32>],
+    tolerance: Tolerance,
+) -> Vec<String> {
+    if expected.len() != actual.len() {
+        return vec![format!(
+            "{name}: {} rows != {} rows",
+            actual.len(),
+            expected.len()
+        )];
+    }
+
+    let mut errors = vec![];
+
+    for (row, (e, a)) in expected.iter().zip(actual).enumerate() {
+        if e.len() != a.len() {
+            errors.push(format!(
+                "{name}[{row}]: {} columns != {} columns",
+                a.len(),
+                e.len()
+            ));
+            continue;
+        }
+
+        for (col, (&e, &a)) in e.iter().zip(a).enumerate() {
+            let ok = if e.is_nan() || a.is_nan() {
+                e.is_nan() && a.is_nan()
+            } else {
+                let e = e.max(tolerance.floor);
+                let a = a.max(tolerance.floor);
+
+                e == a || (e - a).abs() <= tolerance.absolute
+            };
+
+            if !ok {
+                errors.push(format!("{name}[{row}][{col}]: {a} != {e}"));
+            }
+        }
+    }
+
+    errors
+}
+
+#[test]
+fn test_golden() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut errors = vec![];
+
+    for (signal_name, signal) in signals() {
+        let mut outputs = fft_outputs(&signal).to_vec();
+        outputs.push((BARK_BANK, bark_bank_outputs(&signal), UNIT));
+
+        for (analyser_name, rows, tolerance) in outputs {
+            let name = format!("{analyser_name}_{signal_name}");
+
+            if update {
+                write_golden(&name, &rows);
+            } else {
+                errors.extend(compare(&name, &read_golden(&name), &rows, tolerance));
+            }
+        }
+    }
+
+    // only show the first few. one bad constant usually breaks everything
+    for error in errors.iter().take(20) {
+        eprintln!("{error}");
+    }
+
+    assert!(
+        errors.is_empty(),
+        "{} values differ from the golden files",
+        errors.len()
+    );
+}
@@ -7,7 +7,7 @@ use core::iter::repeat;
 
 use embassy_executor::Spawner;
 use embassy_futures::join::join;
-use embassy_stm32::adc::{Adc, SampleTime, Sequence, VREF_CALIB_MV, resolution_to_max_count};
+use embassy_stm32::adc::{Adc, SampleTime, Sequence, VREF_CALIB_MV};
 use embassy_stm32::gpio::{Level, Output, Speed};
 use embassy_stm32::peripherals::{
     ADC1, DMA1_CH4, DMA2_CH0, DMA2_CH2, IWDG, PA0, PB5, PB15, SPI1, SPI2,
@@ -20,7 +20,7 @@ use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::channel::{Channel, Receiver, Sender};
 use embassy_time::Timer;
 use itertools::repeat_n;
-use musical_lights_core::audio::FlatWeighting;
+use musical_lights_core::audio::{AdcConverter, DcBlocker, FlatWeighting};
 use musical_lights_core::lights::{DancingLights, Gradient};
 use musical_lights_core::{
     audio::{
@@ -28,7 +28,6 @@ use musical_lights_core::{
         ExponentialScaleBuilder, FFT, Samples,
     },
     logging::{debug, info, trace, warn},
-    remap,
     windows::HanningWindow,
 };
 use smart_leds::colors::{BLACK, BLUE, RED};
@@ -53,11 +52,8 @@ const MATRIX_BUFFER: usize = MATRIX_N * 12;
 // this is printed on the mic board. it should probably be config
 const MIC_DC_OFFSET_MV: u32 = 1250;
 
-const MIC_DC_OFFSET_V: f32 = MIC_DC_OFFSET_MV as f32 / 1000.0;
-const VREF_CALIB_V: f32 = VREF_CALIB_MV as f32 / 1000.0;
-
-// const VREF_NOMINAL: u16 = 3300;
-// const VREFINT_CALIBRATED: u16 = 1230;
+/// the bias printed on the board is never exact and it drifts. this cleans up whatever is left
+const MIC_HIGH_PASS_HZ: f32 = 20.0;
 
 #[embassy_executor::task]
 pub async fn blink_task(mut led: Output<'static>) {
@@ -91,12 +87,13 @@ async fn mic_task(
     let adc_resolution = embassy_stm32::adc::Resolution::BITS12;
 
     adc.set_resolution(adc_resolution);
-    let full_range = resolution_to_max_count(adc_resolution) as f32;
 
+    // TODO: read vrefint and use `AdcConverter::calibrate_vrefint`. it isn't on the ring_buffered_adc. hmm.
     // let mut vrefint = adc.enable_vrefint();
-    // // TODO: i think we need to read this in the loop. but it isn't on the ring_buffered_adc. hmm.
-    // // TODO: async read?
     // let vref = adc.blocking_read(&mut vrefint);
+    let adc_converter = AdcConverter::new(12, VREF_CALIB_MV as f32, MIC_DC_OFFSET_MV as f32);
+
+    let mut dc_blocker = DcBlocker::new(MIC_HIGH_PASS_HZ, SAMPLE_RATE);
 
     let mut adc_dma_buf = [0u16; MIC_SAMPLES * 2];
     let mut ring_buffered_adc = adc.into_ring_buffered(mic_dma, &mut adc_dma_buf);
@@ -112,35 +109,11 @@ async fn mic_task(
             Ok(_) => {
                 debug!("adc1 raw: {}", measurements);
 
-                for (i, measurement) in measurements.iter().enumerate() {
-                    // TODO: figure out what below is right and what is wrong
-                    // TODO: scale 0-4095 to millivolts. use vref to get rid of any bias. subcract dc offset. convert to float for the fft. fft wants 0 centered data
-
-                    // // max voltage should be ~2.  to make fft happy, subtract 1 to 0 center.
-                    // // TODO: this would probably be better with less floats, but maybe its fine
-                    // // TODO: use VREF_DEFAULT_MV?
-                    let sample_mv =
-                        *measurement as f32 / full_range * VREF_CALIB_V - MIC_DC_OFFSET_V - 1000.0;
-
-                    // TODO: there is no subtracting the dc offset in this...
-                    // let sample_mv = (sample * vrefint.value() as u32 / vref as u32) * 3300 / 4095;
-
-                    // let sample_mv = (sample as u32 * VREFINT_VALUE / vref as u32) * VREF_CALIB_MV / full_range;
-                    // info!("mic: {}", sample_mv);
-
-                    // let sample_mv = sample_mv.saturating_sub(MIC_DC_OFFSET);
-                    // trace!("mic mv: {}", sample_mv);
-
-                    let sample_scaled = remap(sample_mv as f32, 0.0, full_range, -1.0, 1.0);
-                    // let sample_scaled = sample_mv as f32 / MIC_VPP;
-
-                    // TODO: i think vref should be included here, but
-                    // let sample = sample as f32 / range * 3.3;
+                adc_converter.convert(&measurements, &mut modified);
 
-                    modified[i] = sample_scaled;
-                }
+                dc_blocker.process(&mut modified);
 
-                debug!("adc1 scaled mv: {}", modified);
+                debug!("adc1 scaled: {}", modified);
 
                 tx.send(Samples(modified)).await;
             }
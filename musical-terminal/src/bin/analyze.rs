@@ -0,0 +1,212 @@
+//! Run a WAV file through the analysers and dump every frame to stdout.
+//!
+//! This is for reproducing "it looked wrong at the party" bugs on a computer without a mic or lights.
+//!
+//!     cargo run --release --bin analyze -- party.wav bark csv > party.csv
+//!
+//! Try different [`BarkBankConfig`]s with `--bark-config=venue.json`. Any fields that are left out use the defaults.
+//!
+//! Logs go to stderr, so they don't end up in the output.
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write, stdout};
+
+use anyhow::{Context, bail};
+use musical_lights_core::audio::{
+    AWeighting, AggregatedBinsBuilder, BarkBank, BarkBankConfig, BarkScaleBuilder, BufferedFFT,
+    Samples,
+};
+use musical_lights_core::logging::info;
+use musical_lights_core::windows::HanningWindow;
+use musical_terminal::{Pace, WavSource};
+
+const USAGE: &str =
+    "usage: analyze <file.wav> [bark|fft] [csv|json] [--realtime] [--bark-config=<file.json>]";
+
+/// same as the mic in the fft bin
+const MIC_SAMPLES: usize = 512;
+
+const FFT_INPUTS: usize = 4096;
+
+const FFT_OUTPUTS: usize = FFT_INPUTS / 2;
+
+type MyBufferedFFT = BufferedFFT<
+    MIC_SAMPLES,
+    FFT_INPUTS,
+    FFT_OUTPUTS,
+    HanningWindow<FFT_INPUTS>,
+    AWeighting<FFT_OUTPUTS>,
+>;
+
+#[derive(Clone, Copy, Debug)]
+enum Analyser {
+    /// [`BarkBank`]. time domain filters
+    Bark,
+    /// [`BufferedFFT`] into a [`BarkScaleBuilder`]
+    Fft,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    /// one json object per line
+    Json,
+}
+
+struct Args {
+    path: String,
+    analyser: Analyser,
+    format: Format,
+    pace: Pace,
+    bark_config: BarkBankConfig,
+}
+
+impl Args {
+    fn parse() -> anyhow::Result<Self> {
+        let mut path = None;
+        let mut analyser = Analyser::Bark;
+        let mut format = Format::Csv;
+        let mut pace = Pace::AsFastAsPossible;
+        let mut bark_config = BarkBankConfig::default();
+
+        for arg in env::args().skip(1) {
+            match arg.as_str() {
+                "bark" => analyser = Analyser::Bark,
+                "fft" => analyser = Analyser::Fft,
+                "csv" => format = Format::Csv,
+                "json" => format = Format::Json,
+                "--realtime" => pace = Pace::RealTime,
+                "-h" | "--help" => bail!(USAGE),
+                x if x.starts_with("--bark-config=") => {
+                    let config_path = &x["--bark-config=".len()..];
+
+                    let file = File::open(config_path)
+                        .with_context(|| format!("opening {config_path}"))?;
+
+                    bark_config = serde_json::from_reader(file)
+                        .with_context(|| format!("parsing {config_path}"))?;
+
+                    info!("{bark_config:?}");
+                }
+                x if x.starts_with('-') || path.is_some() => {
+                    bail!("unexpected argument: {x}\n{USAGE}")
+                }
+                x => path = Some(x.to_string()),
+            }
+        }
+
+        let path = path.context(USAGE)?;
+
+        Ok(Self {
+            path,
+            analyser,
+            format,
+            pace,
+            bark_config,
+        })
+    }
+}
+
+/// write every frame from `analyse` to `out`
+fn dump<const N: usize>(
+    source: WavSource<MIC_SAMPLES>,
+    format: Format,
+    out: &mut impl Write,
+    mut analyse: impl FnMut(&Samples<MIC_SAMPLES>) -> [f32; N],
+) -> anyhow::Result<()> {
+    let frame_s = source.block_duration().as_secs_f32();
+
+    if let Format::Csv = format {
+        write!(out, "frame,time_s")?;
+        for i in 0..N {
+            write!(out, ",band_{i}")?;
+        }
+        writeln!(out)?;
+    }
+
+    let mut frames = 0;
+
+    for (frame, samples) in source.enumerate() {
+        let bands = analyse(&samples?);
+
+        // the time at the end of the frame. that's when the lights would change
+        let time_s = (frame + 1) as f32 * frame_s;
+
+        match format {
+            Format::Csv => {
+                write!(out, "{frame},{time_s}")?;
+                for x in bands {
+                    write!(out, ",{x}")?;
+                }
+                writeln!(out)?;
+            }
+            Format::Json => {
+                // TODO: non-finite values become null. is that what we want?
+                serde_json::to_writer(
+                    &mut *out,
+                    &serde_json::json!({
+                        "frame": frame,
+                        "time_s": time_s,
+                        "bands": bands.as_slice(),
+                    }),
+                )?;
+                writeln!(out)?;
+            }
+        }
+
+        frames += 1;
+    }
+
+    out.flush()?;
+
+    info!("analyzed {frames} frames");
+
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    unsafe {
+        env::set_var(
+            "RUST_LOG",
+            env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+        );
+    }
+
+    env_logger::builder()
+        .format_timestamp_nanos()
+        .parse_default_env()
+        .init();
+
+    let args = Args::parse()?;
+
+    let source = WavSource::<MIC_SAMPLES>::try_new(&args.path, args.pace)?;
+
+    let sample_rate = source.sample_rate.0 as f32;
+
+    let fps = sample_rate / MIC_SAMPLES as f32;
+
+    info!("{:?} at {fps:.1} fps", args.analyser);
+
+    let mut out = BufWriter::new(stdout().lock());
+
+    match args.analyser {
+        Analyser::Bark => {
+            let mut bank = BarkBank::new(fps, sample_rate).with_config(args.bark_config);
+
+            dump(source, args.format, &mut out, |samples| {
+                bank.push_samples(&samples.0).0
+            })
+        }
+        Analyser::Fft => {
+            let mut fft = MyBufferedFFT::new(AWeighting::new(sample_rate));
+
+            let scale_builder = BarkScaleBuilder::new(sample_rate);
+
+            dump(source, args.format, &mut out, |samples| {
+                fft.push_samples(samples);
+
+                scale_builder.loudness(&fft.fft()).0.0
+            })
+        }
+    }
+}
@@ -3,7 +3,10 @@
 
 use embassy_executor::Spawner;
 use musical_lights_core::{
-    audio::{AWeighting, AggregatedBins, AggregatedBinsBuilder, BarkScaleBuilder, BufferedFFT},
+    audio::{
+        AWeighting, AggregatedBins, AggregatedBinsBuilder, BarkScaleBuilder, BeatDetector,
+        BufferedFFT,
+    },
     lights::{DancingLights, Gradient},
     logging::{debug, info},
     windows::HanningWindow,
@@ -13,15 +16,18 @@ use std::env;
 
 const MIC_SAMPLES: usize = 512;
 
-/// TODO: 2048 or 4096? we need a macro for multiple sizes
+/// any microfft size from 64 to 4096 works here. TODO: 2048 or 4096?
 const FFT_INPUTS: usize = 4096;
 
-/// equal temperment == 120?
+/// equal temperment == 120? TODO: or use ChromaBuilder to fold everything into 12 pitch classes
 const NUM_BANDS: usize = 24;
 // const NUM_BANDS: usize = 10;
 
 const FFT_OUTPUTS: usize = FFT_INPUTS / 2;
 
+/// ~4 seconds of onset history for the tempo
+const BEAT_HISTORY: usize = 384;
+
 type MyBufferedFFT = BufferedFFT<
     MIC_SAMPLES,
     FFT_INPUTS,
@@ -41,7 +47,10 @@ async fn audio_task(
     mut fft: MyBufferedFFT,
     scale_builder: ScaleBuilder,
     tx_loudness: flume::Sender<AggregatedBins<NUM_BANDS>>,
+    fps: f32,
 ) {
+    let mut beat_detector = BeatDetector::<NUM_BANDS, BEAT_HISTORY>::new(fps);
+
     while let Ok(samples) = mic_stream.stream.recv_async().await {
         fft.push_samples(&samples);
 
@@ -52,7 +61,11 @@ async fn audio_task(
         // TODO: decibels here?
 
         // TODO: shazam
-        // TODO: beat detection
+        let beat = beat_detector.push_bands(&loudness.0);
+        if beat.beat {
+            debug!("beat! {:.1} bpm ({:.2})", beat.bpm, beat.confidence);
+        }
+
         // TODO: peak detection
 
         tx_loudness.send_async(loudness.0).await.unwrap();
@@ -103,6 +116,9 @@ async fn main(spawner: Spawner) {
 
     let sample_rate = mic_stream.sample_rate.0 as f32;
 
+    // an fft runs every time the mic gives us MIC_SAMPLES. the device might not give us the rate we asked for
+    let fps = sample_rate / MIC_SAMPLES as f32;
+
     let weighting = AWeighting::new(sample_rate);
     // let weighting = FlatWeighting {};
 
@@ -116,7 +132,7 @@ async fn main(spawner: Spawner) {
     // let scale_builder = ExponentialScaleBuilder::new(80.0, 20_000.0, sample_rate);
     let scale_builder = BarkScaleBuilder::new(sample_rate);
 
-    spawner.must_spawn(audio_task(mic_stream, fft, scale_builder, loudness_tx));
+    spawner.must_spawn(audio_task(mic_stream, fft, scale_builder, loudness_tx, fps));
     spawner.must_spawn(lights_task(loudness_rx));
 
     debug!("all tasks spawned");
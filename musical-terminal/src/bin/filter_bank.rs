@@ -5,10 +5,10 @@
 use std::env;
 
 use embassy_executor::Spawner;
-use musical_lights_core::audio::{AggregatedBins, BarkBank};
+use musical_lights_core::audio::{AggregatedBins, BarkBank, BeatDetector, LoudnessMeter};
 use musical_lights_core::fps::FpsTracker;
 use musical_lights_core::lights::{Bands, Gradient};
-use musical_lights_core::logging::{debug, info};
+use musical_lights_core::logging::{debug, info, trace};
 use musical_lights_core::remap;
 use musical_terminal::MicrophoneStream;
 
@@ -23,14 +23,35 @@ const MIC_SAMPLE_SIZE: usize = (MIC_SAMPLE_RATE as f32 / FPS_TARGET) as usize;
 
 const DEBUGGING_Y: usize = 255;
 
+/// ~4.6 seconds of onset history for the tempo
+const BEAT_HISTORY: usize = 256;
+
 #[embassy_executor::task]
 async fn audio_task(
     mic_stream: MicrophoneStream<MIC_SAMPLE_SIZE>,
     mut bank: BarkBank,
+    mut beat_detector: BeatDetector<NUM_BANDS, BEAT_HISTORY>,
+    mut loudness_meter: LoudnessMeter,
     tx_loudness: flume::Sender<AggregatedBins<NUM_BANDS>>,
 ) {
     while let Ok(samples) = mic_stream.stream.recv_async().await {
         let x = bank.push_samples(&samples.0);
+
+        // TODO: use this for the global brightness instead of the per-band envelopes
+        loudness_meter.push_samples(&samples.0);
+        trace!(
+            "momentary: {:?} LUFS. integrated: {:?} LUFS. brightness: {:.2}",
+            loudness_meter.momentary(),
+            loudness_meter.integrated(),
+            loudness_meter.brightness(20.0)
+        );
+
+        // TODO: send the beat to the lights task too
+        let beat = beat_detector.push_bands(&x);
+        if beat.beat {
+            debug!("beat! {:.1} bpm ({:.2})", beat.bpm, beat.confidence);
+        }
+
         tx_loudness.send_async(x).await.unwrap();
     }
 }
@@ -75,13 +96,26 @@ async fn main(spawner: Spawner) {
 
     let sample_rate = mic_stream.sample_rate.0 as f32;
 
-    let filter_bank = BarkBank::new(FPS_TARGET, sample_rate);
+    // the device might not give us the rate we asked for. every frame is MIC_SAMPLE_SIZE samples no matter what
+    let fps = sample_rate / MIC_SAMPLE_SIZE as f32;
+
+    let filter_bank = BarkBank::new(fps, sample_rate);
+
+    let beat_detector = BeatDetector::new(fps);
+
+    let loudness_meter = LoudnessMeter::new(sample_rate);
 
     let gradient: Gradient<400> = Gradient::new_greg_caitlin_wedding();
 
     // TODO: how can we print this
 
-    spawner.must_spawn(audio_task(mic_stream, filter_bank, loudness_tx));
+    spawner.must_spawn(audio_task(
+        mic_stream,
+        filter_bank,
+        beat_detector,
+        loudness_meter,
+        loudness_tx,
+    ));
     spawner.must_spawn(lights_task(loudness_rx));
 
     debug!("all tasks spawned");
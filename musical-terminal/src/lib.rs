@@ -1,3 +1,5 @@
+mod wav;
+
 use cpal::{
     SampleRate, Stream, StreamConfig,
     traits::{DeviceTrait, HostTrait, StreamTrait},
@@ -5,6 +7,8 @@ use cpal::{
 use musical_lights_core::audio::Samples;
 use musical_lights_core::logging::{error, info, trace};
 
+pub use wav::{Pace, WavSource};
+
 /// TODO: i think this should be a trait. then we could have the i2s mic on embedded and the cpal input use mo
 pub struct MicrophoneStream<const SAMPLES: usize> {
     pub sample_rate: SampleRate,
@@ -0,0 +1,297 @@
+//! Play a recording through the same code as the microphone.
+//!
+//! Useful for "it looked wrong at the party" bugs. Record the party, then run the recording on any computer.
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use cpal::SampleRate;
+use hound::{SampleFormat, WavReader};
+use musical_lights_core::audio::{Samples, StereoSamples};
+use musical_lights_core::logging::info;
+
+/// how fast to hand out the samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pace {
+    /// sleep between blocks like a microphone would. use this when watching the lights
+    RealTime,
+    /// no sleeping. use this for dumping data
+    AsFastAsPossible,
+}
+
+/// Reads a 16 or 24-bit PCM (or 32-bit float) WAV file into blocks of mono samples.
+///
+/// Stereo files are mixed down to mono by [`WavSource::next_samples`]. Use [`WavSource::next_stereo_samples`] to keep the
+/// channels seperate.
+/// TODO: the last partial block is dropped. zero pad it instead?
+pub struct WavSource<const SAMPLES: usize, R: Read = BufReader<File>> {
+    pub sample_rate: SampleRate,
+    channels: usize,
+    /// multiply integer samples by this to get -1.0..=1.0
+    scale: f32,
+    format: SampleFormat,
+    reader: WavReader<R>,
+    pace: Pace,
+    started: Option<Instant>,
+    blocks_sent: u32,
+}
+
+impl<const SAMPLES: usize> WavSource<SAMPLES> {
+    pub fn try_new(path: impl AsRef<Path>, pace: Pace) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        let reader = WavReader::open(path)?;
+
+        info!("reading {}", path.display());
+
+        Self::try_from_reader(reader, pace)
+    }
+}
+
+impl<const SAMPLES: usize, R: Read> WavSource<SAMPLES, R> {
+    pub fn try_from_reader(reader: WavReader<R>, pace: Pace) -> anyhow::Result<Self> {
+        let spec = reader.spec();
+
+        info!("wav spec = {:?}", spec);
+        info!("wav duration = {} samples", reader.duration());
+
+        let scale = match (spec.sample_format, spec.bits_per_sample) {
+            (SampleFormat::Int, 16 | 24) => 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32,
+            (SampleFormat::Float, 32) => 1.0,
+            (format, bits) => bail!("unsupported wav format: {bits}-bit {format:?}"),
+        };
+
+        let channels = spec.channels as usize;
+
+        if channels == 0 || channels > 2 {
+            bail!("unsupported number of channels: {channels}");
+        }
+
+        Ok(Self {
+            sample_rate: SampleRate(spec.sample_rate),
+            channels,
+            scale,
+            format: spec.sample_format,
+            reader,
+            pace,
+            started: None,
+            blocks_sent: 0,
+        })
+    }
+
+    /// how long one block of samples lasts
+    pub fn block_duration(&self) -> Duration {
+        Duration::from_secs_f64(SAMPLES as f64 / self.sample_rate.0 as f64)
+    }
+
+    /// read the next interleaved sample. None at the end of the file
+    fn next_sample(&mut self) -> Option<anyhow::Result<f32>> {
+        let x = match self.format {
+            SampleFormat::Int => self
+                .reader
+                .samples::<i32>()
+                .next()?
+                .map(|x| x as f32 * self.scale),
+            SampleFormat::Float => self.reader.samples::<f32>().next()?,
+        };
+
+        Some(x.map_err(Into::into))
+    }
+
+    /// the next block of samples. None at the end of the file
+    pub fn next_samples(&mut self) -> Option<anyhow::Result<Samples<SAMPLES>>> {
+        let mut samples = [0.0; SAMPLES];
+
+        for x in samples.iter_mut() {
+            let mut sum = 0.0;
+
+            for _ in 0..self.channels {
+                match self.next_sample()? {
+                    Ok(y) => sum += y,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            *x = sum / self.channels as f32;
+        }
+
+        self.wait_for_block();
+
+        Some(Ok(Samples(samples)))
+    }
+
+    /// the next block of left and right samples. mono files put the same samples on both sides. None at the end of the file
+    pub fn next_stereo_samples(&mut self) -> Option<anyhow::Result<StereoSamples<SAMPLES>>> {
+        let mut samples = StereoSamples::new(Samples([0.0; SAMPLES]), Samples([0.0; SAMPLES]));
+
+        for (l, r) in samples.left.0.iter_mut().zip(samples.right.0.iter_mut()) {
+            match self.next_sample()? {
+                Ok(x) => *l = x,
+                Err(err) => return Some(Err(err)),
+            }
+
+            *r = if self.channels == 2 {
+                match self.next_sample()? {
+                    Ok(x) => x,
+                    Err(err) => return Some(Err(err)),
+                }
+            } else {
+                *l
+            };
+        }
+
+        self.wait_for_block();
+
+        Some(Ok(samples))
+    }
+
+    fn wait_for_block(&mut self) {
+        if self.pace == Pace::RealTime {
+            let started = *self.started.get_or_insert_with(Instant::now);
+
+            // sleep until the time that the mic would have finished recording this block
+            let due = started + self.block_duration() * (self.blocks_sent + 1);
+
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+
+        self.blocks_sent += 1;
+    }
+}
+
+impl<const SAMPLES: usize, R: Read> Iterator for WavSource<SAMPLES, R> {
+    type Item = anyhow::Result<Samples<SAMPLES>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_samples()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use hound::{WavSpec, WavWriter};
+
+    use super::*;
+
+    fn wav<F: FnOnce(&mut WavWriter<&mut Cursor<Vec<u8>>>)>(
+        spec: WavSpec,
+        write: F,
+    ) -> Cursor<Vec<u8>> {
+        let mut buf = Cursor::new(Vec::new());
+
+        let mut writer = WavWriter::new(&mut buf, spec).unwrap();
+        write(&mut writer);
+        writer.finalize().unwrap();
+
+        buf.set_position(0);
+        buf
+    }
+
+    #[test]
+    fn test_16_bit_mono() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        let buf = wav(spec, |w| {
+            for x in [0i16, 16384, -16384, i16::MIN, 1, 2, 3] {
+                w.write_sample(x).unwrap();
+            }
+        });
+
+        let mut source = WavSource::<4, _>::try_from_reader(
+            WavReader::new(buf).unwrap(),
+            Pace::AsFastAsPossible,
+        )
+        .unwrap();
+
+        assert_eq!(source.sample_rate, SampleRate(8000));
+
+        let samples = source.next().unwrap().unwrap();
+        assert_eq!(samples.0, [0.0, 0.5, -0.5, -1.0]);
+
+        // only 3 samples are left. that isn't a full block
+        assert!(source.next().is_none());
+    }
+
+    #[test]
+    fn test_24_bit_stereo() {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+
+        let buf = wav(spec, |w| {
+            for (l, r) in [(1 << 22, 0), (-(1 << 23), -(1 << 23)), (1 << 21, 1 << 22)] {
+                w.write_sample(l).unwrap();
+                w.write_sample(r).unwrap();
+            }
+        });
+
+        let mut source = WavSource::<3, _>::try_from_reader(
+            WavReader::new(buf).unwrap(),
+            Pace::AsFastAsPossible,
+        )
+        .unwrap();
+
+        let samples = source.next().unwrap().unwrap();
+        assert_eq!(samples.0, [0.25, -1.0, 0.375]);
+    }
+
+    #[test]
+    fn test_24_bit_stereo_channels() {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+
+        let buf = wav(spec, |w| {
+            for (l, r) in [(1 << 22, 0), (-(1 << 23), -(1 << 23)), (1 << 21, 1 << 22)] {
+                w.write_sample(l).unwrap();
+                w.write_sample(r).unwrap();
+            }
+        });
+
+        let mut source = WavSource::<3, _>::try_from_reader(
+            WavReader::new(buf).unwrap(),
+            Pace::AsFastAsPossible,
+        )
+        .unwrap();
+
+        let samples = source.next_stereo_samples().unwrap().unwrap();
+        assert_eq!(samples.left.0, [0.5, -1.0, 0.25]);
+        assert_eq!(samples.right.0, [0.0, -1.0, 0.5]);
+
+        assert!(source.next_stereo_samples().is_none());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 8,
+            sample_format: SampleFormat::Int,
+        };
+
+        let buf = wav(spec, |w| w.write_sample(0i8).unwrap());
+
+        assert!(
+            WavSource::<4, _>::try_from_reader(WavReader::new(buf).unwrap(), Pace::RealTime)
+                .is_err()
+        );
+    }
+}